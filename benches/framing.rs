@@ -0,0 +1,37 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use gps::msg::GpsMsg;
+
+/// A buffer without any message prefix, the worst case for resyncing.
+fn noise(len: usize) -> Vec<u8> {
+    // A simple xorshift so the benchmark doesn't need a random number crate.
+    let mut state = 0x2545_f491u32;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .map(|x| match x {
+            0xb5 | 0xd3 | b'$' | b'%' => 0,
+            x => x,
+        })
+        .collect()
+}
+
+fn find_prefix(c: &mut Criterion) {
+    let buffer = noise(4096);
+    c.bench_function("find_prefix 4KiB noise", |b| {
+        b.iter(|| GpsMsg::find_prefix(black_box(&buffer)))
+    });
+
+    let mut buffer = noise(4096);
+    let len = buffer.len();
+    buffer[len - 8..].copy_from_slice(&[0xb5, 0x62, 0x05, 0x01, 0x02, 0x00, 0x06, 0x8a]);
+    c.bench_function("find_prefix 4KiB noise then ubx", |b| {
+        b.iter(|| GpsMsg::find_prefix(black_box(&buffer)))
+    });
+}
+
+criterion_group!(benches, find_prefix);
+criterion_main!(benches);
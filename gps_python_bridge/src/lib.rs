@@ -1,62 +1,748 @@
-use std::{io, net::SocketAddr, str::FromStr};
+use std::{
+    future::Future,
+    io,
+    net::SocketAddr,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
 
+use enumflags2::BitFlags;
 use futures::{
-    channel::mpsc::{self, Receiver, Sender},
+    channel::{
+        mpsc::{self, Receiver, Sender},
+        oneshot,
+    },
     future::{self, Either},
     SinkExt, StreamExt,
 };
-use gps::{connection::Connection, msg::GpsMsg, parse::ParseData};
-use pyo3::{exceptions::PyException, prelude::*};
-use tokio::net::TcpStream;
+use gps::{
+    connection::Connection,
+    device,
+    msg::{
+        ubx::{
+            ack::MessageId,
+            cfg::{BitLayer, Cfg, Layer, ValGet, ValGetRequest, ValSet, Value, ValueKey},
+            nav::{CarrierPhaseSol, FixType, Nav, Pvt},
+        },
+        FlatGpsMsg, GpsMsg, Ubx,
+    },
+    parse::{ParseData, ParseIterator},
+};
+use log::{error, info};
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyTimeoutError, PyValueError},
+    prelude::*,
+    types::{PyBytes, PyDict},
+    PyIterProtocol,
+};
+use serde_json::json;
+use tokio::{io::AsyncWriteExt, net::TcpStream, runtime::Runtime};
+
+// Raised when sending without blocking while the send queue is full.
+create_exception!(gps_socket, QueueFull, PyException);
+
+/// How long closing a connection waits for the socket thread to stop.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often a blocking receive checks for signals like a keyboard interrupt.
+const SIGNAL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long to wait for the device to respond to a configuration message.
+const CONFIG_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait before the first attempt to reconnect, doubled after every failed attempt.
+const RECONNECT_MIN_WAIT: Duration = Duration::from_millis(500);
+
+/// The longest time to wait between attempts to reconnect.
+const RECONNECT_MAX_WAIT: Duration = Duration::from_secs(8);
+
+/// An item from the socket thread, `None` if the thread quit.
+type Received = Option<Result<Item, io::Error>>;
+
+/// A change in the connection to the server, passed to python as `{"event": "connected"}` or
+/// `{"event": "disconnected"}`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ConnectionEvent {
+    Connected,
+    Disconnected,
+}
+
+/// A message or event queued for python.
+enum Item {
+    Msg(GpsMsg),
+    Event(ConnectionEvent),
+}
+
+/// Why the loop talking to the server stopped.
+enum Stopped {
+    /// The python side is gone.
+    Closed,
+    /// The connection to the server was closed or failed.
+    Lost(Option<io::Error>),
+}
+
+/// What to do with messages from the server when the queue to python is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Overflow {
+    /// Drop the message.
+    Drop,
+    /// Stop reading from the server until there is room in the queue.
+    Block,
+}
+
+/// The minimum fix waited for by `wait_for_fix`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MinFix {
+    Fix2D,
+    Fix3D,
+    /// A 3D fix with a float carrier phase solution.
+    Float,
+    /// A 3D fix with a fixed carrier phase solution.
+    Fixed,
+}
+
+impl MinFix {
+    fn is_met(self, pvt: &Pvt) -> bool {
+        if !pvt.flags.gnss_fix_ok {
+            return false;
+        }
+        let fix = match pvt.fix_type {
+            FixType::Fix2D => MinFix::Fix2D,
+            FixType::Fix3D | FixType::Gnss => match pvt.flags.car_sol {
+                CarrierPhaseSol::NoSolution => MinFix::Fix3D,
+                CarrierPhaseSol::Float => MinFix::Float,
+                CarrierPhaseSol::Fixed => MinFix::Fixed,
+            },
+            _ => return false,
+        };
+        fix as u8 >= self as u8
+    }
+}
+
+impl FromStr for MinFix {
+    type Err = PyErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "2d" => Ok(MinFix::Fix2D),
+            "3d" => Ok(MinFix::Fix3D),
+            "float" => Ok(MinFix::Float),
+            "fixed" => Ok(MinFix::Fixed),
+            x => Err(PyValueError::new_err(format!(
+                "invalid fix type `{x}`, expected `2d`, `3d`, `float` or `fixed`"
+            ))),
+        }
+    }
+}
+
+fn timeout_from_secs(secs: f64) -> PyResult<Duration> {
+    Duration::try_from_secs_f64(secs)
+        .map_err(|e| PyValueError::new_err(format!("invalid timeout: {e}")))
+}
+
+fn parse_layers(v: &str) -> PyResult<BitFlags<BitLayer>> {
+    match v {
+        "ram" => Ok(BitLayer::Ram.into()),
+        "bbr" => Ok(BitLayer::Bbr.into()),
+        "flash" => Ok(BitLayer::Flash.into()),
+        "all" => Ok(BitFlags::all()),
+        x => Err(PyValueError::new_err(format!(
+            "invalid layer `{x}`, expected `ram`, `bbr`, `flash` or `all`"
+        ))),
+    }
+}
+
+fn parse_key(key: &str) -> PyResult<ValueKey> {
+    serde_json::from_value(json!(key))
+        .map_err(|_| PyValueError::new_err(format!("unknown config key `{key}`")))
+}
+
+fn to_python(py: Python<'_>, msg: &GpsMsg, flat: bool) -> PyResult<PyObject> {
+    let res = if flat {
+        pythonize::pythonize(py, &FlatGpsMsg(msg))
+    } else {
+        pythonize::pythonize(py, msg)
+    };
+    res.map_err(|x| PyException::new_err(format!("serialization error {x}")))
+}
+
+fn event_dict(py: Python<'_>, event: ConnectionEvent) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    let name = match event {
+        ConnectionEvent::Connected => "connected",
+        ConnectionEvent::Disconnected => "disconnected",
+    };
+    dict.set_item("event", name)?;
+    Ok(dict.into())
+}
+
+/// The position from a PVT message in degrees and meters.
+fn position_dict(py: Python<'_>, pvt: &Pvt) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("itow", pvt.i_tow)?;
+    dict.set_item("lat", pvt.lat_deg())?;
+    dict.set_item("lon", pvt.lon_deg())?;
+    dict.set_item("height", pvt.height_m())?;
+    dict.set_item("h_msl", pvt.h_msl_m())?;
+    dict.set_item("h_acc", pvt.h_acc_m())?;
+    dict.set_item("v_acc", pvt.v_acc_m())?;
+    dict.set_item("numsv", pvt.numsv)?;
+    dict.set_item("fix", format!("{:?}", pvt.fix_type))?;
+    dict.set_item("carrier", format!("{:?}", pvt.flags.car_sol))?;
+    Ok(dict.into())
+}
+
+impl FromStr for Overflow {
+    type Err = PyErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop" => Ok(Overflow::Drop),
+            "block" => Ok(Overflow::Block),
+            x => Err(PyValueError::new_err(format!(
+                "invalid overflow policy `{x}`, expected `drop` or `block`"
+            ))),
+        }
+    }
+}
+
+/// The queue of messages from a socket thread to python.
+struct Queue {
+    send: Sender<Result<Item, io::Error>>,
+    overflow: Overflow,
+    dropped: Arc<AtomicU64>,
+    /// The last PVT message, kept even if the message itself is dropped.
+    pvt: Arc<Mutex<Option<Pvt>>>,
+    /// An error writing a message from python, raised on the next call from python.
+    write_error: Arc<Mutex<Option<io::Error>>>,
+    /// Set while the connection to the server is lost and messages from python are refused.
+    disconnected: Arc<AtomicBool>,
+}
+
+impl Queue {
+    /// Queue a message, returns false if the python side is gone.
+    async fn push(&mut self, msg: GpsMsg) -> bool {
+        if let GpsMsg::Ubx(Ubx::Nav(Nav::Pvt(ref x))) = msg {
+            *self.pvt.lock().unwrap() = Some(x.clone());
+        }
+        match self.overflow {
+            Overflow::Drop => {
+                if let Err(e) = self.send.try_send(Ok(Item::Msg(msg))) {
+                    if e.is_disconnected() {
+                        return false;
+                    }
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                true
+            }
+            Overflow::Block => self.send.send(Ok(Item::Msg(msg))).await.is_ok(),
+        }
+    }
+
+    /// Queue an event, events are never dropped. Returns false if the python side is gone.
+    async fn push_event(&mut self, event: ConnectionEvent) -> bool {
+        self.send.send(Ok(Item::Event(event))).await.is_ok()
+    }
+
+    async fn push_error(&mut self, e: io::Error) {
+        self.send.send(Err(e)).await.ok();
+    }
+
+    fn write_failed(&self, e: io::Error) {
+        error!("failed to write message: {e}");
+        *self.write_error.lock().unwrap() = Some(e);
+    }
+}
+
+/// The queues to and from the thread talking to the server or device, shared by the python
+/// classes.
+struct Bridge {
+    send: Sender<GpsMsg>,
+    recv: Receiver<Result<Item, io::Error>>,
+    flat: bool,
+    dropped: Arc<AtomicU64>,
+    pvt: Arc<Mutex<Option<Pvt>>>,
+    write_error: Arc<Mutex<Option<io::Error>>>,
+    disconnected: Arc<AtomicBool>,
+    /// Used for waiting on the queue with a timeout.
+    runtime: Runtime,
+    /// Dropping the sender stops the socket thread, `None` once the connection is closed.
+    shutdown: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+    /// The last error from the socket thread, reported when the thread has quit.
+    error: Option<String>,
+}
+
+impl Bridge {
+    /// Spawn a thread running the future returned by `run` with the queues to python.
+    fn spawn<F, R>(flat: bool, queue_size: usize, overflow: &str, run: R) -> PyResult<Self>
+    where
+        R: FnOnce(Queue, Receiver<GpsMsg>) -> F + Send + 'static,
+        F: Future<Output = ()>,
+    {
+        let overflow = Overflow::from_str(overflow)?;
+        let dropped = Arc::new(AtomicU64::new(0));
+        let pvt = Arc::new(Mutex::new(None));
+        let write_error = Arc::new(Mutex::new(None));
+        let disconnected = Arc::new(AtomicBool::new(false));
+        let (send_a, recv_a) = mpsc::channel(queue_size);
+        let (send_b, recv_b) = mpsc::channel(queue_size);
+        let (shutdown, shutdown_recv) = oneshot::channel();
+        let queue = Queue {
+            send: send_a,
+            overflow,
+            dropped: dropped.clone(),
+            pvt: pvt.clone(),
+            write_error: write_error.clone(),
+            disconnected: disconnected.clone(),
+        };
+        let thread = std::thread::spawn(move || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(async move {
+                    // Dropping the loop closes the connection.
+                    future::select(Box::pin(run(queue, recv_b)), shutdown_recv).await;
+                });
+        });
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()?;
+
+        Ok(Bridge {
+            send: send_b,
+            recv: recv_a,
+            flat,
+            dropped,
+            pvt,
+            write_error,
+            disconnected,
+            runtime,
+            shutdown: Some(shutdown),
+            thread: Some(thread),
+            error: None,
+        })
+    }
+
+    fn close(&mut self, py: Python<'_>) {
+        if self.shutdown.take().is_none() {
+            return;
+        }
+        self.send.close_channel();
+        self.recv.close();
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+        let deadline = Instant::now() + CLOSE_TIMEOUT;
+        py.allow_threads(|| {
+            while !thread.is_finished() && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        });
+        if thread.is_finished() {
+            thread.join().ok();
+        } else {
+            error!("socket thread did not stop within {CLOSE_TIMEOUT:?}");
+        }
+    }
+
+    fn dropped_count(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn next(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        self.check_open()?;
+        match self.recv.try_next() {
+            Ok(x) => self.convert(py, x).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn recv(&mut self, py: Python<'_>, timeout: Option<f64>) -> PyResult<Option<PyObject>> {
+        self.check_open()?;
+        let timeout = timeout.map(timeout_from_secs).transpose()?;
+        match self.wait(py, timeout)? {
+            Some(x) => self.convert(py, x).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn send(&mut self, py: Python<'_>, object: &PyAny, block: bool) -> PyResult<()> {
+        self.check_open()?;
+        let msg = pythonize::depythonize::<GpsMsg>(object)
+            .map_err(|e| PyException::new_err(format!("serialization error {e}")))?;
+        if block {
+            return self.send_msg(py, msg);
+        }
+        self.check_connected()?;
+        self.send.try_send(msg).map_err(|e| {
+            if e.is_full() {
+                QueueFull::new_err("the send queue is full")
+            } else {
+                PyException::new_err("gps socket disconnected")
+            }
+        })
+    }
+
+    /// Send a message, waiting until there is room in the queue.
+    ///
+    /// The GIL is released while waiting so other python threads can continue.
+    fn send_msg(&mut self, py: Python<'_>, msg: GpsMsg) -> PyResult<()> {
+        self.check_connected()?;
+        let disconnected = || PyException::new_err("gps socket disconnected");
+        loop {
+            let send = &mut self.send;
+            let runtime = &self.runtime;
+            let res = py.allow_threads(|| {
+                runtime.block_on(async {
+                    let ready = future::poll_fn(|cx| send.poll_ready(cx));
+                    tokio::time::timeout(SIGNAL_CHECK_INTERVAL, ready).await
+                })
+            });
+            match res {
+                Ok(Ok(())) => break,
+                Ok(Err(_)) => return Err(disconnected()),
+                Err(_) => py.check_signals()?,
+            }
+        }
+        self.send.start_send(msg).map_err(|_| disconnected())
+    }
+
+    fn position(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        match self.pvt.lock().unwrap().as_ref() {
+            Some(x) => position_dict(py, x).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn wait_for_fix(&mut self, py: Python<'_>, min_type: &str, timeout: f64) -> PyResult<PyObject> {
+        self.check_open()?;
+        let min_type = MinFix::from_str(min_type)?;
+        let res = self.wait_for(py, Some(timeout_from_secs(timeout)?), |msg| match msg {
+            GpsMsg::Ubx(Ubx::Nav(Nav::Pvt(x))) if min_type.is_met(x) => Some(x.clone()),
+            _ => None,
+        })?;
+        match res {
+            Some(x) => position_dict(py, &x),
+            None => Err(PyTimeoutError::new_err("no fix within the timeout")),
+        }
+    }
+
+    fn val_set(&mut self, py: Python<'_>, values: &PyDict, layer: &str) -> PyResult<()> {
+        self.check_open()?;
+        let layers = parse_layers(layer)?;
+        let values = values
+            .iter()
+            .map(|(key, value)| {
+                let key: &str = key.extract()?;
+                let value = pythonize::depythonize::<serde_json::Value>(value).map_err(|e| {
+                    PyValueError::new_err(format!("invalid value for `{key}`: {e}"))
+                })?;
+                let kind = parse_key(key)?;
+                serde_json::from_value::<Value>(json!({ "kind": kind, "value": value }))
+                    .map_err(|e| PyValueError::new_err(format!("invalid value for `{key}`: {e}")))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+
+        self.send_msg(
+            py,
+            GpsMsg::Ubx(Ubx::Cfg(Cfg::ValSet(ValSet::new(layers, values)))),
+        )?;
+        let res = self.wait_for(py, Some(CONFIG_TIMEOUT), |msg| match msg {
+            GpsMsg::Ubx(Ubx::Ack(x)) => x.result_for(MessageId::CFG_VALSET),
+            _ => None,
+        })?;
+        match res {
+            Some(true) => Ok(()),
+            Some(false) => Err(PyException::new_err(
+                "device did not acknowledge the values",
+            )),
+            None => Err(PyTimeoutError::new_err(
+                "no acknowledgement from the device within the timeout",
+            )),
+        }
+    }
+
+    fn val_get(&mut self, py: Python<'_>, keys: Vec<&str>) -> PyResult<PyObject> {
+        self.check_open()?;
+        let keys = keys
+            .into_iter()
+            .map(parse_key)
+            .collect::<PyResult<Vec<_>>>()?;
+        self.send_msg(
+            py,
+            GpsMsg::Ubx(Ubx::Cfg(Cfg::ValGet(ValGet::Request(ValGetRequest {
+                layer: Layer::Ram,
+                res1: [0; 2],
+                keys,
+            })))),
+        )?;
+        let res = self.wait_for(py, Some(CONFIG_TIMEOUT), |msg| match msg {
+            GpsMsg::Ubx(Ubx::Cfg(Cfg::ValGet(ValGet::Response(x)))) => Some(Ok(x.keys.clone())),
+            GpsMsg::Ubx(Ubx::Ack(x)) if x.result_for(MessageId::CFG_VALGET) == Some(false) => {
+                Some(Err(()))
+            }
+            _ => None,
+        })?;
+        let values = match res {
+            Some(Ok(x)) => x,
+            Some(Err(())) => {
+                return Err(PyException::new_err(
+                    "device did not acknowledge the request, a key might not be supported",
+                ))
+            }
+            None => {
+                return Err(PyTimeoutError::new_err(
+                    "no response from the device within the timeout",
+                ))
+            }
+        };
+
+        let dict = PyDict::new(py);
+        for v in values {
+            let v = serde_json::to_value(v)
+                .map_err(|e| PyException::new_err(format!("serialization error {e}")))?;
+            let value = pythonize::pythonize(py, &v["value"])
+                .map_err(|e| PyException::new_err(format!("serialization error {e}")))?;
+            dict.set_item(v["kind"].as_str(), value)?;
+        }
+        Ok(dict.into())
+    }
+
+    /// Receive messages until `f` returns a result or the timeout expires, the messages are not
+    /// returned to python.
+    fn wait_for<T>(
+        &mut self,
+        py: Python<'_>,
+        timeout: Option<Duration>,
+        mut f: impl FnMut(&GpsMsg) -> Option<T>,
+    ) -> PyResult<Option<T>> {
+        let deadline = timeout.map(|x| Instant::now() + x);
+        loop {
+            let timeout = deadline.map(|x| x.saturating_duration_since(Instant::now()));
+            let item = match self.wait(py, timeout)? {
+                Some(Some(Ok(Item::Msg(x)))) => x,
+                Some(Some(Ok(Item::Event(_)))) => continue,
+                // Raises the error or reports the thread quitting.
+                Some(x) => return self.convert(py, x).map(|_| None),
+                None => return Ok(None),
+            };
+            if let Some(x) = f(&item) {
+                return Ok(Some(x));
+            }
+        }
+    }
+
+    /// Returns the next message for iteration, `None` stops the iteration.
+    fn iter_next(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        self.check_open()?;
+        match self.wait(py, None)? {
+            Some(None) | None => Ok(None),
+            Some(x) => self.convert(py, x).map(Some),
+        }
+    }
+
+    /// Wait for an item from the socket thread, returns `None` if the timeout expired.
+    ///
+    /// The GIL is released while waiting so other python threads can continue.
+    fn wait(&mut self, py: Python<'_>, timeout: Option<Duration>) -> PyResult<Option<Received>> {
+        let deadline = timeout.map(|x| Instant::now() + x);
+        loop {
+            let wait = deadline.map_or(SIGNAL_CHECK_INTERVAL, |x| {
+                x.saturating_duration_since(Instant::now())
+                    .min(SIGNAL_CHECK_INTERVAL)
+            });
+            let recv = &mut self.recv;
+            let runtime = &self.runtime;
+            let res = py.allow_threads(|| {
+                runtime.block_on(async { tokio::time::timeout(wait, recv.next()).await })
+            });
+            if let Ok(x) = res {
+                return Ok(Some(x));
+            }
+            py.check_signals()?;
+            if deadline.is_some_and(|x| Instant::now() >= x) {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Raises if the connection is closed or writing a previous message failed.
+    fn check_open(&self) -> PyResult<()> {
+        if self.shutdown.is_none() {
+            return Err(PyException::new_err("gps connection is closed"));
+        }
+        if let Some(e) = self.write_error.lock().unwrap().take() {
+            return Err(PyException::new_err(format!("failed to send message: {e}")));
+        }
+        Ok(())
+    }
+
+    /// Raises if the connection to the server is lost and messages are not buffered until it
+    /// is reconnected.
+    fn check_connected(&self) -> PyResult<()> {
+        if self.disconnected.load(Ordering::Relaxed) {
+            return Err(PyException::new_err("gps socket disconnected"));
+        }
+        Ok(())
+    }
+
+    fn convert(&mut self, py: Python<'_>, item: Received) -> PyResult<PyObject> {
+        match item {
+            Some(Ok(Item::Msg(x))) => to_python(py, &x, self.flat),
+            Some(Ok(Item::Event(x))) => event_dict(py, x),
+            Some(Err(e)) => {
+                self.error = Some(e.to_string());
+                Err(PyException::new_err(format!("socket error {e}")))
+            }
+            None => match self.error.as_ref() {
+                Some(e) => Err(PyException::new_err(format!(
+                    "gps socket quit after error: {e}"
+                ))),
+                None => Err(PyException::new_err("gps socket quit")),
+            },
+        }
+    }
+}
 
 #[pyclass]
 pub struct GpsConnection {
-    send: Sender<GpsMsg>,
-    recv: Receiver<Result<GpsMsg, io::Error>>,
+    bridge: Bridge,
 }
 
 impl GpsConnection {
-    async fn socket_loop(
-        address: SocketAddr,
-        mut send: Sender<Result<GpsMsg, io::Error>>,
-        mut recv: Receiver<GpsMsg>,
-    ) {
-        let tcp = match TcpStream::connect(address).await {
+    async fn connect(address: SocketAddr) -> io::Result<Connection> {
+        match TcpStream::connect(address).await {
+            Ok(x) => Ok(Connection::new(x)),
+            Err(e) => Err(io::Error::new(
+                e.kind(),
+                format!("failed to connect to `{address}`: {e}"),
+            )),
+        }
+    }
+
+    async fn socket_loop(address: SocketAddr, mut queue: Queue, mut recv: Receiver<GpsMsg>) {
+        let mut connection = match GpsConnection::connect(address).await {
             Ok(x) => x,
             Err(e) => {
-                send.send(Err(e)).await.ok();
+                queue.push_error(e).await;
                 return;
             }
         };
-        let mut connection = Connection::new(tcp);
+        if let Stopped::Lost(Some(e)) =
+            GpsConnection::run(&mut connection, &mut queue, &mut recv).await
+        {
+            queue.push_error(e).await;
+        }
+    }
 
+    /// Like `socket_loop` but connects again with a backoff when connecting fails or the
+    /// connection is lost, until the python side is gone.
+    ///
+    /// If `buffer` is true messages from python are kept in the send queue while disconnected,
+    /// otherwise they are refused.
+    async fn reconnect_loop(
+        address: SocketAddr,
+        mut queue: Queue,
+        mut recv: Receiver<GpsMsg>,
+        buffer: bool,
+    ) {
+        let mut wait = RECONNECT_MIN_WAIT;
+        let mut attempts = 0u32;
+        loop {
+            match GpsConnection::connect(address).await {
+                Ok(mut connection) => {
+                    if attempts > 0 {
+                        info!("reconnected to `{address}` after {} attempts", attempts + 1);
+                    }
+                    attempts = 0;
+                    wait = RECONNECT_MIN_WAIT;
+                    queue.disconnected.store(false, Ordering::Relaxed);
+                    if !queue.push_event(ConnectionEvent::Connected).await {
+                        return;
+                    }
+                    match GpsConnection::run(&mut connection, &mut queue, &mut recv).await {
+                        Stopped::Closed => return,
+                        Stopped::Lost(Some(e)) => error!("lost connection to `{address}`: {e}"),
+                        Stopped::Lost(None) => info!("connection to `{address}` closed"),
+                    }
+                    queue.disconnected.store(!buffer, Ordering::Relaxed);
+                    if !queue.push_event(ConnectionEvent::Disconnected).await {
+                        return;
+                    }
+                }
+                Err(e) => {
+                    if attempts == 0 {
+                        error!("{e}, retrying");
+                    }
+                    attempts += 1;
+                    queue.disconnected.store(!buffer, Ordering::Relaxed);
+                }
+            }
+
+            let sleep = tokio::time::sleep(wait);
+            if buffer {
+                sleep.await;
+            } else {
+                // Refuse messages queued before python noticed the connection was lost.
+                let refuse = async {
+                    while recv.next().await.is_some() {
+                        queue.write_failed(io::Error::new(
+                            io::ErrorKind::NotConnected,
+                            "not connected to the server",
+                        ));
+                    }
+                };
+                if let Either::Right(_) = future::select(Box::pin(sleep), Box::pin(refuse)).await {
+                    return;
+                }
+            }
+            wait = (wait * 2).min(RECONNECT_MAX_WAIT);
+        }
+    }
+
+    /// Pass messages between the server and python until either side stops.
+    async fn run(
+        connection: &mut Connection,
+        queue: &mut Queue,
+        recv: &mut Receiver<GpsMsg>,
+    ) -> Stopped {
         let mut buffer = Vec::new();
 
         loop {
             match future::select(connection.next(), recv.next()).await {
                 Either::Left((Some(Ok(x)), _)) => {
-                    if let Ok((_, msg)) =
-                        GpsMsg::parse_read(&x).map_err(|e| println!("error parsing message: {e}"))
-                    {
-                        if let Err(e) = send.try_send(Ok(msg)) {
-                            if e.is_disconnected() {
-                                return;
+                    for msg in GpsMsg::parse_frame(&x) {
+                        let msg = match msg {
+                            Ok(x) => x,
+                            Err(e) => {
+                                error!("error parsing message: {e}");
+                                continue;
                             }
+                        };
+                        if !queue.push(msg).await {
+                            return Stopped::Closed;
                         }
                     }
                 }
-                Either::Left((Some(Err(e)), _)) => {
-                    send.send(Err(e)).await.ok();
-                }
-                Either::Left((None, _)) => return,
+                Either::Left((Some(Err(e)), _)) => return Stopped::Lost(Some(e)),
+                Either::Left((None, _)) => return Stopped::Lost(None),
                 Either::Right((Some(x), _)) => {
                     buffer.clear();
                     x.parse_write(&mut buffer).unwrap();
                     if let Err(e) = connection.write_message(&buffer).await {
-                        println!("connection error: {e}");
+                        queue.write_failed(e);
                     }
                 }
-                Either::Right((None, _)) => return,
+                Either::Right((None, _)) => return Stopped::Closed,
             }
         }
     }
@@ -65,54 +751,372 @@ impl GpsConnection {
 #[pymethods]
 impl GpsConnection {
     #[new]
-    #[args(address = "\"0.0.0.0:9165\"")]
-    fn new(address: &str) -> PyResult<Self> {
+    #[args(
+        address = "\"0.0.0.0:9165\"",
+        flat = "false",
+        queue_size = "64",
+        overflow = "\"drop\"",
+        reconnect = "false",
+        buffer_while_disconnected = "true"
+    )]
+    fn new(
+        address: &str,
+        flat: bool,
+        queue_size: usize,
+        overflow: &str,
+        reconnect: bool,
+        buffer_while_disconnected: bool,
+    ) -> PyResult<Self> {
         let addr = SocketAddr::from_str(&address)?;
-        let (send_a, recv_a) = mpsc::channel(64);
-        let (send_b, recv_b) = mpsc::channel(64);
-        std::thread::spawn(move || {
-            tokio::runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()
-                .unwrap()
-                .block_on(GpsConnection::socket_loop(addr, send_a, recv_b));
-        });
+        let bridge = Bridge::spawn(flat, queue_size, overflow, move |queue, recv| {
+            if reconnect {
+                Either::Left(GpsConnection::reconnect_loop(
+                    addr,
+                    queue,
+                    recv,
+                    buffer_while_disconnected,
+                ))
+            } else {
+                Either::Right(GpsConnection::socket_loop(addr, queue, recv))
+            }
+        })?;
+        Ok(GpsConnection { bridge })
+    }
 
-        Ok(GpsConnection {
-            send: send_b,
-            recv: recv_a,
-        })
+    fn __enter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
     }
 
+    fn __exit__(
+        &mut self,
+        py: Python<'_>,
+        _exc_type: &PyAny,
+        _exc_value: &PyAny,
+        _traceback: &PyAny,
+    ) -> bool {
+        self.close(py);
+        false
+    }
+
+    /// Close the connection and stop the socket thread, the connection can't be used afterwards.
+    fn close(&mut self, py: Python<'_>) {
+        self.bridge.close(py)
+    }
+
+    /// The number of messages dropped because the queue was full.
+    #[getter]
+    fn dropped_count(&self) -> u64 {
+        self.bridge.dropped_count()
+    }
+
+    /// Returns the next message if one is queued, otherwise returns `None` immediately.
     fn next(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
-        match self.recv.try_next() {
-            Ok(Some(Ok(x))) => pythonize::pythonize(py, &x)
-                .map(Some)
-                .map_err(|x| PyException::new_err(format!("serialization error {x}"))),
-            Ok(Some(Err(e))) => Err(PyException::new_err(format!("socket error {e}"))),
-            Ok(None) => Err(PyException::new_err("gps socket quit")),
-            Err(_) => return Ok(None),
-        }
+        self.bridge.next(py)
     }
 
-    fn send(&mut self, object: &PyAny) -> PyResult<()> {
-        let msg = pythonize::depythonize::<GpsMsg>(object)
-            .map_err(|e| PyException::new_err(format!("serialization error {e}")))?;
+    /// Wait for the next message, returns `None` if no message arrived before the timeout in
+    /// seconds. Waits indefinitely if no timeout is given.
+    #[args(timeout = "None")]
+    fn recv(&mut self, py: Python<'_>, timeout: Option<f64>) -> PyResult<Option<PyObject>> {
+        self.bridge.recv(py, timeout)
+    }
+
+    /// Send a message, waits for room in the send queue unless `block` is false in which case
+    /// `QueueFull` is raised if the queue is full.
+    #[args(block = "true")]
+    fn send(&mut self, py: Python<'_>, object: &PyAny, block: bool) -> PyResult<()> {
+        self.bridge.send(py, object, block)
+    }
+
+    /// Returns the position from the last PVT message as a dict with the latitude and longitude
+    /// in degrees and heights and accuracies in meters, `None` if no PVT was received yet.
+    fn position(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        self.bridge.position(py)
+    }
+
+    /// Wait for a PVT message with at least the given fix, one of `2d`, `3d`, `float` or `fixed`,
+    /// and return its position. Other messages received while waiting are discarded.
+    #[args(min_type = "\"3d\"", timeout = "30.0")]
+    fn wait_for_fix(&mut self, py: Python<'_>, min_type: &str, timeout: f64) -> PyResult<PyObject> {
+        self.bridge.wait_for_fix(py, min_type, timeout)
+    }
+
+    /// Set configuration values in the given layer, one of `ram`, `bbr`, `flash` or `all`.
+    /// Raises if the device does not acknowledge the values.
+    #[args(layer = "\"ram\"")]
+    fn val_set(&mut self, py: Python<'_>, values: &PyDict, layer: &str) -> PyResult<()> {
+        self.bridge.val_set(py, values, layer)
+    }
 
-        match self.send.try_send(msg) {
-            Ok(_) => Ok(()),
+    /// Get configuration values from the ram layer as a dict. Raises if the device does not
+    /// acknowledge the request.
+    fn val_get(&mut self, py: Python<'_>, keys: Vec<&str>) -> PyResult<PyObject> {
+        self.bridge.val_get(py, keys)
+    }
+}
+
+/// Iterating over a connection blocks until the next message arrives and stops once the
+/// connection to the server is closed.
+#[pyproto]
+impl PyIterProtocol for GpsConnection {
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<Self>) -> PyResult<Option<PyObject>> {
+        // The GIL is already held, this only gives a token not bound to the borrow of `slf`.
+        Python::with_gil(|py| slf.bridge.iter_next(py))
+    }
+}
+
+/// A receiver connected directly over a serial port, with the same interface as
+/// `GpsConnection`.
+#[pyclass]
+pub struct GpsSerial {
+    bridge: Bridge,
+    path: String,
+    baud: u32,
+    flat: bool,
+    queue_size: usize,
+    overflow: String,
+}
+
+impl GpsSerial {
+    async fn serial_loop(path: String, baud: u32, mut queue: Queue, mut recv: Receiver<GpsMsg>) {
+        let port = match device::open_serial(&path, baud) {
+            Ok(x) => x,
             Err(e) => {
-                if e.is_disconnected() {
-                    return Err(PyException::new_err("gps socket disconnected"));
+                let e = io::Error::new(e.kind(), format!("failed to open `{path}`: {e}"));
+                queue.push_error(e).await;
+                return;
+            }
+        };
+        let (read, mut write) = tokio::io::split(port);
+        let mut port = ParseIterator::new(read);
+
+        let mut buffer = Vec::new();
+
+        loop {
+            match future::select(port.next(), recv.next()).await {
+                Either::Left((Some(Ok(msg)), _)) => {
+                    if !queue.push(msg).await {
+                        return;
+                    }
+                }
+                Either::Left((Some(Err(e)), _)) => match e.downcast::<io::Error>() {
+                    Ok(e) => {
+                        queue.push_error(e).await;
+                        return;
+                    }
+                    Err(e) => error!("error parsing message: {e}"),
+                },
+                Either::Left((None, _)) => return,
+                Either::Right((Some(x), _)) => {
+                    buffer.clear();
+                    x.parse_write(&mut buffer).unwrap();
+                    if let Err(e) = write.write_all(&buffer).await {
+                        queue.write_failed(e);
+                    }
                 }
-                Ok(())
+                Either::Right((None, _)) => return,
+            }
+        }
+    }
+
+    fn spawn(
+        path: String,
+        baud: u32,
+        flat: bool,
+        queue_size: usize,
+        overflow: &str,
+    ) -> PyResult<Bridge> {
+        Bridge::spawn(flat, queue_size, overflow, move |queue, recv| {
+            GpsSerial::serial_loop(path, baud, queue, recv)
+        })
+    }
+}
+
+#[pymethods]
+impl GpsSerial {
+    #[new]
+    #[args(
+        path = "\"/dev/ttyACM0\"",
+        baud = "9600",
+        flat = "false",
+        queue_size = "64",
+        overflow = "\"drop\""
+    )]
+    fn new(path: &str, baud: u32, flat: bool, queue_size: usize, overflow: &str) -> PyResult<Self> {
+        let bridge = GpsSerial::spawn(path.to_string(), baud, flat, queue_size, overflow)?;
+        Ok(GpsSerial {
+            bridge,
+            path: path.to_string(),
+            baud,
+            flat,
+            queue_size,
+            overflow: overflow.to_string(),
+        })
+    }
+
+    fn __enter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        py: Python<'_>,
+        _exc_type: &PyAny,
+        _exc_value: &PyAny,
+        _traceback: &PyAny,
+    ) -> bool {
+        self.close(py);
+        false
+    }
+
+    /// Close the serial port and stop the thread reading it, the object can't be used afterwards
+    /// unless it is reopened.
+    fn close(&mut self, py: Python<'_>) {
+        self.bridge.close(py)
+    }
+
+    /// Close and open the serial port again, for example after the receiver was reset.
+    /// Queued messages are discarded.
+    fn reopen(&mut self, py: Python<'_>) -> PyResult<()> {
+        self.bridge.close(py);
+        self.bridge = GpsSerial::spawn(
+            self.path.clone(),
+            self.baud,
+            self.flat,
+            self.queue_size,
+            &self.overflow,
+        )?;
+        Ok(())
+    }
+
+    /// The number of messages dropped because the queue was full.
+    #[getter]
+    fn dropped_count(&self) -> u64 {
+        self.bridge.dropped_count()
+    }
+
+    /// Returns the next message if one is queued, otherwise returns `None` immediately.
+    fn next(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        self.bridge.next(py)
+    }
+
+    /// Wait for the next message, returns `None` if no message arrived before the timeout in
+    /// seconds. Waits indefinitely if no timeout is given.
+    #[args(timeout = "None")]
+    fn recv(&mut self, py: Python<'_>, timeout: Option<f64>) -> PyResult<Option<PyObject>> {
+        self.bridge.recv(py, timeout)
+    }
+
+    /// Send a message, waits for room in the send queue unless `block` is false in which case
+    /// `QueueFull` is raised if the queue is full.
+    #[args(block = "true")]
+    fn send(&mut self, py: Python<'_>, object: &PyAny, block: bool) -> PyResult<()> {
+        self.bridge.send(py, object, block)
+    }
+
+    /// Returns the position from the last PVT message as a dict with the latitude and longitude
+    /// in degrees and heights and accuracies in meters, `None` if no PVT was received yet.
+    fn position(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        self.bridge.position(py)
+    }
+
+    /// Wait for a PVT message with at least the given fix, one of `2d`, `3d`, `float` or `fixed`,
+    /// and return its position. Other messages received while waiting are discarded.
+    #[args(min_type = "\"3d\"", timeout = "30.0")]
+    fn wait_for_fix(&mut self, py: Python<'_>, min_type: &str, timeout: f64) -> PyResult<PyObject> {
+        self.bridge.wait_for_fix(py, min_type, timeout)
+    }
+
+    /// Set configuration values in the given layer, one of `ram`, `bbr`, `flash` or `all`.
+    /// Raises if the device does not acknowledge the values.
+    #[args(layer = "\"ram\"")]
+    fn val_set(&mut self, py: Python<'_>, values: &PyDict, layer: &str) -> PyResult<()> {
+        self.bridge.val_set(py, values, layer)
+    }
+
+    /// Get configuration values from the ram layer as a dict. Raises if the device does not
+    /// acknowledge the request.
+    fn val_get(&mut self, py: Python<'_>, keys: Vec<&str>) -> PyResult<PyObject> {
+        self.bridge.val_get(py, keys)
+    }
+}
+
+/// Iterating over a serial port blocks until the next message arrives and stops once the port
+/// is closed.
+#[pyproto]
+impl PyIterProtocol for GpsSerial {
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<Self>) -> PyResult<Option<PyObject>> {
+        // The GIL is already held, this only gives a token not bound to the borrow of `slf`.
+        Python::with_gil(|py| slf.bridge.iter_next(py))
+    }
+}
+
+/// Parse the message at the start of the buffer, returns the message and the number of bytes
+/// it used.
+#[pyfunction(flat = "false")]
+fn parse(py: Python<'_>, data: &[u8], flat: bool) -> PyResult<(PyObject, usize)> {
+    let (rest, msg) =
+        GpsMsg::parse_read(data).map_err(|e| PyValueError::new_err(format!("{e:#}")))?;
+    Ok((to_python(py, &msg, flat)?, data.len() - rest.len()))
+}
+
+/// Serialize a message in the same form as returned by `parse` to bytes.
+#[pyfunction]
+fn serialize(py: Python<'_>, msg: &PyAny) -> PyResult<PyObject> {
+    let msg = pythonize::depythonize::<GpsMsg>(msg)
+        .map_err(|e| PyValueError::new_err(format!("serialization error {e}")))?;
+    let bytes = msg
+        .parse_to_vec()
+        .map_err(|e| PyValueError::new_err(format!("{e:#}")))?;
+    Ok(PyBytes::new(py, &bytes).into())
+}
+
+/// Parse all the messages in the buffer, skipping over bytes which are not part of a valid
+/// message. Returns a list of `(offset, message)` tuples.
+#[pyfunction(flat = "false")]
+fn scan(py: Python<'_>, data: &[u8], flat: bool) -> PyResult<Vec<(usize, PyObject)>> {
+    let mut res = Vec::new();
+    let mut offset = 0;
+    while let Some(idx) = GpsMsg::find_prefix(&data[offset..]) {
+        offset += idx;
+        let b = &data[offset..];
+        // The prefix might have been there by chance, in which case the length can be garbage,
+        // so resynchronize from the next byte if the message is incomplete or fails to parse.
+        let len = match GpsMsg::message_usage(b) {
+            Some(x) => x,
+            None => {
+                offset += 1;
+                continue;
+            }
+        };
+        match GpsMsg::parse_read(&b[..len]) {
+            Ok((_, msg)) => {
+                res.push((offset, to_python(py, &msg, flat)?));
+                offset += len;
             }
+            Err(_) => offset += 1,
         }
     }
+    Ok(res)
 }
 
 #[pymodule]
-fn gps_socket(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+fn gps_socket(py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    // Fails if the module is initialized more than once, which is fine.
+    gps::logging::init(None, 0).ok();
     m.add_class::<GpsConnection>()?;
+    m.add_class::<GpsSerial>()?;
+    m.add("QueueFull", py.get_type::<QueueFull>())?;
+    m.add_function(wrap_pyfunction!(parse, m)?)?;
+    m.add_function(wrap_pyfunction!(serialize, m)?)?;
+    m.add_function(wrap_pyfunction!(scan, m)?)?;
     Ok(())
 }
@@ -0,0 +1,98 @@
+"""Builds the `gps_socket` extension for the tests and provides a mock of the gps server.
+
+Run with `pytest gps_python_bridge/tests` from the root of the repository.
+"""
+
+import json
+import os
+import shutil
+import socket
+import struct
+import subprocess
+import sys
+
+import pytest
+
+CRATE = os.path.dirname(os.path.dirname(os.path.abspath(__file__)))
+
+# Every frame send by the server starts with a little endian `u32` length and `u64` sequence
+# number.
+HEADER = struct.Struct("<IQ")
+
+
+@pytest.fixture(scope="session")
+def gps_socket(tmp_path_factory):
+    subprocess.run(["cargo", "build", "-p", "gps_python_bridge"], cwd=CRATE, check=True)
+    metadata = subprocess.run(
+        ["cargo", "metadata", "--format-version", "1", "--no-deps"],
+        cwd=CRATE,
+        check=True,
+        capture_output=True,
+    )
+    target = json.loads(metadata.stdout)["target_directory"]
+    lib = "libgps_socket.dylib" if sys.platform == "darwin" else "libgps_socket.so"
+
+    path = tmp_path_factory.mktemp("gps_socket")
+    shutil.copy(os.path.join(target, "debug", lib), path / "gps_socket.so")
+    sys.path.insert(0, str(path))
+    import gps_socket
+
+    return gps_socket
+
+
+class MockConnection:
+    """A connection from a client to the mock server."""
+
+    def __init__(self, sock):
+        self.sock = sock
+        self.seq = 0
+
+    def send(self, frame):
+        self.sock.sendall(HEADER.pack(len(frame), self.seq) + frame)
+        self.seq += 1
+
+    def recv(self):
+        length, _ = HEADER.unpack(self._read(HEADER.size))
+        return self._read(length)
+
+    def _read(self, length):
+        data = b""
+        while len(data) < length:
+            chunk = self.sock.recv(length - len(data))
+            if not chunk:
+                raise EOFError("client closed the connection")
+            data += chunk
+        return data
+
+    def close(self):
+        self.sock.close()
+
+
+class MockServer:
+    """Listens for clients on localhost, connections are accepted one at a time by the test."""
+
+    def __init__(self, port=0):
+        self.listener = socket.create_server(("127.0.0.1", port))
+        self.address = "127.0.0.1:{}".format(self.listener.getsockname()[1])
+
+    def accept(self, timeout=5.0):
+        self.listener.settimeout(timeout)
+        sock, _ = self.listener.accept()
+        sock.settimeout(timeout)
+        return MockConnection(sock)
+
+    def close(self):
+        self.listener.close()
+
+
+def free_address():
+    """An address on localhost nothing listens on."""
+    with socket.create_server(("127.0.0.1", 0)) as listener:
+        return "127.0.0.1:{}".format(listener.getsockname()[1])
+
+
+@pytest.fixture
+def server():
+    server = MockServer()
+    yield server
+    server.close()
@@ -0,0 +1,135 @@
+import threading
+import time
+
+import pytest
+
+from conftest import MockServer, free_address
+
+EOE = {"Ubx": {"Nav": {"Eoe": {"i_tow": 1000}}}}
+POLL = {"UbxPoll": {"Nav": "Clock"}}
+TIMEOUT = 5.0
+
+
+def test_serialize_parse_round_trip(gps_socket):
+    data = gps_socket.serialize(EOE)
+    assert data == bytes.fromhex("b56201610400e80300005170")
+    assert gps_socket.parse(data) == (EOE, len(data))
+    assert gps_socket.parse(data + b"trailing") == (EOE, len(data))
+    assert gps_socket.parse(data, flat=True) == ({"type": "nav-eoe", "i_tow": 1000}, len(data))
+    assert gps_socket.serialize(gps_socket.parse(gps_socket.serialize(POLL))[0]) == (
+        gps_socket.serialize(POLL)
+    )
+
+
+def test_parse_invalid_message_raises(gps_socket):
+    data = bytearray(gps_socket.serialize(EOE))
+    data[-1] ^= 0xFF
+    with pytest.raises(ValueError):
+        gps_socket.parse(bytes(data))
+
+
+def test_scan_skips_garbage(gps_socket):
+    eoe = gps_socket.serialize(EOE)
+    # A stray prefix which is not the start of a message.
+    data = b"junk" + eoe + b"\xb5\x62" + eoe + eoe[:5]
+    assert gps_socket.scan(data) == [(4, EOE), (18, EOE)]
+    assert gps_socket.scan(b"") == []
+
+
+def test_receive_every_message_in_a_frame(gps_socket, server):
+    with gps_socket.GpsConnection(server.address) as conn:
+        client = server.accept()
+        client.send(gps_socket.serialize(EOE) * 2)
+        assert conn.recv(timeout=TIMEOUT) == EOE
+        assert conn.recv(timeout=TIMEOUT) == EOE
+        assert conn.recv(timeout=0.1) is None
+
+
+def test_send_reaches_server(gps_socket, server):
+    with gps_socket.GpsConnection(server.address) as conn:
+        client = server.accept()
+        conn.send(POLL)
+        conn.send(POLL, block=False)
+        assert client.recv() == gps_socket.serialize(POLL)
+        assert client.recv() == gps_socket.serialize(POLL)
+
+
+def test_server_closing_ends_connection(gps_socket, server):
+    with gps_socket.GpsConnection(server.address) as conn:
+        server.accept().close()
+        with pytest.raises(Exception, match="gps socket quit"):
+            conn.recv(timeout=TIMEOUT)
+
+
+def fill_send_queue(gps_socket, conn):
+    """Send without blocking until the queue is full, returns the number of messages sent."""
+    for sent in range(100):
+        try:
+            conn.send(POLL, block=False)
+        except gps_socket.QueueFull:
+            return sent
+    pytest.fail("the send queue never filled up")
+
+
+def test_send_without_blocking_raises_queue_full(gps_socket):
+    # Messages are kept in the queue while reconnecting, so nothing drains it.
+    with gps_socket.GpsConnection(free_address(), reconnect=True, queue_size=2) as conn:
+        assert fill_send_queue(gps_socket, conn) <= 4
+        with pytest.raises(gps_socket.QueueFull):
+            conn.send(POLL, block=False)
+
+
+def test_blocking_send_waits_for_room(gps_socket):
+    address = free_address()
+    with gps_socket.GpsConnection(address, reconnect=True, queue_size=2) as conn:
+        queued = fill_send_queue(gps_socket, conn)
+
+        done = threading.Event()
+
+        def send():
+            conn.send(EOE)
+            done.set()
+
+        thread = threading.Thread(target=send)
+        thread.start()
+        assert not done.wait(0.3)
+
+        # Once the server is up the queue is drained and the send completes.
+        port = int(address.rsplit(":", 1)[1])
+        server = MockServer(port)
+        try:
+            client = server.accept(timeout=TIMEOUT * 2)
+            assert done.wait(TIMEOUT)
+            frames = [client.recv() for _ in range(queued + 1)]
+            assert frames == [gps_socket.serialize(POLL)] * queued + [gps_socket.serialize(EOE)]
+        finally:
+            thread.join()
+            server.close()
+
+
+def test_send_while_disconnected_without_buffer_raises(gps_socket):
+    with gps_socket.GpsConnection(
+        free_address(), reconnect=True, buffer_while_disconnected=False
+    ) as conn:
+        deadline = time.monotonic() + TIMEOUT
+        with pytest.raises(Exception, match="disconnected|not connected"):
+            while time.monotonic() < deadline:
+                conn.send(POLL)
+                time.sleep(0.05)
+
+
+def test_reconnect_after_server_closes(gps_socket, server):
+    with gps_socket.GpsConnection(server.address, reconnect=True) as conn:
+        assert conn.recv(timeout=TIMEOUT) == {"event": "connected"}
+        client = server.accept()
+        client.send(gps_socket.serialize(EOE))
+        assert conn.recv(timeout=TIMEOUT) == EOE
+        client.close()
+        assert conn.recv(timeout=TIMEOUT) == {"event": "disconnected"}
+
+        assert conn.recv(timeout=TIMEOUT) == {"event": "connected"}
+        client = server.accept()
+        client.send(gps_socket.serialize(EOE))
+        assert conn.recv(timeout=TIMEOUT) == EOE
+        conn.send(POLL)
+        assert client.recv() == gps_socket.serialize(POLL)
@@ -16,20 +16,26 @@ class GpsSocket:
         self.socket = socket()
         self.bytes = b'';
         self.addr = None
+        self.seq = None
 
     def connect(self, addr):
         self.addr = addr
         self.socket.connect(addr)
 
     def parse(self):
-        if len(self.bytes) < 4:
+        if len(self.bytes) < 12:
             return None
 
         length = int.from_bytes(self.bytes[:4],"little")
-        if len(self.bytes) < length + 4:
+        if len(self.bytes) < length + 12:
             return None
 
-        self.bytes = self.bytes[4:]
+        seq = int.from_bytes(self.bytes[4:12],"little")
+        if self.seq is not None and seq > self.seq:
+            print("dropped {} messages".format(seq - self.seq), file=sys.stderr)
+        self.seq = seq + 1
+
+        self.bytes = self.bytes[12:]
         str = self.bytes[:length]
         self.bytes = self.bytes[length:]
         return str.decode("utf-8")
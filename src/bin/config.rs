@@ -1,34 +1,78 @@
-use anyhow::{Context, Result};
-use clap::{arg, ArgAction, ArgMatches, Command};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{
+    arg, builder::TypedValueParser, value_parser, Arg, ArgAction, ArgMatches, Command,
+    PossibleValue, ValueEnum,
+};
 use enumflags2::BitFlags;
-use futures::StreamExt;
+use futures::{FutureExt, StreamExt};
 use gps::{
     connection::Connection,
     msg::{
         self,
         ubx::{
             self,
-            ack::Ack,
+            ack::MessageId,
             cfg::{
-                BbrMask, BitLayer, Cfg, Layer, Rst, ValGet, ValGetRequest, ValSet, Value, ValueKey,
+                BbrMask, BitLayer, Cfg, Layer, ResetMode, Rst, Tp5, Tp5Config, ValDel, ValGet,
+                ValGetRequest, ValSet, Value, ValueKey,
             },
         },
         GpsMsg, Ubx,
     },
-    parse::ParseData,
+    parse::{self, ParseData},
+};
+use log::{error, info, trace, warn};
+use rustyline::{error::ReadlineError, Editor};
+use serde_json::{json, Error as JsonError};
+use std::{ffi::OsStr, fmt, result::Result as StdResult, sync::mpsc::SyncSender};
+use tokio::{
+    net::TcpStream,
+    sync::mpsc::{self, UnboundedReceiver},
 };
-use log::{error, info, trace};
-use serde_json::Error as JsonError;
-use std::result::Result as StdResult;
-use tokio::net::TcpStream;
 
+/// Parse a key by its name or by its id in hex, i.e. `0x30210001`.
 fn parse_config_value(v: &str) -> StdResult<ubx::cfg::ValueKey, JsonError> {
-    serde_json::from_str(&format!("\"{v}\""))
+    let e = match serde_json::from_str(&format!("\"{v}\"")) {
+        Ok(x) => return Ok(x),
+        Err(e) => e,
+    };
+    v.strip_prefix("0x")
+        .or_else(|| v.strip_prefix("0X"))
+        .and_then(|x| u32::from_str_radix(x, 16).ok())
+        .and_then(ValueKey::from_id)
+        .ok_or(e)
+}
+
+/// Parses keys with [`parse_config_value`] while listing the key names for shell completions.
+#[derive(Clone)]
+struct ConfigKeyParser;
+
+impl TypedValueParser for ConfigKeyParser {
+    type Value = ValueKey;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> StdResult<Self::Value, clap::Error> {
+        parse_config_value.parse_ref(cmd, arg, value)
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue<'static>> + '_>> {
+        Some(Box::new(
+            ValueKey::value_variants()
+                .iter()
+                .filter_map(|x| x.to_possible_value()),
+        ))
+    }
 }
 
-async fn reconnect(mut tcp: Connection) -> Result<()> {
+async fn reconnect(mut tcp: Connection, matches: &ArgMatches) -> Result<()> {
+    let device = *matches.get_one::<u8>("device").unwrap();
     let bytes = msg::Server {
         msg: msg::server::ServerMsg::ResetPort,
+        device,
     }
     .parse_to_vec()
     .unwrap();
@@ -42,17 +86,132 @@ async fn reconnect(mut tcp: Connection) -> Result<()> {
     Ok(())
 }
 
-async fn reset(mut tcp: Connection, matches: &ArgMatches) -> Result<()> {
-    let cold = matches.get_one::<bool>("cold").unwrap();
+/// Read the next frame from the server and parse all the messages in it, the server can send
+/// multiple messages in a single frame. Returns `None` if the connection quit.
+async fn next_messages(tcp: &mut Connection) -> Option<Vec<parse::Result<GpsMsg>>> {
+    while let Some(x) = tcp.next().await {
+        match x {
+            Ok(x) => return Some(GpsMsg::parse_frame(&x).collect()),
+            Err(e) => error!("error reading from server: {:?}", e),
+        }
+    }
+    None
+}
+
+async fn stats(mut tcp: Connection, matches: &ArgMatches) -> Result<()> {
+    let device = *matches.get_one::<u8>("device").unwrap();
+    let bytes = msg::Server {
+        msg: msg::server::ServerMsg::Stats,
+        device,
+    }
+    .parse_to_vec()
+    .unwrap();
+
+    tcp.write_message(&bytes)
+        .await
+        .context("failed to send message to server")?;
+
+    while let Some(msgs) = next_messages(&mut tcp).await {
+        for msg in msgs {
+            if let Ok(GpsMsg::Server(msg::Server {
+                msg: msg::server::ServerMsg::StatsResponse(stats),
+                ..
+            })) = msg
+            {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+                return Ok(());
+            }
+        }
+    }
+    error!("server connection quit unexpectedly");
+    Ok(())
+}
+
+async fn status(mut tcp: Connection, matches: &ArgMatches) -> Result<()> {
+    let device = *matches.get_one::<u8>("device").unwrap();
+    let bytes = msg::Server {
+        msg: msg::server::ServerMsg::StatusRequest,
+        device,
+    }
+    .parse_to_vec()
+    .unwrap();
+
+    tcp.write_message(&bytes)
+        .await
+        .context("failed to send message to server")?;
+
+    while let Some(msgs) = next_messages(&mut tcp).await {
+        for msg in msgs {
+            if let Ok(GpsMsg::Server(msg::Server {
+                msg:
+                    msg::server::ServerMsg::Status {
+                        device,
+                        baud,
+                        clients,
+                        ntrip,
+                        uptime_secs,
+                    },
+                ..
+            })) = msg
+            {
+                println!("device:  {device}");
+                println!("baud:    {baud}");
+                println!("clients: {clients}");
+                println!("ntrip:   {}", if ntrip { "active" } else { "inactive" });
+                println!("uptime:  {uptime_secs}s");
+                return Ok(());
+            }
+        }
+    }
+    error!("server connection quit unexpectedly");
+    Ok(())
+}
+
+/// The kinds of reset which can be send with CFG-RST.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ResetType {
+    /// Hardware reset keeping all navigation data.
+    Hot,
+    /// Hardware reset clearing the ephemeris.
+    Warm,
+    /// Hardware reset clearing all navigation data.
+    Cold,
+    /// Controlled hardware reset.
+    Hw,
+    /// Controlled software reset.
+    Sw,
+    /// Stop the GNSS, the device keeps running.
+    GnssStop,
+    /// Start the GNSS after it was stopped.
+    GnssStart,
+}
+
+impl ResetType {
+    fn mode_and_mask(self) -> (ResetMode, BitFlags<BbrMask>) {
+        match self {
+            ResetType::Hot => (ResetMode::HardwareImmediately, BitFlags::empty()),
+            ResetType::Warm => (ResetMode::HardwareImmediately, BbrMask::Ephemeris.into()),
+            ResetType::Cold => (ResetMode::HardwareImmediately, BitFlags::all()),
+            ResetType::Hw => (ResetMode::Hardware, BitFlags::empty()),
+            ResetType::Sw => (ResetMode::ControlledSoftware, BitFlags::empty()),
+            ResetType::GnssStop => (ResetMode::ControlledGnssStop, BitFlags::empty()),
+            ResetType::GnssStart => (ResetMode::ControlledGnssStart, BitFlags::empty()),
+        }
+    }
+}
 
-    let nav_bbr_mask = if *cold {
-        BitFlags::<BbrMask>::all()
+/// Send a reset message, CFG-RST is never acknowledged so this only waits until the message is
+/// written to the server.
+async fn reset(mut tcp: Connection, matches: &ArgMatches) -> Result<()> {
+    let ty = if *matches.get_one::<bool>("cold").unwrap() {
+        ResetType::Cold
     } else {
-        BbrMask::Ephemeris.into()
+        *matches.get_one::<ResetType>("mode").unwrap()
     };
+    let (reset_mode, nav_bbr_mask) = ty.mode_and_mask();
 
     let msg = ubx::Ubx::Cfg(Cfg::Rst(Rst {
-        reset_mode: ubx::cfg::ResetMode::HardwareImmediately,
+        reset_mode,
         nav_bbr_mask,
         res1: 0,
     }));
@@ -66,7 +225,52 @@ async fn reset(mut tcp: Connection, matches: &ArgMatches) -> Result<()> {
     Ok(())
 }
 
-async fn set(mut tcp: Connection, path: &str) -> Result<()> {
+/// Wait for the server to forward an acknowledgement for the message with the given id.
+/// Returns `None` if the connection quit before an acknowledgement was recieved.
+async fn wait_for_ack(tcp: &mut Connection, id: MessageId) -> Option<bool> {
+    info!("waiting for ack...");
+    while let Some(msgs) = next_messages(tcp).await {
+        for msg in msgs {
+            trace!("msg: {:?}", msg);
+            match msg {
+                Ok(GpsMsg::Ubx(Ubx::Ack(x))) => {
+                    if let Some(x) = x.result_for(id) {
+                        return Some(x);
+                    }
+                }
+                Ok(x) => {
+                    info!("message {:?}", x)
+                }
+                Err(e) => {
+                    error!("error parsing message {:?}", e)
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Write values to the given layers of the device and wait for the acknowledgement.
+async fn valset(
+    tcp: &mut Connection,
+    values: &[Value],
+    layers: BitFlags<BitLayer>,
+) -> Result<Option<bool>> {
+    let msg = ubx::Ubx::Cfg(Cfg::ValSet(ValSet::new(layers, values.into())));
+    let bytes = msg.parse_to_vec().unwrap();
+
+    tcp.write_message(&bytes)
+        .await
+        .context("failed to send message to server")?;
+
+    Ok(wait_for_ack(tcp, MessageId::CFG_VALSET).await)
+}
+
+async fn set(mut tcp: Connection, path: &str, layers: BitFlags<BitLayer>) -> Result<()> {
+    if !layers.contains(BitLayer::Flash) {
+        warn!("values are not written to flash and will be lost once the device loses power");
+    }
+
     info!("reading config file");
     let file = tokio::fs::read(path)
         .await
@@ -78,51 +282,13 @@ async fn set(mut tcp: Connection, path: &str) -> Result<()> {
     for v in keys.chunks(64) {
         i += v.len();
         info!("writing up to `{i}` configuration values");
-        let msg = ubx::Ubx::Cfg(Cfg::ValSet(ValSet {
-            version: 0,
-            res1: [0; 2],
-            values: v.into(),
-            layers: BitLayer::Ram.into(),
-        }));
-        let bytes = msg.parse_to_vec().unwrap();
-
-        tcp.write_message(&bytes)
-            .await
-            .context("failed to send message to server")?;
-
-        info!("waiting for ack...");
-        loop {
-            if let Some(x) = tcp.next().await {
-                let x = match x {
-                    Ok(x) => x,
-                    Err(e) => {
-                        error!("error reading from server: {:?}", e);
-                        continue;
-                    }
-                };
-                let msg = GpsMsg::parse_read(&x).map(|x| x.1);
-                trace!("msg: {:?}", msg);
-                match msg {
-                    Ok(GpsMsg::Ubx(Ubx::Ack(Ack::Ack(x)))) => {
-                        if x.cls_id == 0x06 && x.msg_id == 0x8a {
-                            info!("recieved acknowledgement");
-                            break;
-                        }
-                    }
-                    Ok(GpsMsg::Ubx(Ubx::Ack(Ack::Nak(x)))) => {
-                        if x.cls_id == 0x06 && x.msg_id == 0x8a {
-                            error!("device did not acknowledge config");
-                            return Ok(());
-                        }
-                    }
-                    Ok(x) => {
-                        info!("message {:?}", x)
-                    }
-                    Err(e) => {
-                        error!("error parsing message {:?}", e)
-                    }
-                }
-            } else {
+        match valset(&mut tcp, v, layers).await? {
+            Some(true) => info!("recieved acknowledgement"),
+            Some(false) => {
+                error!("device did not acknowledge config");
+                return Ok(());
+            }
+            None => {
                 error!("server connection quit unexpectedly");
                 return Ok(());
             }
@@ -132,39 +298,114 @@ async fn set(mut tcp: Connection, path: &str) -> Result<()> {
     Ok(())
 }
 
-async fn get(mut tcp: Connection, value: Vec<ubx::cfg::ValueKey>) -> Result<()> {
-    for v in value.chunks(64) {
-        let msg = ubx::Ubx::Cfg(Cfg::ValGet(ValGet::Request(ValGetRequest {
-            layer: Layer::Ram,
-            res1: [0u8; 2],
-            keys: v.into(),
-        })));
-        let mut bytes = Vec::<u8>::new();
-        msg.parse_write(&mut bytes).unwrap();
+/// Delete values from the given layers of the device so it falls back to the defaults.
+async fn del(mut tcp: Connection, keys: Vec<ValueKey>, layers: BitFlags<BitLayer>) -> Result<()> {
+    let msg = ubx::Ubx::Cfg(Cfg::ValDel(ValDel::new(layers, keys)));
+    let bytes = msg.parse_to_vec().unwrap();
 
-        tcp.write_message(&bytes)
-            .await
-            .context("failed to send message to server")?;
+    tcp.write_message(&bytes)
+        .await
+        .context("failed to send message to server")?;
 
-        while let Some(x) = tcp.next().await {
-            let x = match x {
-                Ok(x) => x,
+    match wait_for_ack(&mut tcp, MessageId::CFG_VALDEL).await {
+        Some(true) => info!("recieved acknowledgement"),
+        Some(false) => error!("device did not acknowledge deleting the values"),
+        None => error!("server connection quit unexpectedly"),
+    }
+    Ok(())
+}
+
+/// Poll the configuration of a timepulse, returns `None` if the device did not acknowledge the
+/// poll or the connection quit.
+async fn poll_tp5(tcp: &mut Connection, index: u8) -> Result<Option<Tp5Config>> {
+    let bytes = Ubx::Cfg(Cfg::Tp5(Tp5::Poll(index))).parse_to_vec().unwrap();
+    tcp.write_message(&bytes)
+        .await
+        .context("failed to send message to server")?;
+
+    while let Some(msgs) = next_messages(tcp).await {
+        for msg in msgs {
+            match msg {
+                Ok(GpsMsg::Ubx(Ubx::Cfg(Cfg::Tp5(Tp5::Config(x))))) if x.tp_idx == index => {
+                    return Ok(Some(x));
+                }
+                Ok(GpsMsg::Ubx(Ubx::Ack(x))) => {
+                    if x.result_for(MessageId::CFG_TP5) == Some(false) {
+                        error!("device did not acknowledge timepulse poll");
+                        return Ok(None);
+                    }
+                }
+                Ok(x) => {
+                    info!("message {:?}", x)
+                }
                 Err(e) => {
-                    error!("error reading from server: {:?}", e);
-                    continue;
+                    error!("error parsing message {:?}", e)
                 }
-            };
-            match GpsMsg::parse_read(&x).map(|x| x.1) {
+            }
+        }
+    }
+    error!("server connection quit unexpectedly");
+    Ok(None)
+}
+
+async fn get_tp5(mut tcp: Connection, index: u8) -> Result<()> {
+    if let Some(x) = poll_tp5(&mut tcp, index).await? {
+        println!("{}", serde_json::to_string_pretty(&x)?);
+    }
+    Ok(())
+}
+
+async fn set_tp5(mut tcp: Connection, path: &str) -> Result<()> {
+    info!("reading timepulse config file");
+    let file = tokio::fs::read(path)
+        .await
+        .context("failed to read config file")?;
+    let config: Tp5Config = serde_json::from_slice(&file).context("failed to parse config file")?;
+
+    let bytes = Ubx::Cfg(Cfg::Tp5(Tp5::Config(config)))
+        .parse_to_vec()
+        .unwrap();
+    tcp.write_message(&bytes)
+        .await
+        .context("failed to send message to server")?;
+
+    match wait_for_ack(&mut tcp, MessageId::CFG_TP5).await {
+        Some(true) => info!("recieved acknowledgement"),
+        Some(false) => error!("device did not acknowledge timepulse config"),
+        None => error!("server connection quit unexpectedly"),
+    }
+
+    Ok(())
+}
+
+/// Request the values of the given keys in the given layer from the device.
+/// Returns `None` if the device did not acknowledge the request.
+async fn valget(
+    tcp: &mut Connection,
+    layer: Layer,
+    keys: &[ValueKey],
+) -> Result<Option<Vec<Value>>> {
+    let msg = ubx::Ubx::Cfg(Cfg::ValGet(ValGet::Request(ValGetRequest {
+        layer,
+        res1: [0u8; 2],
+        keys: keys.into(),
+    })));
+    let mut bytes = Vec::<u8>::new();
+    msg.parse_write(&mut bytes).unwrap();
+
+    tcp.write_message(&bytes)
+        .await
+        .context("failed to send message to server")?;
+
+    while let Some(msgs) = next_messages(tcp).await {
+        for msg in msgs {
+            match msg {
                 Ok(GpsMsg::Ubx(Ubx::Cfg(Cfg::ValGet(ValGet::Response(x))))) => {
-                    for k in x.keys {
-                        println!("{:?}", k);
-                    }
-                    break;
+                    return Ok(Some(x.keys));
                 }
-                Ok(GpsMsg::Ubx(Ubx::Ack(Ack::Nak(x)))) => {
-                    if x.cls_id == 0x06 && x.msg_id == 0x8b {
-                        error!("could not get value, one of the requested values might not be known to the gps device");
-                        return Ok(());
+                Ok(GpsMsg::Ubx(Ubx::Ack(x))) => {
+                    if x.result_for(MessageId::CFG_VALGET) == Some(false) {
+                        return Ok(None);
                     }
                 }
                 Ok(x) => {
@@ -176,11 +417,443 @@ async fn get(mut tcp: Connection, value: Vec<ubx::cfg::ValueKey>) -> Result<()>
             }
         }
     }
+    bail!("server connection quit unexpectedly")
+}
+
+/// Request the values of the given keys from the device in chunks.
+/// Returns the values and the keys which the device does not know.
+async fn get_values(
+    tcp: &mut Connection,
+    layer: Layer,
+    keys: &[ValueKey],
+) -> Result<(Vec<Value>, Vec<ValueKey>)> {
+    let mut values = Vec::new();
+    let mut unknown = Vec::new();
+    let mut pending: Vec<&[ValueKey]> = keys.chunks(64).rev().collect();
+    while let Some(keys) = pending.pop() {
+        match valget(tcp, layer, keys).await? {
+            Some(x) => values.extend(x),
+            None if keys.len() == 1 => unknown.push(keys[0]),
+            None => {
+                // Split the request in half to find out which of the keys is not known.
+                trace!("chunk of {} values not acknowledged, splitting", keys.len());
+                let (a, b) = keys.split_at(keys.len() / 2);
+                pending.push(b);
+                pending.push(a);
+            }
+        }
+    }
+    Ok((values, unknown))
+}
+
+async fn get(mut tcp: Connection, keys: Vec<ValueKey>) -> Result<()> {
+    let (values, unknown) = get_values(&mut tcp, Layer::Ram, &keys).await?;
+    for v in values {
+        println!("{:?}", v);
+    }
+    for k in unknown {
+        error!("gps device does not know value `{:?}`", k);
+    }
     Ok(())
 }
 
-async fn run() -> Result<()> {
-    let matches = Command::new("gps config")
+fn key_name(key: ValueKey) -> String {
+    key.to_possible_value().unwrap().get_name().to_string()
+}
+
+/// The value without the key, as it would be written in a configuration file.
+fn value_string(value: &Value) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|x| x.get("value").map(|x| x.to_string()))
+        .unwrap_or_default()
+}
+
+/// The value of a key on the device, as compared by [`diff`].
+enum DeviceValue {
+    Value(Value),
+    /// The device returned no value for the key.
+    Missing,
+    /// The device does not know the key.
+    Unknown,
+}
+
+impl DeviceValue {
+    fn differs(&self, file: &Value) -> bool {
+        match self {
+            DeviceValue::Value(x) => x != file,
+            DeviceValue::Missing | DeviceValue::Unknown => true,
+        }
+    }
+}
+
+impl fmt::Display for DeviceValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceValue::Value(x) => f.write_str(&value_string(x)),
+            DeviceValue::Missing => f.write_str("<missing>"),
+            DeviceValue::Unknown => f.write_str("<unknown key>"),
+        }
+    }
+}
+
+/// Whether the values on the device match the values in a config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiffStatus {
+    Same,
+    Different,
+}
+
+impl DiffStatus {
+    /// The exit code of the diff subcommand, like `diff` it exits with 1 if there are
+    /// differences.
+    fn exit_code(self) -> i32 {
+        match self {
+            DiffStatus::Same => 0,
+            DiffStatus::Different => 1,
+        }
+    }
+}
+
+/// Print the difference between the values on the device and the values in the given file.
+async fn diff(mut tcp: Connection, path: &str, all: bool) -> Result<DiffStatus> {
+    let file = tokio::fs::read(path)
+        .await
+        .context("failed to read config file")?;
+    let file_values: Vec<Value> =
+        serde_json::from_slice(&file).context("failed to parse config file")?;
+    let keys: Vec<ValueKey> = file_values.iter().map(|x| x.key()).collect();
+
+    let (device_values, unknown) = get_values(&mut tcp, Layer::Ram, &keys).await?;
+
+    let mut rows = Vec::new();
+    let mut status = DiffStatus::Same;
+    for value in file_values.iter() {
+        let key = value.key();
+        let device = if unknown.contains(&key) {
+            DeviceValue::Unknown
+        } else {
+            match device_values.iter().find(|x| x.key() == key) {
+                Some(x) => DeviceValue::Value(*x),
+                None => DeviceValue::Missing,
+            }
+        };
+        if device.differs(value) {
+            status = DiffStatus::Different;
+        } else if !all {
+            continue;
+        }
+        rows.push((key_name(key), device.to_string(), value_string(value)));
+    }
+
+    let key_width = rows.iter().map(|x| x.0.len()).max().unwrap_or(0).max(3);
+    let device_width = rows.iter().map(|x| x.1.len()).max().unwrap_or(0).max(6);
+    println!("{:key_width$}  {:device_width$}  file", "key", "device");
+    for (key, device, file) in rows {
+        println!("{key:key_width$}  {device:device_width$}  {file}");
+    }
+    Ok(status)
+}
+
+fn parse_bit_layer(v: &str) -> StdResult<BitFlags<BitLayer>, String> {
+    match v {
+        "ram" => Ok(BitLayer::Ram.into()),
+        "bbr" => Ok(BitLayer::Bbr.into()),
+        "flash" => Ok(BitLayer::Flash.into()),
+        "all" => Ok(BitFlags::all()),
+        _ => Err("expected one of `ram`, `bbr`, `flash` or `all`".to_string()),
+    }
+}
+
+fn parse_del_layer(v: &str) -> StdResult<BitFlags<BitLayer>, String> {
+    match v {
+        "bbr" => Ok(BitLayer::Bbr.into()),
+        "flash" => Ok(BitLayer::Flash.into()),
+        "all" => Ok(BitLayer::Bbr | BitLayer::Flash),
+        _ => Err("expected one of `bbr`, `flash` or `all`".to_string()),
+    }
+}
+
+fn parse_layer(v: &str) -> StdResult<Layer, String> {
+    match v {
+        "ram" => Ok(Layer::Ram),
+        "bbr" => Ok(Layer::Bbr),
+        "flash" => Ok(Layer::Flash),
+        "default" => Ok(Layer::Default),
+        _ => Err("expected one of `ram`, `bbr`, `flash` or `default`".to_string()),
+    }
+}
+
+/// Write the values of all known configuration keys of the device to a file which can be
+/// applied again with `set`.
+async fn dump(mut tcp: Connection, layer: Layer, path: &str) -> Result<()> {
+    let keys = ValueKey::value_variants();
+    let mut values = Vec::new();
+    let mut unknown = 0;
+
+    let mut start = 0;
+    while start < keys.len() {
+        let prefix = key_name(keys[start]);
+        let prefix = prefix.split('-').next().unwrap();
+        let len = keys[start..]
+            .iter()
+            .take_while(|x| key_name(**x).split('-').next().unwrap() == prefix)
+            .count();
+        let group = &keys[start..start + len];
+        start += len;
+
+        let (group_values, group_unknown) = get_values(&mut tcp, layer, group).await?;
+        info!(
+            "group `{prefix}`: {} values, {} unknown",
+            group_values.len(),
+            group_unknown.len()
+        );
+        unknown += group_unknown.len();
+        values.extend(group_values);
+    }
+
+    let json = serde_json::to_vec_pretty(&values)?;
+    tokio::fs::write(path, json)
+        .await
+        .context("failed to write config file")?;
+    info!(
+        "wrote {} values to `{path}`, {unknown} keys unknown to the device",
+        values.len()
+    );
+    Ok(())
+}
+
+/// A configuration file setting every key to an example value.
+fn sample_config() -> Vec<Value> {
+    ValueKey::value_variants()
+        .iter()
+        .map(|x| x.example())
+        .collect()
+}
+
+/// Print all the configuration keys grouped by their prefix or, with `--sample`, a configuration
+/// file setting every key to an example value.
+fn schema(matches: &ArgMatches) -> Result<()> {
+    if *matches.get_one::<bool>("sample").unwrap() {
+        println!("{}", serde_json::to_string_pretty(&sample_config())?);
+        return Ok(());
+    }
+
+    let keys = ValueKey::value_variants();
+
+    let mut prefix = None;
+    for key in keys {
+        let name = key.to_possible_value().unwrap().get_name();
+        let key_prefix = name.split('-').next().unwrap();
+        if prefix != Some(key_prefix) {
+            if prefix.is_some() {
+                println!();
+            }
+            println!("{key_prefix}:");
+            prefix = Some(key_prefix);
+        }
+        println!("    {name}: {}", key.type_name());
+    }
+    Ok(())
+}
+
+const SHELL_HELP: &str = "\
+get <key>...        print the values of configuration keys
+set <key> <value>   set a configuration key in ram
+send <json>         send a json encoded message to the device
+watch [filter]      print messages containing the filter, stop watching without a filter
+rate <hz>           set the measurement rate
+quit                leave the shell";
+
+/// Read lines from the terminal on a separate thread as the line editor blocks.
+/// The next prompt is only shown once the previous line was handled, which is signaled by sending
+/// on the returned sender.
+fn spawn_readline() -> (UnboundedReceiver<String>, SyncSender<()>) {
+    let (line_send, lines) = mpsc::unbounded_channel();
+    let (done, done_recv) = std::sync::mpsc::sync_channel(1);
+    std::thread::spawn(move || {
+        let mut editor = match Editor::<()>::new() {
+            Ok(x) => x,
+            Err(e) => {
+                error!("failed to create line editor: {e}");
+                return;
+            }
+        };
+        loop {
+            match editor.readline("gps> ") {
+                Ok(line) => {
+                    editor.add_history_entry(line.as_str());
+                    if line_send.send(line).is_err() || done_recv.recv().is_err() {
+                        break;
+                    }
+                }
+                Err(ReadlineError::Interrupted) => {}
+                Err(ReadlineError::Eof) => break,
+                Err(e) => {
+                    error!("error reading line: {e}");
+                    break;
+                }
+            }
+        }
+    });
+    (lines, done)
+}
+
+/// Handle a message which arrived while no command was running.
+fn shell_message(frame: &[u8], watch: Option<&str>) {
+    for msg in GpsMsg::parse_frame(frame) {
+        match msg {
+            Ok(msg) => {
+                let text = msg.to_string();
+                if watch.is_some_and(|x| text.to_lowercase().contains(x)) {
+                    println!("{text}");
+                } else {
+                    trace!("message {text}");
+                }
+            }
+            Err(e) => trace!("error parsing message {e}"),
+        }
+    }
+}
+
+fn parse_shell_key(key: &str) -> Result<ValueKey> {
+    parse_config_value(key).map_err(|_| anyhow!("unknown key `{key}`, see `gps config schema`"))
+}
+
+/// Run a single shell command, returns false if the shell should quit.
+async fn shell_command(
+    tcp: &mut Connection,
+    watch: &mut Option<String>,
+    line: &str,
+) -> Result<bool> {
+    let line = line.trim();
+    let mut args = line.split_whitespace();
+    let cmd = match args.next() {
+        Some(x) => x,
+        None => return Ok(true),
+    };
+    match cmd {
+        "get" => {
+            let keys = args.map(parse_shell_key).collect::<Result<Vec<_>>>()?;
+            if keys.is_empty() {
+                bail!("usage: get <key>...");
+            }
+            let (values, unknown) = get_values(tcp, Layer::Ram, &keys).await?;
+            for v in values {
+                println!("{} = {}", key_name(v.key()), value_string(&v));
+            }
+            for k in unknown {
+                error!("gps device does not know value `{}`", key_name(k));
+            }
+        }
+        "set" => {
+            let (key, value) = match (args.next(), args.next(), args.next()) {
+                (Some(key), Some(value), None) => (key, value),
+                _ => bail!("usage: set <key> <value>"),
+            };
+            let key = parse_shell_key(key)?;
+            let value: serde_json::Value = serde_json::from_str(value).context("invalid value")?;
+            let value: Value = serde_json::from_value(json!({ "kind": key, "value": value }))
+                .with_context(|| format!("invalid value for `{}`", key_name(key)))?;
+            shell_valset(tcp, value).await?;
+        }
+        "send" => {
+            let msg: GpsMsg = serde_json::from_str(line[cmd.len()..].trim())
+                .context("failed to parse message")?;
+            let bytes = msg.parse_to_vec().context("failed to serialize message")?;
+            tcp.write_message(&bytes)
+                .await
+                .context("failed to send message to server")?;
+        }
+        "watch" => {
+            let filter = line[cmd.len()..].trim();
+            if filter.is_empty() {
+                *watch = None;
+            } else {
+                *watch = Some(filter.to_lowercase());
+            }
+        }
+        "rate" => {
+            let hz = match (args.next().map(str::parse::<f64>), args.next()) {
+                (Some(Ok(x)), None) if x > 0.0 => x,
+                _ => bail!("usage: rate <hz>"),
+            };
+            let ms = (1000.0 / hz).round();
+            if !(1.0..=u16::MAX as f64).contains(&ms) {
+                bail!("rate of `{hz}` hz out of range");
+            }
+            shell_valset(tcp, Value::RateMeas(ms as u16)).await?;
+        }
+        "help" => println!("{SHELL_HELP}"),
+        "quit" | "exit" => return Ok(false),
+        x => bail!("unknown command `{x}`, try `help`"),
+    }
+    Ok(true)
+}
+
+async fn shell_valset(tcp: &mut Connection, value: Value) -> Result<()> {
+    match valset(tcp, &[value], BitLayer::Ram.into()).await? {
+        Some(true) => Ok(()),
+        Some(false) => bail!("device did not acknowledge config"),
+        None => bail!("server connection quit unexpectedly"),
+    }
+}
+
+/// An interactive shell which keeps the connection to the server open between commands.
+async fn shell(mut tcp: Connection) -> Result<()> {
+    let (mut lines, done) = spawn_readline();
+    let mut watch = None;
+    loop {
+        // Keep reading messages while waiting on the user so they don't pile up.
+        let line = futures::select! {
+            x = Box::pin(lines.recv()).fuse() => match x {
+                Some(x) => x,
+                None => break,
+            },
+            x = tcp.next().fuse() => {
+                match x {
+                    Some(Ok(x)) => shell_message(&x, watch.as_deref()),
+                    Some(Err(e)) => error!("error reading from server: {:?}", e),
+                    None => bail!("server connection quit unexpectedly"),
+                }
+                continue;
+            }
+        };
+        match shell_command(&mut tcp, &mut watch, &line).await {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => error!("{e:#}"),
+        }
+        done.send(()).ok();
+    }
+    Ok(())
+}
+
+/// Print a table of all configuration keys.
+fn list() {
+    let keys = ValueKey::value_variants();
+    let names: Vec<String> = keys.iter().map(|x| key_name(*x)).collect();
+    let name_width = names.iter().map(|x| x.len()).max().unwrap_or(0);
+    let type_width = keys.iter().map(|x| x.type_name().len()).max().unwrap_or(0);
+    let unit_width = keys
+        .iter()
+        .filter_map(|x| x.unit())
+        .map(|x| x.len())
+        .max()
+        .unwrap_or(0);
+
+    for (key, name) in keys.iter().zip(names) {
+        println!(
+            "{name:<name_width$}  {:<type_width$}  {:<unit_width$}  {}",
+            key.type_name(),
+            key.unit().unwrap_or(""),
+            key.description()
+        );
+    }
+}
+
+fn command() -> Command<'static> {
+    Command::new("gps config")
         .version("0.1")
         .arg(
             arg!(
@@ -190,24 +863,156 @@ async fn run() -> Result<()> {
             .default_value("0.0.0.0:9165"),
         )
         .subcommand(
-            Command::new("get").arg(
-                arg!(
-                        <VALUE> "The value(s) to get the value from"
+            Command::new("get")
+                .arg(
+                    arg!(
+                            <VALUE> "The value(s) to get the value from"
+                    )
+                    .multiple_values(true)
+                    .hide_possible_values(true)
+                    .value_parser(ConfigKeyParser),
                 )
-                .multiple_values(true)
-                .value_parser(parse_config_value),
-            ),
+                .subcommand(
+                    Command::new("tp5").arg(
+                        arg!(-i --index <INDEX> "The index of the timepulse")
+                            .required(false)
+                            .default_value("0")
+                            .value_parser(value_parser!(u8).range(0..=1)),
+                    ),
+                )
+                .args_conflicts_with_subcommands(true)
+                .subcommand_negates_reqs(true),
+        )
+        .subcommand(
+            Command::new("set")
+                .arg(arg!(<FILE> "the file to read the configuration from"))
+                .arg(
+                    arg!(-l --layer <LAYER> "The layers to write to: any of ram, bbr and flash, or all")
+                        .required(false)
+                        .use_value_delimiter(true)
+                        .action(ArgAction::Append)
+                        .default_value("ram")
+                        .value_parser(parse_bit_layer),
+                ),
+        )
+        .subcommand(
+            Command::new("del")
+                .about("Delete values from bbr or flash so the device falls back to the defaults")
+                .arg(
+                    arg!(<VALUE> "The value(s) to delete")
+                        .multiple_values(true)
+                        .hide_possible_values(true)
+                        .value_parser(ConfigKeyParser),
+                )
+                .arg(
+                    arg!(-l --layer <LAYER> "The layers to delete from: any of bbr and flash, or all")
+                        .required(false)
+                        .use_value_delimiter(true)
+                        .action(ArgAction::Append)
+                        .default_value("flash")
+                        .value_parser(parse_del_layer),
+                ),
         )
-        .subcommand(Command::new("set").arg(arg!(
-            <FILE> "the file to read the configuration from"
+        .subcommand(Command::new("set-tp5").arg(arg!(
+            <FILE> "the file to read the timepulse configuration from"
         )))
         .subcommand(
             Command::new("reset")
-                .arg(arg!(-c --cold "do a cold reset of the device").action(ArgAction::SetTrue)),
+                .arg(
+                    arg!(-m --mode <MODE> "The kind of reset")
+                        .required(false)
+                        .default_value("warm")
+                        .value_parser(value_parser!(ResetType)),
+                )
+                .arg(
+                    arg!(-c --cold "do a cold reset of the device, same as `--mode cold`")
+                        .conflicts_with("mode")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("reconnect").arg(
+                arg!(-d --device <INDEX> "The index of the device to reconnect")
+                    .required(false)
+                    .default_value("0")
+                    .value_parser(value_parser!(u8)),
+            ),
+        )
+        .subcommand(
+            Command::new("stats").arg(
+                arg!(-d --device <INDEX> "The index of the device")
+                    .required(false)
+                    .default_value("0")
+                    .value_parser(value_parser!(u8)),
+            ),
+        )
+        .subcommand(
+            Command::new("status")
+                .about("Show the status of a device of the server")
+                .arg(
+                    arg!(-d --device <INDEX> "The index of the device")
+                        .required(false)
+                        .default_value("0")
+                        .value_parser(value_parser!(u8)),
+                ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Show the values in a configuration file which differ from the device")
+                .arg(arg!(<FILE> "the file to read the configuration from"))
+                .arg(
+                    arg!(-a --all "Show all values, not only differing ones")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("dump")
+                .about("Write the value of every known key on the device to a configuration file")
+                .arg(arg!(<OUT> "the file to write the configuration to"))
+                .arg(
+                    arg!(-l --layer <LAYER> "The layer to read from: ram, bbr, flash or default")
+                        .required(false)
+                        .default_value("ram")
+                        .value_parser(parse_layer),
+                ),
+        )
+        .subcommand(
+            Command::new("shell").about("Run commands interactively over a single connection"),
+        )
+        .subcommand(
+            Command::new("list")
+                .about("List the available configuration keys with their descriptions"),
+        )
+        .subcommand(
+            Command::new("schema")
+                .about("List the available configuration keys and their types")
+                .arg(
+                    arg!(--sample "Print an example configuration file instead")
+                        .action(ArgAction::SetTrue),
+                ),
         )
-        .subcommand(Command::new("reconnect"))
         .subcommand_required(true)
-        .get_matches();
+        .subcommand(gps::completions::command())
+        .args(gps::logging::args())
+}
+
+async fn run() -> Result<()> {
+    let matches = command().get_matches();
+
+    gps::logging::init_from_matches(&matches).context("failed to initialize logging")?;
+
+    match matches.subcommand() {
+        Some(("schema", m)) => return schema(m),
+        Some(("list", _)) => {
+            list();
+            return Ok(());
+        }
+        Some((gps::completions::SUBCOMMAND, m)) => {
+            gps::completions::generate(command(), m);
+            return Ok(());
+        }
+        _ => {}
+    }
 
     let address = matches.get_one::<String>("address").unwrap();
 
@@ -219,6 +1024,10 @@ async fn run() -> Result<()> {
 
     match matches.subcommand() {
         Some(("get", sub_m)) => {
+            if let Some(("tp5", m)) = sub_m.subcommand() {
+                get_tp5(tcp, *m.get_one::<u8>("index").unwrap()).await?;
+                return Ok(());
+            }
             let values = sub_m
                 .get_many::<ValueKey>("VALUE")
                 .unwrap()
@@ -228,13 +1037,53 @@ async fn run() -> Result<()> {
         }
         Some(("set", sub_m)) => {
             let file = sub_m.get_one::<String>("FILE").unwrap();
-            set(tcp, file).await?;
+            let layers = sub_m
+                .get_many::<BitFlags<BitLayer>>("layer")
+                .unwrap()
+                .fold(BitFlags::empty(), |acc, x| acc | *x);
+            set(tcp, file, layers).await?;
+        }
+        Some(("del", sub_m)) => {
+            let keys = sub_m
+                .get_many::<ValueKey>("VALUE")
+                .unwrap()
+                .copied()
+                .collect();
+            let layers = sub_m
+                .get_many::<BitFlags<BitLayer>>("layer")
+                .unwrap()
+                .fold(BitFlags::empty(), |acc, x| acc | *x);
+            del(tcp, keys, layers).await?;
+        }
+        Some(("diff", sub_m)) => {
+            let file = sub_m.get_one::<String>("FILE").unwrap();
+            let status = diff(tcp, file, *sub_m.get_one::<bool>("all").unwrap()).await?;
+            if status != DiffStatus::Same {
+                std::process::exit(status.exit_code());
+            }
+        }
+        Some(("dump", sub_m)) => {
+            let file = sub_m.get_one::<String>("OUT").unwrap();
+            dump(tcp, *sub_m.get_one::<Layer>("layer").unwrap(), file).await?;
+        }
+        Some(("set-tp5", sub_m)) => {
+            let file = sub_m.get_one::<String>("FILE").unwrap();
+            set_tp5(tcp, file).await?;
         }
         Some(("reset", sub_m)) => {
             reset(tcp, sub_m).await?;
         }
-        Some(("reconnect", _)) => {
-            reconnect(tcp).await?;
+        Some(("stats", m)) => {
+            stats(tcp, m).await?;
+        }
+        Some(("status", m)) => {
+            status(tcp, m).await?;
+        }
+        Some(("reconnect", m)) => {
+            reconnect(tcp, m).await?;
+        }
+        Some(("shell", _)) => {
+            shell(tcp).await?;
         }
         _ => unreachable!(),
     }
@@ -243,12 +1092,297 @@ async fn run() -> Result<()> {
 }
 
 fn main() -> Result<()> {
-    env_logger::init_from_env(
-        env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "info"),
-    );
-
     tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()?
         .block_on(run())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gps::msg::ubx::cfg::ValGetResponse;
+    use gps::msg::ubx::{
+        ack::{Ack, AckData},
+        nav::{Eoe, Nav},
+    };
+    use std::future::Future;
+    use tokio::{net::TcpListener, sync::oneshot};
+
+    /// A connection to a mock server, `device` is run with the server side of the connection.
+    async fn mock<F, Fut>(device: F) -> Connection
+    where
+        F: FnOnce(Connection) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let stream = listener.accept().await.unwrap().0;
+            stream.set_nodelay(true).unwrap();
+            device(Connection::new(stream)).await;
+        });
+        let stream = TcpStream::connect(addr).await.unwrap();
+        stream.set_nodelay(true).unwrap();
+        Connection::new(stream)
+    }
+
+    /// A connection to a server which sends the given frames and then disconnects.
+    async fn serve(frames: Vec<Vec<u8>>) -> Connection {
+        mock(|mut server| async move {
+            for frame in frames {
+                server.write_message(&frame).await.unwrap();
+            }
+        })
+        .await
+    }
+
+    fn ack(id: MessageId) -> Vec<u8> {
+        GpsMsg::Ubx(Ubx::Ack(Ack::Ack(AckData {
+            cls_id: id.class,
+            msg_id: id.id,
+        })))
+        .parse_to_vec()
+        .unwrap()
+    }
+
+    fn nak(id: MessageId) -> Vec<u8> {
+        GpsMsg::Ubx(Ubx::Ack(Ack::Nak(AckData {
+            cls_id: id.class,
+            msg_id: id.id,
+        })))
+        .parse_to_vec()
+        .unwrap()
+    }
+
+    fn batch(msgs: &[GpsMsg]) -> Vec<u8> {
+        msgs.iter()
+            .flat_map(|x| x.parse_to_vec().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn batched_frame_is_fully_decoded() {
+        let eoe = GpsMsg::Ubx(Ubx::Nav(Nav::Eoe(Eoe { i_tow: 1000 })));
+        let ack = GpsMsg::Ubx(Ubx::Ack(Ack::Ack(AckData {
+            cls_id: MessageId::CFG_VALSET.class,
+            msg_id: MessageId::CFG_VALSET.id,
+        })));
+        let frame = batch(&[eoe.clone(), eoe, ack]);
+
+        let mut tcp = serve(vec![frame.clone()]).await;
+        let msgs = next_messages(&mut tcp).await.unwrap();
+        assert_eq!(msgs.len(), 3);
+        assert!(msgs.iter().all(|x| x.is_ok()));
+        assert!(next_messages(&mut tcp).await.is_none());
+
+        // The acknowledgement is found even though it is not the first message of the frame.
+        let mut tcp = serve(vec![frame]).await;
+        assert_eq!(
+            wait_for_ack(&mut tcp, MessageId::CFG_VALSET).await,
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn sample_config_parses_back() {
+        let sample = sample_config();
+        assert_eq!(sample.len(), ValueKey::value_variants().len());
+
+        let file = serde_json::to_string_pretty(&sample).unwrap();
+        let values: Vec<Value> = serde_json::from_str(&file).unwrap();
+        assert_eq!(values, sample);
+
+        // The values are written to the device in chunks like `set` does.
+        for chunk in values.chunks(64) {
+            let msg = ubx::Ubx::Cfg(Cfg::ValSet(ValSet::new(BitLayer::Ram.into(), chunk.into())));
+            let bytes = msg.parse_to_vec().unwrap();
+            match ubx::Ubx::parse_read(&bytes).unwrap() {
+                ([], ubx::Ubx::Cfg(Cfg::ValSet(x))) => {
+                    assert_eq!(x.values, chunk)
+                }
+                x => panic!("expected CFG-VALSET, found {x:?}"),
+            }
+        }
+    }
+
+    /// A CFG-TP5 response for timepulse 1, a 1Hz pulse of 100ms aligned to UTC with a cable delay
+    /// of 50ns.
+    const TP5_FRAME: [u8; 40] = [
+        0xb5, 0x62, 0x06, 0x31, 0x20, 0x00, 0x01, 0x01, 0x00, 0x00, 0x32, 0x00, 0x00, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0xa0, 0x86, 0x01, 0x00, 0xa0, 0x86, 0x01, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0xf7, 0x00, 0x00, 0x00, 0xd2, 0xbe,
+    ];
+
+    #[tokio::test]
+    async fn tp5_poll_returns_the_device_config() {
+        let mut tcp = mock(|mut device| async move {
+            let poll = device.next().await.unwrap().unwrap();
+            assert_eq!(
+                poll,
+                Ubx::Cfg(Cfg::Tp5(Tp5::Poll(1))).parse_to_vec().unwrap()
+            );
+            device.write_message(&TP5_FRAME).await.unwrap();
+        })
+        .await;
+
+        let config = poll_tp5(&mut tcp, 1).await.unwrap().unwrap();
+        assert_eq!(config.tp_idx, 1);
+        assert_eq!(config.ant_cable_delay, 50);
+        assert_eq!(config.freq_period, 1);
+        assert_eq!(config.pulse_len_ratio, 100_000);
+        assert!(config.flags.active && config.flags.is_length && config.flags.polarity);
+        assert!(!config.flags.is_freq);
+        assert_eq!(config.flags.grid_utc_gnss, 1);
+
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["pulse_len_ratio"], 100_000);
+        assert_eq!(json["flags"]["align_to_tow"], true);
+    }
+
+    #[tokio::test]
+    async fn tp5_poll_without_acknowledgement_returns_nothing() {
+        let mut tcp = mock(|mut device| async move {
+            device.next().await.unwrap().unwrap();
+            device
+                .write_message(&nak(MessageId::CFG_TP5))
+                .await
+                .unwrap();
+        })
+        .await;
+        assert!(poll_tp5(&mut tcp, 0).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn set_tp5_sends_the_config_file() {
+        let config = match Ubx::parse_read(&TP5_FRAME).unwrap() {
+            (_, Ubx::Cfg(Cfg::Tp5(Tp5::Config(x)))) => x,
+            x => panic!("expected CFG-TP5, found {x:?}"),
+        };
+        let path = std::env::temp_dir().join(format!("gps-config-tp5-{}.json", std::process::id()));
+        std::fs::write(&path, serde_json::to_vec_pretty(&config).unwrap()).unwrap();
+
+        let (send, recv) = oneshot::channel();
+        let tcp = mock(|mut device| async move {
+            let frame = device.next().await.unwrap().unwrap();
+            device
+                .write_message(&ack(MessageId::CFG_TP5))
+                .await
+                .unwrap();
+            send.send(frame).unwrap();
+        })
+        .await;
+        set_tp5(tcp, path.to_str().unwrap()).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(recv.await.unwrap(), TP5_FRAME);
+    }
+
+    #[tokio::test]
+    async fn bisecting_finds_the_rejected_key() {
+        // More than one chunk, the second one containing the key the device doesn't know.
+        let keys = ValueKey::value_variants()[..100].to_vec();
+        let rejected = keys[70];
+
+        let (send, recv) = oneshot::channel();
+        let mut tcp = mock(move |mut device| async move {
+            let mut requests = 0;
+            while let Some(Ok(frame)) = device.next().await {
+                requests += 1;
+                let keys = match Ubx::parse_read(&frame).unwrap() {
+                    (_, Ubx::Cfg(Cfg::ValGet(ValGet::Request(x)))) => x.keys,
+                    x => panic!("expected CFG-VALGET, found {x:?}"),
+                };
+                let reply = if keys.contains(&rejected) {
+                    nak(MessageId::CFG_VALGET)
+                } else {
+                    Ubx::Cfg(Cfg::ValGet(ValGet::Response(ValGetResponse {
+                        layer: Layer::Ram,
+                        res1: [0; 2],
+                        keys: keys.iter().map(|x| x.example()).collect(),
+                    })))
+                    .parse_to_vec()
+                    .unwrap()
+                };
+                device.write_message(&reply).await.unwrap();
+            }
+            send.send(requests).unwrap();
+        })
+        .await;
+
+        let (values, unknown) = get_values(&mut tcp, Layer::Ram, &keys).await.unwrap();
+        assert_eq!(unknown, [rejected]);
+        let value_keys = values.iter().map(|x| x.key()).collect::<Vec<_>>();
+        let known = keys.iter().copied().filter(|x| *x != rejected);
+        assert_eq!(value_keys, known.collect::<Vec<_>>());
+
+        // Two requests for the chunks and two for each of the five splits which take the chunk
+        // of 36 keys down to the rejected key.
+        drop(tcp);
+        assert_eq!(recv.await.unwrap(), 2 + 2 * 5);
+    }
+
+    #[test]
+    fn bash_completions_list_config_keys() {
+        let mut script = Vec::new();
+        clap_complete::generate(
+            clap_complete::Shell::Bash,
+            &mut command(),
+            "config",
+            &mut script,
+        );
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("uart1-baudrate"));
+        assert!(script.contains(gps::completions::SUBCOMMAND));
+    }
+
+    /// A device which answers every CFG-VALGET with the given values for the requested keys.
+    async fn valget_device(values: Vec<Value>) -> Connection {
+        mock(move |mut device| async move {
+            while let Some(Ok(frame)) = device.next().await {
+                let keys = match Ubx::parse_read(&frame).unwrap() {
+                    (_, Ubx::Cfg(Cfg::ValGet(ValGet::Request(x)))) => x.keys,
+                    x => panic!("expected CFG-VALGET, found {x:?}"),
+                };
+                let reply = Ubx::Cfg(Cfg::ValGet(ValGet::Response(ValGetResponse {
+                    layer: Layer::Ram,
+                    res1: [0; 2],
+                    keys: values
+                        .iter()
+                        .filter(|x| keys.contains(&x.key()))
+                        .cloned()
+                        .collect(),
+                })));
+                device
+                    .write_message(&reply.parse_to_vec().unwrap())
+                    .await
+                    .unwrap();
+            }
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn diff_exits_with_one_on_differences() {
+        let dir = std::env::temp_dir().join(format!("gps-config-diff-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        let file = vec![Value::RateMeas(100), Value::Uart1Baudrate(115_200)];
+        std::fs::write(&path, serde_json::to_vec(&file).unwrap()).unwrap();
+        let path = path.to_str().unwrap();
+
+        let tcp = valget_device(file.clone()).await;
+        let status = diff(tcp, path, false).await.unwrap();
+        assert_eq!((status, status.exit_code()), (DiffStatus::Same, 0));
+
+        let tcp = valget_device(vec![Value::RateMeas(100), Value::Uart1Baudrate(9600)]).await;
+        let status = diff(tcp, path, true).await.unwrap();
+        assert_eq!((status, status.exit_code()), (DiffStatus::Different, 1));
+
+        // A key the device doesn't return a value for counts as a difference.
+        let tcp = valget_device(vec![Value::RateMeas(100)]).await;
+        let status = diff(tcp, path, false).await.unwrap();
+        assert_eq!((status, status.exit_code()), (DiffStatus::Different, 1));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -1,21 +1,36 @@
-use std::{net::SocketAddr, str::FromStr};
+use std::{net::SocketAddr, path::PathBuf, str::FromStr};
 
-use anyhow::{anyhow, Context, Result};
-use clap::{arg, value_parser, ArgAction, Command};
+use anyhow::{Context, Result};
+use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
 use futures::{
     future::{self, Either},
     SinkExt, StreamExt,
 };
 use gps::{
     connection::{ConnectionPool, OutgoingConnection},
-    msg::GpsMsg,
+    msg::{FlatGpsMsg, GpsMsg},
     parse::ParseData,
 };
 use log::{error, info, trace};
-use tokio::net::TcpListener;
+use tokio::{
+    net::TcpListener,
+    signal::unix::{signal, SignalKind},
+};
+
+#[cfg(feature = "schemars")]
+fn schema() -> Result<()> {
+    let schema = schemars::schema_for!(GpsMsg);
+    println!("{}", serde_json::to_string_pretty(&schema)?);
+    Ok(())
+}
 
-async fn run() -> Result<()> {
-    let matches = Command::new("gps format")
+#[cfg(not(feature = "schemars"))]
+fn schema() -> Result<()> {
+    anyhow::bail!("built without json schema support, enable the `schemars` feature")
+}
+
+fn command() -> Command<'static> {
+    Command::new("gps format")
         .version("0.1")
         .arg(
             arg!(
@@ -46,11 +61,32 @@ async fn run() -> Result<()> {
             )
             .action(ArgAction::SetTrue),
         )
-        .get_matches();
+        .arg(
+            arg!(
+                --pidfile <PATH> "Write the pid of the deamon to the given file"
+            )
+            .required(false)
+            .requires("deamon")
+            .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(
+                --flat "Send messages as flat objects with a `type` field instead of nested enums"
+            )
+            .action(ArgAction::SetTrue),
+        )
+        .subcommand(Command::new("schema").about(
+            "Print the json schema of the messages send to clients, without the `--flat` option",
+        ))
+        .subcommand_negates_reqs(true)
+        .args(gps::logging::args())
+}
 
+async fn run(matches: ArgMatches) -> Result<()> {
     let address = matches.get_one::<SocketAddr>("ADDRESS").unwrap();
     let server_address = matches.get_one::<String>("host").unwrap();
     let server_port = *matches.get_one::<u16>("port").unwrap();
+    let flat = *matches.get_one::<bool>("flat").unwrap();
 
     let listener = TcpListener::bind((server_address.as_str(), server_port))
         .await
@@ -60,15 +96,20 @@ async fn run() -> Result<()> {
 
     let mut outgoing = OutgoingConnection::new(Some(*address));
 
-    if *matches.get_one::<bool>("deamon").unwrap() {
-        gps::deamonize()
-            .map_err(|_| anyhow!("deamon creation error"))
-            .context("failed to create a deamon")?;
-    }
+    let mut terminate =
+        signal(SignalKind::terminate()).context("failed to install signal handler")?;
 
     info!("starting parsing server");
     loop {
-        match future::select(connections.next(), outgoing.next()).await {
+        let next = future::select(connections.next(), outgoing.next());
+        let next = match future::select(next, Box::pin(terminate.recv())).await {
+            Either::Left((x, _)) => x,
+            Either::Right(_) => {
+                info!("recieved terminate signal, quiting");
+                return Ok(());
+            }
+        };
+        match next {
             // Just to ensure that connections are accepting, messages are ignored.
             Either::Left((Some(x), _)) => match serde_json::from_slice::<GpsMsg>(&x) {
                 Ok(x) => {
@@ -80,35 +121,73 @@ async fn run() -> Result<()> {
                     error!("error deserializing incomming message {e}");
                 }
             },
-            Either::Right((Some(x), _)) => match GpsMsg::parse_read(&x) {
-                Ok((_, x)) => {
-                    trace!("message: {:?}", x);
-                    match serde_json::to_vec(&x) {
-                        Ok(data) => {
-                            connections.send(data).await.unwrap();
-                            connections.flush().await.unwrap();
+            Either::Right((Some(x), _)) => {
+                trace!("frame from server:\n{}", gps::logging::trace_hexdump(&x));
+                for x in GpsMsg::parse_frame(&x) {
+                    match x {
+                        Ok(x) => {
+                            trace!("message: {:?}", x);
+                            let data = if flat {
+                                serde_json::to_vec(&FlatGpsMsg(&x))
+                            } else {
+                                serde_json::to_vec(&x)
+                            };
+                            match data {
+                                Ok(data) => {
+                                    connections.send(data).await.unwrap();
+                                    connections.flush().await.unwrap();
+                                }
+                                Err(e) => {
+                                    error!("error serializing message {e}");
+                                }
+                            }
                         }
                         Err(e) => {
-                            error!("error serializing message {e}");
+                            error!("error parsing message: {e}");
                         }
                     }
                 }
-                Err(e) => {
-                    error!("error parsing message: {e}");
-                }
-            },
+            }
             _ => unreachable!(),
         }
     }
 }
 
 fn main() -> Result<()> {
-    env_logger::init_from_env(
-        env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "info"),
-    );
+    let matches = command().get_matches();
+
+    gps::logging::init_from_matches(&matches).context("failed to initialize logging")?;
+
+    if let Some(("schema", _)) = matches.subcommand() {
+        return schema();
+    }
+
+    // Only the forking thread survives in the deamon, so this has to happen before the runtime
+    // is started.
+    let _pidfile = if *matches.get_one::<bool>("deamon").unwrap() {
+        gps::deamonize(matches.get_one::<PathBuf>("pidfile").map(|x| x.as_path()))
+            .context("failed to create a deamon")?
+    } else {
+        None
+    };
 
     tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()?
-        .block_on(run())
+        .block_on(run(matches))
+}
+
+#[cfg(all(test, feature = "schemars"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema_describes_pvt_fields() {
+        let schema = serde_json::to_value(schemars::schema_for!(GpsMsg)).unwrap();
+        let pvt = &schema["$defs"]["Pvt"]["properties"];
+        assert_eq!(pvt["lon"]["type"], "integer");
+        assert_eq!(pvt["lon"]["format"], "int32");
+        assert_eq!(pvt["numsv"]["type"], "integer");
+        assert_eq!(pvt["valid"]["type"], "array");
+    }
 }
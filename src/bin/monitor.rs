@@ -1,26 +1,80 @@
 use std::{
-    collections::VecDeque,
-    io::{stdout, Write},
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    fs::OpenOptions,
+    io::{self, stdin, stdout, Write},
     net::SocketAddr,
+    path::PathBuf,
     str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
-use anyhow::Result;
-use clap::{arg, Command};
-use futures::StreamExt;
+use anyhow::{Context, Result};
+use clap::{arg, value_parser, Command, ValueEnum};
+use futures::{FutureExt, StreamExt};
 use gps::{
     connection::OutgoingConnection,
+    device,
     msg::{
         ubx::{
+            cfg::{Cfg, Layer, ValGet, ValGetRequest, Value, ValueKey},
             mon::{CommBlock, Mon},
-            nav::{Nav, Pvt, RelPosNed},
-            rxm::Rxm,
+            nav::{
+                CarrierPhaseSol, Clock, FixType, Hpposllh, Nav, Odo, Pvt, RelPosNed, Sat, Svin,
+                TimeLs,
+            },
+            rxm::{self, RtcmFlags, Rxm},
         },
         GpsMsg, Ubx,
     },
-    parse::ParseData,
+    parse::{ParseData, ParseIterator},
+};
+use log::error;
+use serde::Serialize;
+use termion::{event::Key, input::TermRead, raw::IntoRawMode, screen::AlternateScreen};
+use tokio::{
+    io::{ReadHalf, WriteHalf},
+    signal::unix::{signal, SignalKind},
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
 };
-use termion::screen::AlternateScreen;
+use tokio_serial::SerialStream;
+
+/// The width of the signal strength bars in the satellite table.
+const SAT_BAR_WIDTH: usize = 20;
+/// The signal strength in dBHz shown as a full bar.
+const SAT_BAR_MAX_CNO: usize = 50;
+/// The time without a new navigation epoch after which the shown data is marked as stale.
+const STALE_TIMEOUT: Duration = Duration::from_secs(5);
+/// The window over which the rate of rtcm corrections is computed.
+const RTCM_RATE_WINDOW: Duration = Duration::from_secs(10);
+/// The age after which missing rtcm observations are highlighted.
+const RTCM_STALE_TIMEOUT: Duration = Duration::from_secs(5);
+/// The smoothing factor of the moving average of rtcm crc failures.
+const RTCM_CRC_ALPHA: f32 = 0.1;
+/// The percentage of rtcm crc failures above which the rate is highlighted.
+const RTCM_CRC_WARN_RATE: f32 = 5.0;
+/// The width of the progress bar of the survey-in.
+const SVIN_BAR_WIDTH: usize = 20;
+
+type MessageFilter = fn(&GpsMsg) -> bool;
+
+/// The filters for the message list which can be cycled through.
+const MESSAGE_FILTERS: &[(&str, MessageFilter)] = &[
+    ("all", |_| true),
+    ("nav", |x| matches!(x, GpsMsg::Ubx(Ubx::Nav(_)))),
+    ("rxm", |x| matches!(x, GpsMsg::Ubx(Ubx::Rxm(_)))),
+    ("mon", |x| matches!(x, GpsMsg::Ubx(Ubx::Mon(_)))),
+    ("ack", |x| matches!(x, GpsMsg::Ubx(Ubx::Ack(_)))),
+    ("rtcm", |x| matches!(x, GpsMsg::Rtcm3(_))),
+    ("nmea", |x| matches!(x, GpsMsg::Nmea(_))),
+];
+
+/// The number of messages kept for the message list.
+const MESSAGE_HISTORY: usize = 100;
 
 pub struct Writer {
     size: (u16, u16),
@@ -50,15 +104,27 @@ impl Writer {
         self.cursor = (0, 0);
     }
 
+    /// Write text at the cursor, text which does not fit on the screen is cut off.
     fn write_line(&mut self, line: &str) {
-        let remaining = self.size.0 - self.cursor.0;
-        if line.len() > remaining as usize {
-            self.cursor.0 = self.size.1;
-            write!(&mut self.buffer, "{}", &line[..(remaining as usize) - 3]).unwrap();
-            write!(&mut self.buffer, "...").unwrap();
+        // Lines below the screen would scroll the terminal and garble the output.
+        if self.cursor.1 >= self.size.1 {
+            return;
+        }
+        let remaining = self.size.0.saturating_sub(self.cursor.0) as usize;
+        let len = line.chars().count();
+        if len > remaining {
+            // Only end with dots if there is room for at least one character of the text.
+            let (keep, dots) = if remaining > 3 {
+                (remaining - 3, "...")
+            } else {
+                (remaining, "")
+            };
+            let end = line.char_indices().nth(keep).map_or(line.len(), |x| x.0);
+            write!(&mut self.buffer, "{}{}", &line[..end], dots).unwrap();
+            self.cursor.0 = self.size.0;
         } else {
-            self.cursor.0 = self.cursor.0 + line.len() as u16;
             write!(&mut self.buffer, "{}", line).unwrap();
+            self.cursor.0 += len as u16;
         }
     }
 
@@ -83,6 +149,15 @@ impl Writer {
         .unwrap();
     }
 
+    /// Write a line of cells, each padded to the width given with the cell.
+    fn write_columns(&mut self, cells: &[(&str, usize)]) {
+        let mut line = String::new();
+        for (cell, width) in cells {
+            line += &format!("{cell:<width$} ");
+        }
+        self.write_line(line.trim_end());
+    }
+
     fn flush(&mut self, w: &mut impl Write) -> Result<()> {
         w.write_all(&self.buffer)?;
         self.buffer.clear();
@@ -90,15 +165,176 @@ impl Writer {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Output {
+    /// The terminal interface.
+    Tui,
+    /// A json object per epoch.
+    Json,
+    /// A line of text per epoch.
+    Plain,
+}
+
+/// The state at the end of a navigation epoch, printed per epoch when not showing the interface.
+#[derive(Debug, Clone, Serialize)]
+pub struct EpochSummary {
+    itow: u32,
+    fix: Option<FixType>,
+    carrier_solution: Option<CarrierPhaseSol>,
+    numsv: Option<u8>,
+    /// The horizontal accuracy in meters.
+    h_acc: Option<f64>,
+    /// The vertical accuracy in meters.
+    v_acc: Option<f64>,
+    /// The length of the baseline to the base station in meters.
+    baseline_length: Option<f64>,
+    /// The RTCM message types used in the epoch.
+    rtcm: Vec<u16>,
+}
+
+impl fmt::Display for EpochSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn opt<T: fmt::Display>(x: Option<T>) -> String {
+            x.map(|x| x.to_string()).unwrap_or_else(|| "-".to_string())
+        }
+
+        let rtcm = self
+            .rtcm
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        write!(
+            f,
+            "itow={} fix={} carrier={} numsv={} hacc={} vacc={} baseline={} rtcm={}",
+            self.itow,
+            opt(self.fix.map(|x| format!("{x:?}"))),
+            opt(self.carrier_solution.map(|x| format!("{x:?}"))),
+            opt(self.numsv),
+            opt(self.h_acc.map(|x| format!("{x:.3}"))),
+            opt(self.v_acc.map(|x| format!("{x:.3}"))),
+            opt(self.baseline_length.map(|x| format!("{x:.4}"))),
+            if rtcm.is_empty() { "-" } else { &rtcm }
+        )
+    }
+}
+
+/// When rtcm corrections of a single message type were used by the device.
+struct RtcmStats {
+    /// The times the type was seen within the last [`RTCM_RATE_WINDOW`].
+    times: VecDeque<Instant>,
+    last: Instant,
+}
+
+impl RtcmStats {
+    fn new() -> Self {
+        RtcmStats {
+            times: VecDeque::new(),
+            last: Instant::now(),
+        }
+    }
+
+    fn push(&mut self) {
+        self.last = Instant::now();
+        self.times.push_back(self.last);
+        self.prune();
+    }
+
+    /// Remove the times which are outside of the rate window.
+    fn prune(&mut self) {
+        while self
+            .times
+            .front()
+            .is_some_and(|x| x.elapsed() > RTCM_RATE_WINDOW)
+        {
+            self.times.pop_front();
+        }
+    }
+
+    /// The number of messages per second within the rate window.
+    fn rate(&self) -> f32 {
+        self.times.len() as f32 / RTCM_RATE_WINDOW.as_secs_f32()
+    }
+}
+
+/// An exponential moving average of the rtcm corrections which failed the crc check.
+#[derive(Default)]
+struct RtcmCrcStats {
+    /// The fraction of failed messages.
+    average: f32,
+}
+
+impl RtcmCrcStats {
+    fn update(&mut self, msg: &rxm::Rtcm) {
+        let failed = if msg.flags.contains(RtcmFlags::CrcFailed) {
+            1.0
+        } else {
+            0.0
+        };
+        self.average += RTCM_CRC_ALPHA * (failed - self.average);
+    }
+
+    /// The number of failed messages per 100 messages.
+    fn crc_fail_rate(&self) -> f32 {
+        self.average * 100.0
+    }
+}
+
+fn clock_line(x: &Clock) -> String {
+    format!(
+        "clk bias {} ns, drift {} ns/s, acc {} ns",
+        x.clk_b, x.clk_d, x.t_acc
+    )
+}
+
+/// Whether the rtcm message type contains observations, as opposed to for example the position
+/// of the base station which is send less often.
+fn is_rtcm_observation(kind: u16) -> bool {
+    match kind {
+        // Legacy GPS and GLONASS observations.
+        1001..=1004 | 1009..=1012 => true,
+        // MSM1 to MSM7 of all constellations.
+        1071..=1137 => (1..=7).contains(&(kind % 10)),
+        _ => false,
+    }
+}
+
 pub struct Info {
     last_itow: Option<u32>,
+    /// When the last navigation message was recieved.
+    last_epoch: Option<Instant>,
+    /// When the connection to the server was opened, `None` while reconnecting.
+    connected_since: Option<Instant>,
+    connect_attempt: u32,
     error: Option<String>,
     messages: VecDeque<GpsMsg>,
+    /// Don't redraw on new messages.
+    paused: bool,
+    /// The index of the filter in [`MESSAGE_FILTERS`] applied to the message list.
+    filter: usize,
+    /// The number of messages scrolled back in the message list.
+    scroll: usize,
     comms: Vec<CommBlock>,
     acked_rtcm: Vec<u16>,
     prev_acked_rtcm: Vec<u16>,
+    /// The timing of the rtcm corrections by rtcm message type.
+    rtcm_stats: BTreeMap<u16, RtcmStats>,
+    rtcm_crc: RtcmCrcStats,
     pvt: Option<Pvt>,
+    hpposllh: Option<Hpposllh>,
+    odo: Option<Odo>,
+    svin: Option<Svin>,
+    /// The configured minimum survey-in duration in seconds, polled once a survey-in is seen.
+    svin_min_dur: Option<u32>,
+    /// The configured survey-in accuracy limit in 0.1 mm.
+    svin_acc_limit: Option<u32>,
+    /// Show the position as the integers send by the device.
+    raw_position: bool,
+    recorder: Option<Recorder>,
     relposned: Option<RelPosNed>,
+    clock: Option<Clock>,
+    time_ls: Option<TimeLs>,
+    sat: Option<Sat>,
     writer: Writer,
 }
 
@@ -106,13 +342,31 @@ impl Info {
     pub fn new() -> Self {
         Info {
             last_itow: None,
+            last_epoch: None,
+            connected_since: None,
+            connect_attempt: 1,
             error: None,
             messages: VecDeque::new(),
+            paused: false,
+            filter: 0,
+            scroll: 0,
             comms: Vec::new(),
             pvt: None,
+            hpposllh: None,
+            odo: None,
+            svin: None,
+            svin_min_dur: None,
+            svin_acc_limit: None,
+            raw_position: false,
+            recorder: None,
             relposned: None,
+            clock: None,
+            time_ls: None,
+            sat: None,
             acked_rtcm: Vec::new(),
             prev_acked_rtcm: Vec::new(),
+            rtcm_stats: BTreeMap::new(),
+            rtcm_crc: RtcmCrcStats::default(),
             writer: Writer {
                 size: (0, 0),
                 cursor: (0, 0),
@@ -125,6 +379,26 @@ impl Info {
         self.writer.reset_size()?;
         self.writer.clear();
 
+        if let Some(x) = self.last_epoch.filter(|x| x.elapsed() > STALE_TIMEOUT) {
+            write!(
+                &mut self.writer,
+                "{}",
+                termion::color::Fg(termion::color::Yellow)
+            )?;
+            let line = format!(
+                "STALE: no navigation epoch for {} s, the data below may be outdated",
+                x.elapsed().as_secs()
+            );
+            self.writer.write_line(&line);
+            write!(
+                &mut self.writer,
+                "{}",
+                termion::color::Fg(termion::color::Reset)
+            )?;
+            self.writer.next_line();
+            self.writer.next_line();
+        }
+
         for (idx, b) in self.comms.iter().enumerate() {
             let msg = format!(
                 "port {idx}({:>3}): rx/tx {:>3}%/{:>3}% errors: {:>4}, skipped: {:>6}",
@@ -137,14 +411,10 @@ impl Info {
             self.writer.next_line();
         }
 
-        if !self.prev_acked_rtcm.is_empty() {
-            self.writer.write_line("RXM RTCM: ");
-            for x in self.prev_acked_rtcm.iter().copied() {
-                self.writer.write_line(&format!("{x} "));
-            }
-            self.writer.next_line();
-            self.writer.next_line();
-        }
+        self.draw_rtcm()?;
+
+        self.draw_position()?;
+        self.draw_svin()?;
 
         if let Some(x) = self.pvt.as_ref() {
             self.writer.write_line("PVT:");
@@ -167,6 +437,44 @@ impl Info {
             self.writer.next_line();
         }
 
+        if let Some(x) = self.odo.as_ref() {
+            self.writer.write_line("Odometer:");
+            self.writer.next_line();
+            self.writer.write_line("    ");
+            let line = format!(
+                "distance {} m ± {} m, total {} m",
+                x.distance, x.distance_std, x.total_distance
+            );
+            self.writer.write_line(&line);
+            self.writer.next_line();
+            self.writer.next_line();
+        }
+
+        if let Some(x) = self.clock.as_ref() {
+            self.writer.write_line("Clock:");
+            self.writer.next_line();
+            self.writer.write_line("    ");
+            self.writer.write_line(&clock_line(x));
+            self.writer.next_line();
+            self.writer.next_line();
+        }
+
+        if let Some(x) = self.time_ls.as_ref().filter(|x| x.cur_ls_valid()) {
+            self.writer.write_line("Leap seconds:");
+            self.writer.next_line();
+            self.writer.write_line("    ");
+            let mut line = format!("current {}", x.cur_ls);
+            if x.ls_change != 0 && x.time_to_ls_event_valid() {
+                line += &format!(
+                    ", change of {:+} in {} s (gps week {} day {})",
+                    x.ls_change, x.time_to_ls_event, x.date_of_ls_gps_wn, x.date_of_ls_gps_dn
+                );
+            }
+            self.writer.write_line(&line);
+            self.writer.next_line();
+            self.writer.next_line();
+        }
+
         if let Some(x) = self.relposned.as_ref() {
             self.writer.write_line("RelPosNed:");
             self.writer.next_line();
@@ -197,6 +505,8 @@ impl Info {
             self.writer.next_line();
         }
 
+        self.draw_sats();
+
         if let Some(x) = self.error.as_ref() {
             write!(
                 &mut self.writer,
@@ -222,10 +532,13 @@ impl Info {
             "{}",
             termion::color::Fg(termion::color::Green)
         )?;
-        for m in self.messages.iter() {
-            let msg = format!("{:?}", m);
+        let filter = MESSAGE_FILTERS[self.filter].1;
+        let messages = self.messages.iter().filter(|x| filter(x)).skip(self.scroll);
+        for m in messages {
+            let msg = m.to_string();
             self.writer.write_line(&msg);
-            if self.writer.cursor.1 >= self.writer.size.1 - 1 {
+            // The last line is used for the status line.
+            if self.writer.cursor.1 + 2 >= self.writer.size.1 {
                 break;
             }
             self.writer.next_line();
@@ -235,11 +548,159 @@ impl Info {
             "{}",
             termion::color::Fg(termion::color::Reset)
         )?;
+
+        self.writer.goto((0, height.saturating_sub(1)));
+        let link = match self.connected_since {
+            Some(x) => {
+                let secs = x.elapsed().as_secs();
+                format!(
+                    "connected {:02}:{:02}:{:02}",
+                    secs / 3600,
+                    secs / 60 % 60,
+                    secs % 60
+                )
+            }
+            None => format!("reconnecting (attempt {})", self.connect_attempt),
+        };
+        let record = match self.recorder.as_ref() {
+            Some(x) => format!("rec: {x} | "),
+            None => String::new(),
+        };
+        let status = format!(
+            "link: {link} | {}{record}filter: {} | scroll: {} | q: quit, p: pause, f: filter, {}i: raw position, up/down: scroll",
+            if self.paused { "PAUSED | " } else { "" },
+            MESSAGE_FILTERS[self.filter].0,
+            self.scroll,
+            if self.recorder.is_some() { "r: record, " } else { "" },
+        );
+        self.writer.write_line(&status);
         self.writer.flush(w)?;
         Ok(())
     }
 
+    /// Draw a table of the tracked satellites with the strongest signals, limited to the rows
+    /// above the message list.
+    fn draw_sats(&mut self) {
+        let x = match self.sat.as_ref() {
+            Some(x) => x,
+            None => return,
+        };
+        // Leave room for the header and the empty line after the table.
+        let rows = (self.writer.size.1 / 2).saturating_sub(self.writer.cursor.1 + 3) as usize;
+        if rows == 0 {
+            return;
+        }
+
+        let mut svs: Vec<_> = x.svs.iter().collect();
+        svs.sort_by_key(|x| std::cmp::Reverse(x.cno));
+
+        let widths = [7, 3, 3, SAT_BAR_WIDTH, 4, 4, 4];
+        let header = ["gnss", "sv", "cno", "", "elev", "azim", "used"];
+        self.writer
+            .write_columns(&header.iter().copied().zip(widths).collect::<Vec<_>>());
+        self.writer.next_line();
+        for sv in svs.into_iter().take(rows) {
+            let bar =
+                "#".repeat((sv.cno as usize * SAT_BAR_WIDTH / SAT_BAR_MAX_CNO).min(SAT_BAR_WIDTH));
+            let cells = [
+                sv.gnss_name().to_string(),
+                sv.sv_id.to_string(),
+                sv.cno.to_string(),
+                bar,
+                sv.elev.to_string(),
+                sv.azim.to_string(),
+                if sv.used() { "yes" } else { "no" }.to_string(),
+            ];
+            self.writer.write_columns(
+                &cells
+                    .iter()
+                    .map(|x| x.as_str())
+                    .zip(widths)
+                    .collect::<Vec<_>>(),
+            );
+            self.writer.next_line();
+        }
+        self.writer.next_line();
+    }
+
+    /// Draw the progress of the survey-in against the configured minimum duration and accuracy,
+    /// once the device sends NAV-SVIN.
+    fn draw_svin(&mut self) -> Result<()> {
+        let Some(x) = self.svin.as_ref() else {
+            return Ok(());
+        };
+
+        let (color, state): (&dyn termion::color::Color, _) = if x.valid != 0 {
+            (&termion::color::Green, "valid")
+        } else if x.active != 0 {
+            (&termion::color::Yellow, "active")
+        } else {
+            (&termion::color::Reset, "inactive")
+        };
+        self.writer.write_line("Survey-in: ");
+        write!(&mut self.writer, "{}", termion::color::Fg(color))?;
+        self.writer.write_line(state);
+        write!(
+            &mut self.writer,
+            "{}",
+            termion::color::Fg(termion::color::Reset)
+        )?;
+        self.writer.next_line();
+
+        self.writer.write_line("    ");
+        let mut line = format!("duration {} s", x.dur);
+        if let Some(min_dur) = self.svin_min_dur {
+            let progress = if min_dur == 0 {
+                1.0
+            } else {
+                (x.dur as f64 / min_dur as f64).min(1.0)
+            };
+            let bar = "#".repeat((progress * SVIN_BAR_WIDTH as f64) as usize);
+            line += &format!(" / {min_dur} s [{bar:<SVIN_BAR_WIDTH$}]");
+        }
+        line += &format!(", observations {}", x.obs);
+        self.writer.write_line(&line);
+        self.writer.next_line();
+
+        self.writer.write_line("    ");
+        let mut line = format!("mean acc {:.4} m", x.mean_acc as f64 * 1e-4);
+        if let Some(limit) = self.svin_acc_limit {
+            line += &format!(" / {:.4} m", limit as f64 * 1e-4);
+        }
+        self.writer.write_line(&line);
+        self.writer.next_line();
+
+        self.writer.write_line("    ");
+        let line = format!(
+            "mean ecef x/y/z {:.4}/{:.4}/{:.4} m",
+            x.mean_x_m(),
+            x.mean_y_m(),
+            x.mean_z_m()
+        );
+        self.writer.write_line(&line);
+        self.writer.next_line();
+        self.writer.next_line();
+        Ok(())
+    }
+
+    /// Update the state of the connection to the server.
+    fn update_link(&mut self, source: &Source) {
+        if !source.is_connected() {
+            if self.connected_since.take().is_some() {
+                // Don't show rtcm messages from before the link dropped as if they are current.
+                self.acked_rtcm.clear();
+                self.prev_acked_rtcm.clear();
+                self.rtcm_stats.clear();
+                self.rtcm_crc = RtcmCrcStats::default();
+            }
+            self.connect_attempt = source.retry_count() + 1;
+        } else if self.connected_since.is_none() {
+            self.connected_since = Some(Instant::now());
+        }
+    }
+
     fn handle_itow(&mut self, itow: u32) {
+        self.last_epoch = Some(Instant::now());
         if self.last_itow == Some(itow) {
             return;
         }
@@ -253,6 +714,11 @@ impl Info {
         match *msg {
             GpsMsg::Ubx(Ubx::Rxm(Rxm::Rtcm(ref x))) => {
                 self.acked_rtcm.push(x.msg_type);
+                self.rtcm_stats
+                    .entry(x.msg_type)
+                    .or_insert_with(RtcmStats::new)
+                    .push();
+                self.rtcm_crc.update(x);
             }
             GpsMsg::Ubx(Ubx::Nav(Nav::Eoe(ref x))) => {
                 self.handle_itow(x.i_tow);
@@ -261,6 +727,39 @@ impl Info {
                 self.handle_itow(x.i_tow);
                 self.pvt = Some(x.clone())
             }
+            GpsMsg::Ubx(Ubx::Nav(Nav::Hpposllh(ref x))) => {
+                self.handle_itow(x.i_tow);
+                self.hpposllh = Some(x.clone())
+            }
+            GpsMsg::Ubx(Ubx::Nav(Nav::Odo(ref x))) => {
+                self.handle_itow(x.i_tow);
+                self.odo = Some(x.clone())
+            }
+            GpsMsg::Ubx(Ubx::Nav(Nav::Svin(ref x))) => {
+                self.handle_itow(x.i_tow);
+                self.svin = Some(x.clone())
+            }
+            GpsMsg::Ubx(Ubx::Cfg(Cfg::ValGet(ValGet::Response(ref x)))) => {
+                for v in x.keys.iter() {
+                    match *v {
+                        Value::TmodeSvinMinDur(x) => self.svin_min_dur = Some(x),
+                        Value::TmodeSvinAccLimit(x) => self.svin_acc_limit = Some(x),
+                        _ => {}
+                    }
+                }
+            }
+            GpsMsg::Ubx(Ubx::Nav(Nav::Clock(ref x))) => {
+                self.handle_itow(x.i_tow);
+                self.clock = Some(x.clone())
+            }
+            GpsMsg::Ubx(Ubx::Nav(Nav::Sat(ref x))) => {
+                self.handle_itow(x.i_tow);
+                self.sat = Some(x.clone())
+            }
+            GpsMsg::Ubx(Ubx::Nav(Nav::TimeLs(ref x))) => {
+                self.handle_itow(x.i_tow);
+                self.time_ls = Some(x.clone())
+            }
             GpsMsg::Ubx(Ubx::Nav(Nav::RelPosNed(ref x))) => {
                 self.handle_itow(x.i_tow);
                 self.relposned = Some(x.clone())
@@ -275,12 +774,184 @@ impl Info {
         }
     }
 
-    pub fn push_message(&mut self, msg: GpsMsg) {
+    /// Draw the rate and age of the rtcm corrections used by the device.
+    fn draw_rtcm(&mut self) -> Result<()> {
+        if self.rtcm_stats.is_empty() {
+            return Ok(());
+        }
+
+        let widths = [6, 6, 7, 5];
+        let header = ["rtcm", "rate/s", "age s", "epoch"];
+        self.writer
+            .write_columns(&header.iter().copied().zip(widths).collect::<Vec<_>>());
+        self.writer.next_line();
+        for (kind, stats) in self.rtcm_stats.iter_mut() {
+            stats.prune();
+            let age = stats.last.elapsed();
+            let stale = age > RTCM_STALE_TIMEOUT && is_rtcm_observation(*kind);
+            if stale {
+                write!(
+                    &mut self.writer,
+                    "{}",
+                    termion::color::Fg(termion::color::Red)
+                )?;
+            }
+            let cells = [
+                kind.to_string(),
+                format!("{:.1}", stats.rate()),
+                format!("{:.1}", age.as_secs_f32()),
+                if self.prev_acked_rtcm.contains(kind) {
+                    "yes"
+                } else {
+                    "no"
+                }
+                .to_string(),
+            ];
+            self.writer.write_columns(
+                &cells
+                    .iter()
+                    .map(|x| x.as_str())
+                    .zip(widths)
+                    .collect::<Vec<_>>(),
+            );
+            if stale {
+                write!(
+                    &mut self.writer,
+                    "{}",
+                    termion::color::Fg(termion::color::Reset)
+                )?;
+            }
+            self.writer.next_line();
+        }
+
+        let crc_fail_rate = self.rtcm_crc.crc_fail_rate();
+        if crc_fail_rate > RTCM_CRC_WARN_RATE {
+            write!(
+                &mut self.writer,
+                "{}",
+                termion::color::Fg(termion::color::Red)
+            )?;
+        }
+        self.writer
+            .write_line(&format!("crc failures: {crc_fail_rate:.1}%"));
+        write!(
+            &mut self.writer,
+            "{}",
+            termion::color::Fg(termion::color::Reset)
+        )?;
+        self.writer.next_line();
+        self.writer.next_line();
+        Ok(())
+    }
+
+    /// Draw the position, from NAV-HPPOSLLH if the device sends it otherwise from NAV-PVT.
+    fn draw_position(&mut self) -> Result<()> {
+        let Some(pvt) = self.pvt.as_ref() else {
+            return Ok(());
+        };
+
+        let color: &dyn termion::color::Color = match (pvt.fix_type, pvt.flags.car_sol) {
+            (FixType::NoFix, _) => &termion::color::Red,
+            (_, CarrierPhaseSol::Float) => &termion::color::Yellow,
+            (_, CarrierPhaseSol::Fixed) => &termion::color::Green,
+            _ => &termion::color::Reset,
+        };
+        self.writer.write_line("Position:");
+        self.writer.next_line();
+        self.writer.write_line("    ");
+        write!(&mut self.writer, "{}", termion::color::Fg(color))?;
+        let line = format!("fix `{:?}` carrier `{:?}`", pvt.fix_type, pvt.flags.car_sol);
+        self.writer.write_line(&line);
+        write!(
+            &mut self.writer,
+            "{}",
+            termion::color::Fg(termion::color::Reset)
+        )?;
+        self.writer.next_line();
+        self.writer.write_line("    ");
+
+        let line = match (self.hpposllh.as_ref(), self.raw_position) {
+            (Some(x), false) => format!(
+                "lat {:.8}° lon {:.8}° height {:.4} m msl {:.4} m",
+                x.lat_deg(),
+                x.lon_deg(),
+                x.height_m(),
+                x.h_msl_m()
+            ),
+            (Some(x), true) => format!(
+                "lat {}/{} lon {}/{} height {}/{} msl {}/{}",
+                x.lat, x.lat_hp, x.lon, x.lon_hp, x.height, x.height_hp, x.h_msl, x.h_msl_hp
+            ),
+            (None, false) => format!(
+                "lat {:.8}° lon {:.8}° height {:.3} m msl {:.3} m",
+                pvt.lat as f64 * 1e-7,
+                pvt.lon as f64 * 1e-7,
+                pvt.height as f64 * 1e-3,
+                pvt.height_sea as f64 * 1e-3
+            ),
+            (None, true) => format!(
+                "lat {} lon {} height {} msl {}",
+                pvt.lat, pvt.lon, pvt.height, pvt.height_sea
+            ),
+        };
+        self.writer.write_line(&line);
+        self.writer.next_line();
+        self.writer.next_line();
+        Ok(())
+    }
+
+    /// Handle a key press, returns false if the monitor should quit.
+    fn handle_key(&mut self, key: Key) -> bool {
+        match key {
+            Key::Char('q') | Key::Ctrl('c') => return false,
+            Key::Char('p') => self.paused = !self.paused,
+            Key::Char('i') => self.raw_position = !self.raw_position,
+            Key::Char('r') => {
+                if let Some(x) = self.recorder.as_mut() {
+                    x.toggle();
+                }
+            }
+            Key::Char('f') => {
+                self.filter = (self.filter + 1) % MESSAGE_FILTERS.len();
+                self.scroll = 0;
+            }
+            Key::Up => self.scroll = (self.scroll + 1).min(MESSAGE_HISTORY - 1),
+            Key::Down => self.scroll = self.scroll.saturating_sub(1),
+            _ => {}
+        }
+        true
+    }
+
+    /// Handle a message, returns the summary of the epoch when the message ends an epoch.
+    pub fn push_message(&mut self, msg: GpsMsg) -> Option<EpochSummary> {
         self.handle_msg(&msg);
+        let summary = match msg {
+            GpsMsg::Ubx(Ubx::Nav(Nav::Eoe(ref x))) => Some(self.epoch_summary(x.i_tow)),
+            _ => None,
+        };
         self.messages.push_front(msg);
-        if self.messages.len() > 100 {
+        if self.messages.len() > MESSAGE_HISTORY {
             self.messages.pop_back();
         }
+        summary
+    }
+
+    fn epoch_summary(&self, itow: u32) -> EpochSummary {
+        let pvt = self.pvt.as_ref().filter(|x| x.i_tow == itow);
+        EpochSummary {
+            itow,
+            fix: pvt.map(|x| x.fix_type),
+            carrier_solution: pvt.map(|x| x.flags.car_sol),
+            numsv: pvt.map(|x| x.numsv),
+            h_acc: pvt.map(|x| x.h_acc as f64 * 1e-3),
+            v_acc: pvt.map(|x| x.v_acc as f64 * 1e-3),
+            baseline_length: self
+                .relposned
+                .as_ref()
+                .filter(|x| x.i_tow == itow)
+                .map(|x| x.rel_pos_length as f64 * 1e-2 + x.rel_pos_length_hp as f64 * 1e-4),
+            rtcm: self.acked_rtcm.clone(),
+        }
     }
 }
 
@@ -295,28 +966,315 @@ async fn run() -> Result<()> {
             .default_value("127.0.0.1:9165")
             .value_parser(SocketAddr::from_str),
         )
+        .arg(
+            arg!(--serial <PATH> "Read from a receiver on a serial port instead of connecting to a server")
+                .required(false),
+        )
+        .arg(
+            arg!(--baud <BAUD> "The baud rate of the serial port")
+                .required(false)
+                .requires("serial")
+                .default_value("9600")
+                .value_parser(value_parser!(u32)),
+        )
+        .arg(
+            arg!(--record <PATH> "Append the raw messages to the given file while recording is toggled on with `r`")
+                .required(false)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--output <FORMAT> "Print a summary of every epoch instead of showing the interface")
+                .required(false)
+                .default_value("tui")
+                .value_parser(value_parser!(Output)),
+        )
+        .args(gps::logging::args())
         .get_matches();
 
-    let address = matches.get_one::<SocketAddr>("ADDRESS").unwrap();
-    let mut outgoing_connection = OutgoingConnection::new(Some(*address));
+    let output = *matches.get_one::<Output>("output").unwrap();
+
+    // Logs would garble the screen, so they are only written when logging to a file.
+    if output != Output::Tui || matches.contains_id("log-file") {
+        gps::logging::init_from_matches(&matches).context("failed to initialize logging")?;
+    }
 
-    let mut screen = AlternateScreen::from(stdout());
+    let source = match matches.get_one::<String>("serial") {
+        Some(path) => Source::serial(path, *matches.get_one::<u32>("baud").unwrap())?,
+        None => {
+            let address = matches.get_one::<SocketAddr>("ADDRESS").unwrap();
+            Source::Server(OutgoingConnection::new(Some(*address)))
+        }
+    };
 
+    match output {
+        Output::Tui => {
+            let record = matches.get_one::<PathBuf>("record").cloned();
+            tui(source, record).await
+        }
+        Output::Json | Output::Plain => headless(source, output).await,
+    }
+}
+
+/// Print a summary of every epoch to stdout.
+async fn headless(mut source: Source, output: Output) -> Result<()> {
     let mut info = Info::new();
+    let mut stdout = stdout();
+    while let Some(x) = source.next().await? {
+        for m in GpsMsg::parse_frame(&x) {
+            let m = match m {
+                Ok(x) => x,
+                Err(e) => {
+                    error!("parsing error: `{e}`");
+                    continue;
+                }
+            };
+            let Some(summary) = info.push_message(m) else {
+                continue;
+            };
+            if output == Output::Json {
+                serde_json::to_writer(&mut stdout, &summary)?;
+                writeln!(stdout)?;
+            } else {
+                writeln!(stdout, "{summary}")?;
+            }
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
 
-    while let Some(x) = outgoing_connection.next().await {
-        match GpsMsg::parse_read(&x) {
-            Ok((_, m)) => {
-                info.push_message(m);
+async fn tui(mut source: Source, record: Option<PathBuf>) -> Result<()> {
+    let mut screen = AlternateScreen::from(
+        stdout()
+            .into_raw_mode()
+            .context("failed to put terminal in raw mode")?,
+    );
+    let mut keys = spawn_keys();
+    let mut resize = signal(SignalKind::window_change()).context("failed to listen for resizes")?;
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+
+    let mut info = Info::new();
+    info.recorder = record.map(Recorder::new);
+    // The survey-in targets are only polled once the device is seen doing a survey-in.
+    let mut svin_polled = false;
+    let svin_poll = Ubx::Cfg(Cfg::ValGet(ValGet::Request(ValGetRequest {
+        layer: Layer::Ram,
+        res1: [0; 2],
+        keys: vec![ValueKey::TmodeSvinMinDur, ValueKey::TmodeSvinAccLimit],
+    })))
+    .parse_to_vec()
+    .unwrap();
+
+    loop {
+        info.update_link(&source);
+        futures::select! {
+            x = source.next().fuse() => {
+                let Some(x) = x? else {
+                    return Ok(());
+                };
+                if let Some(recorder) = info.recorder.as_mut() {
+                    recorder.write(&x);
+                }
+                for m in GpsMsg::parse_frame(&x) {
+                    match m {
+                        Ok(m) => {
+                            info.push_message(m);
+                        }
+                        Err(e) => {
+                            info.error = Some(format!("parsing error: `{e}`"));
+                        }
+                    }
+                }
+                if info.svin.is_some() && !svin_polled {
+                    svin_polled = source.try_send_message(&svin_poll).await;
+                }
+                if info.paused {
+                    continue;
+                }
             }
-            Err(e) => {
-                info.error = Some(format!("parsing error: `{e}`"));
+            x = keys.recv().fuse() => {
+                let Some(x) = x else {
+                    return Ok(());
+                };
+                if !info.handle_key(x) {
+                    return Ok(());
+                }
+            }
+            // The size of the terminal is read again on every redraw.
+            _ = resize.recv().fuse() => {}
+            // Keep the link status and stale marker up to date when no messages arrive.
+            _ = tick.tick().fuse() => {
+                if info.paused {
+                    continue;
+                }
             }
         }
         info.redraw(&mut screen)?;
     }
+}
+
+/// Where the monitor reads messages from.
+enum Source {
+    /// A gps server, reconnected when the connection drops.
+    Server(OutgoingConnection),
+    /// A receiver connected directly over a serial port.
+    Serial {
+        port: ParseIterator<ReadHalf<SerialStream>>,
+        writer: WriteHalf<SerialStream>,
+    },
+}
+
+impl Source {
+    /// Open a receiver on a serial port.
+    fn serial(path: &str, baud: u32) -> Result<Self> {
+        let port = device::open_serial(path, baud)
+            .with_context(|| format!("failed to open serial port `{path}`"))?;
+        let (read, writer) = tokio::io::split(port);
+        Ok(Source::Serial {
+            port: ParseIterator::new(read),
+            writer,
+        })
+    }
 
-    Ok(())
+    /// Returns the next frame, a frame from a serial port contains a single message.
+    async fn next(&mut self) -> Result<Option<Vec<u8>>> {
+        match self {
+            Source::Server(x) => Ok(x.next().await),
+            Source::Serial { port, .. } => match port.next().await {
+                Some(Ok(_)) => Ok(Some(port.last_frame().to_vec())),
+                Some(Err(e)) => match e.downcast::<io::Error>() {
+                    Ok(e) => Err(e).context("failed to read from serial port"),
+                    // The frame is parsed again by the caller which reports the error.
+                    Err(_) => Ok(Some(port.last_frame().to_vec())),
+                },
+                None => Ok(None),
+            },
+        }
+    }
+
+    fn is_connected(&self) -> bool {
+        match self {
+            Source::Server(x) => x.is_connected(),
+            Source::Serial { .. } => true,
+        }
+    }
+
+    fn retry_count(&self) -> u32 {
+        match self {
+            Source::Server(x) => x.retry_count(),
+            Source::Serial { .. } => 0,
+        }
+    }
+
+    /// Write a message to the source, returns whether the message was written.
+    async fn try_send_message(&mut self, message: &[u8]) -> bool {
+        // Not imported at the top as it conflicts with `std::io::Write` for the screen writer.
+        use tokio::io::AsyncWriteExt;
+
+        match self {
+            Source::Server(x) => x.try_send_message(message).await,
+            Source::Serial { writer, .. } => match writer.write_all(message).await {
+                Ok(()) => true,
+                Err(e) => {
+                    error!("error writing to serial port {e}");
+                    false
+                }
+            },
+        }
+    }
+}
+
+/// Appends the raw frames to a file while toggled on, in the same format as the recordings of
+/// the server.
+///
+/// The file is written on a blocking task so a slow disk does not hold up redrawing.
+struct Recorder {
+    path: PathBuf,
+    send: Option<UnboundedSender<Vec<u8>>>,
+    /// The size of the file, updated by the writing task.
+    size: Arc<AtomicU64>,
+    failed: bool,
+}
+
+impl Recorder {
+    fn new(path: PathBuf) -> Self {
+        let size = std::fs::metadata(&path).map(|x| x.len()).unwrap_or(0);
+        Recorder {
+            path,
+            send: None,
+            size: Arc::new(AtomicU64::new(size)),
+            failed: false,
+        }
+    }
+
+    fn toggle(&mut self) {
+        if self.send.take().is_some() {
+            // Dropping the sender stops the task once it has written the remaining frames.
+            return;
+        }
+        let (send, mut recv) = mpsc::unbounded_channel::<Vec<u8>>();
+        let path = self.path.clone();
+        let size = self.size.clone();
+        tokio::task::spawn_blocking(move || {
+            let res = (|| {
+                let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+                size.store(file.metadata()?.len(), Ordering::Relaxed);
+                while let Some(x) = recv.blocking_recv() {
+                    file.write_all(&x)?;
+                    size.fetch_add(x.len() as u64, Ordering::Relaxed);
+                }
+                file.flush()
+            })();
+            if let Err(e) = res {
+                error!("failed to write recording `{}`: {e}", path.display());
+            }
+        });
+        self.send = Some(send);
+        self.failed = false;
+    }
+
+    fn write(&mut self, frame: &[u8]) {
+        let Some(send) = self.send.as_ref() else {
+            return;
+        };
+        // The task only stops early if writing failed.
+        if send.send(frame.to_vec()).is_err() {
+            self.send = None;
+            self.failed = true;
+        }
+    }
+}
+
+impl fmt::Display for Recorder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match (self.send.is_some(), self.failed) {
+            (true, _) => "on",
+            (false, true) => "failed",
+            (false, false) => "off",
+        };
+        let size = self.size.load(Ordering::Relaxed) as f64;
+        if size >= 1024.0 * 1024.0 {
+            write!(f, "{state} {:.1} MiB", size / (1024.0 * 1024.0))
+        } else {
+            write!(f, "{state} {:.1} KiB", size / 1024.0)
+        }
+    }
+}
+
+/// Read key presses from stdin on a separate thread as termion only provides blocking reads.
+fn spawn_keys() -> UnboundedReceiver<Key> {
+    let (send, recv) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        for k in stdin().keys() {
+            let Ok(k) = k else {
+                break;
+            };
+            if send.send(k).is_err() {
+                break;
+            }
+        }
+    });
+    recv
 }
 
 fn main() -> Result<()> {
@@ -325,3 +1283,169 @@ fn main() -> Result<()> {
         .build()?
         .block_on(run())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::CStr, fs::File, os::unix::io::FromRawFd};
+
+    use super::*;
+    use gps::msg::ubx::nav::Eoe;
+
+    #[test]
+    fn clock_line_format() {
+        let clock = Clock {
+            clk_b: -1500,
+            clk_d: 12,
+            t_acc: 20,
+            ..Default::default()
+        };
+        assert_eq!(
+            clock_line(&clock),
+            "clk bias -1500 ns, drift 12 ns/s, acc 20 ns"
+        );
+    }
+
+    /// The text written by a single line on a screen of the given width.
+    fn written(width: u16, line: &str) -> String {
+        let mut writer = Writer {
+            size: (width, 1),
+            cursor: (0, 0),
+            buffer: Vec::new(),
+        };
+        writer.write_line(line);
+        assert!(writer.cursor.0 <= width);
+        String::from_utf8(writer.buffer).unwrap()
+    }
+
+    #[test]
+    fn lines_are_cut_to_the_screen() {
+        let line = "lat 52.0°, lön 4.5°";
+        assert_eq!(written(0, line), "");
+        assert_eq!(written(1, line), "l");
+        assert_eq!(written(2, line), "la");
+        assert_eq!(written(3, line), "lat");
+        assert_eq!(written(4, line), "l...");
+        assert_eq!(written(11, line), "lat 52.0...");
+        assert_eq!(written(14, line), "lat 52.0°, ...");
+        assert_eq!(written(16, line), "lat 52.0°, lö...");
+        assert_eq!(written(19, line), line);
+        assert_eq!(written(80, line), line);
+    }
+
+    #[test]
+    fn lines_below_the_screen_are_dropped() {
+        let mut writer = Writer {
+            size: (10, 2),
+            cursor: (0, 0),
+            buffer: Vec::new(),
+        };
+        writer.write_line("first");
+        writer.cursor = (0, 2);
+        writer.write_line("third");
+        assert_eq!(writer.buffer, b"first");
+    }
+
+    fn rtcm(failed: bool) -> rxm::Rtcm {
+        rxm::Rtcm {
+            version: 2,
+            flags: if failed {
+                RtcmFlags::CrcFailed.into()
+            } else {
+                Default::default()
+            },
+            res1: [0; 2],
+            ref_stations: 0,
+            msg_type: 1077,
+        }
+    }
+
+    #[test]
+    fn crc_failures_are_smoothed() {
+        let mut stats = RtcmCrcStats::default();
+        stats.update(&rtcm(false));
+        assert_eq!(stats.crc_fail_rate(), 0.0);
+
+        // A single failure moves the average by the smoothing factor and then decays.
+        stats.update(&rtcm(true));
+        assert!((stats.crc_fail_rate() - 10.0).abs() < 1e-4);
+        stats.update(&rtcm(false));
+        assert!((stats.crc_fail_rate() - 9.0).abs() < 1e-4);
+        for _ in 0..100 {
+            stats.update(&rtcm(false));
+        }
+        assert!(stats.crc_fail_rate() < 0.01);
+    }
+
+    #[test]
+    fn crc_fail_rate_follows_the_failure_fraction() {
+        let mut stats = RtcmCrcStats::default();
+        for _ in 0..200 {
+            stats.update(&rtcm(true));
+        }
+        assert!((stats.crc_fail_rate() - 100.0).abs() < 0.01);
+
+        // Every other message failing settles around half of the messages.
+        for idx in 0..200 {
+            stats.update(&rtcm(idx % 2 == 0));
+        }
+        let rate = stats.crc_fail_rate();
+        assert!((45.0..55.0).contains(&rate), "{rate}");
+    }
+
+    /// A pseudo terminal standing in for a serial port. Returns the controlling side, which
+    /// acts as the receiver, and the path of the port.
+    fn pty() -> (File, String) {
+        unsafe {
+            let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+            assert!(
+                fd >= 0,
+                "failed to open pty: {}",
+                io::Error::last_os_error()
+            );
+            let master = File::from_raw_fd(fd);
+            assert_eq!(libc::grantpt(fd), 0);
+            assert_eq!(libc::unlockpt(fd), 0);
+            let path = CStr::from_ptr(libc::ptsname(fd))
+                .to_str()
+                .unwrap()
+                .to_string();
+            (master, path)
+        }
+    }
+
+    #[tokio::test]
+    async fn serial_frames_update_the_info() {
+        let (mut master, path) = pty();
+        let mut source = Source::serial(&path, 9600).unwrap();
+        assert!(source.is_connected());
+
+        let pvt = Ubx::Nav(Nav::Pvt(Pvt {
+            i_tow: 2000,
+            lat: 520_000_000,
+            lon: 45_000_000,
+            numsv: 7,
+            ..Default::default()
+        }))
+        .parse_to_vec()
+        .unwrap();
+        let eoe = Ubx::Nav(Nav::Eoe(Eoe { i_tow: 2000 }))
+            .parse_to_vec()
+            .unwrap();
+        master.write_all(&[&pvt[..], &eoe].concat()).unwrap();
+
+        let mut info = Info::new();
+        for expected in [&pvt, &eoe] {
+            let frame = source.next().await.unwrap().unwrap();
+            assert_eq!(&frame, expected);
+            for m in GpsMsg::parse_frame(&frame) {
+                info.handle_msg(&m.unwrap());
+            }
+        }
+        let shown = info.pvt.as_ref().unwrap();
+        assert_eq!(
+            (shown.lat, shown.lon, shown.numsv),
+            (520_000_000, 45_000_000, 7)
+        );
+        assert_eq!(info.last_itow, Some(2000));
+    }
+}
@@ -26,8 +26,11 @@ async fn run() -> Result<()> {
             .value_parser(Uri::from_str)
             .required(true),
         )
+        .args(gps::logging::args())
         .get_matches();
 
+    gps::logging::init_from_matches(&matches).context("failed to initialize logging")?;
+
     let connect = matches.get_one::<SocketAddr>("connect").unwrap();
     let uri = matches.get_one::<Uri>("ADDRESS").unwrap();
 
@@ -119,10 +122,6 @@ async fn run() -> Result<()> {
 }
 
 fn main() -> Result<()> {
-    env_logger::init_from_env(
-        env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "info"),
-    );
-
     tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()?
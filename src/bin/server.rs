@@ -1,153 +1,1348 @@
-use std::{net::SocketAddr, str::FromStr, time::Duration};
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    net::SocketAddr,
+    num::ParseIntError,
+    path::{Path, PathBuf},
+    pin::Pin,
+    result::Result as StdResult,
+    str::FromStr,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
 
 use anyhow::{anyhow, bail, Context as ErrorContext, Result};
-use clap::{arg, value_parser, ArgAction, ArgGroup, Command};
-use futures::{FutureExt, SinkExt, StreamExt};
+use clap::{arg, value_parser, ArgAction, ArgGroup, ArgMatches, Command};
+use futures::{FutureExt, Sink, SinkExt, Stream, StreamExt};
 use gps::{
     bluetooth::{BluetoothClient, BluetoothServer},
     connection::{ConnectionPool, OutgoingConnection},
-    msg::{self, GpsMsg},
-    parse::ParseData,
-    VecExt,
+    device::{self, DeviceWatcher},
+    logging::RotatingWriter,
+    msg::{
+        self,
+        ubx::{
+            ack::MessageId,
+            cfg::{BitLayer, Cfg, ValSet, Value},
+        },
+        GpsMsg, MessageDedup, Ubx,
+    },
+    parse::{self, ErrorOffset, ParseData, ParseIterator},
+    state::{SharedHealth, SharedState},
 };
 
 use log::{error, info, trace, warn};
+use serde::Deserialize;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
     net::TcpListener,
+    signal::unix::{signal, SignalKind},
+    sync::mpsc::{self, error::TrySendError, Sender},
+    time::{Instant, Sleep},
 };
-use tokio_serial::{DataBits, Parity, SerialStream, StopBits};
 
-fn find_message(b: &mut Vec<u8>) {
-    if b.len() < 2 {
-        return;
+const CONFIG_ACK_TIMEOUT: Duration = Duration::from_secs(2);
+const CONFIG_RETRIES: usize = 3;
+/// The maximum number of messages buffered for a device while its port is closed.
+const PENDING_LIMIT: usize = 64;
+/// The maximum number of messages waiting to be written to an open port.
+const WRITE_QUEUE_LIMIT: usize = 64;
+/// The maximum time navigation messages are held back waiting for the end of an epoch.
+const BATCH_TIMEOUT: Duration = Duration::from_millis(50);
+/// The number of recent navigation messages remembered when removing duplicates.
+const DEDUP_WINDOW: usize = 32;
+/// The time between attempts to open a serial port with `--wait-serial`.
+const WAIT_SERIAL_INTERVAL: Duration = Duration::from_secs(1);
+/// The minimum time between logs about waiting for a serial port.
+const WAIT_SERIAL_LOG_INTERVAL: Duration = Duration::from_secs(10);
+/// NTRIP is reported as active if RTCM corrections were recieved from a client within this time.
+const NTRIP_ACTIVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Read from the device until an acknowledgement for a CFG-VALSET message arrives.
+/// Returns whether the device acknowledged the message.
+async fn wait_for_config_ack(port: &mut DevicePort) -> Result<bool> {
+    loop {
+        let msg = match port.next().await {
+            Some(x) => x,
+            None => bail!("serial port closed"),
+        };
+        match msg {
+            Ok(GpsMsg::Ubx(Ubx::Ack(x))) => {
+                if let Some(acked) = x.result_for(MessageId::CFG_VALSET) {
+                    return Ok(acked);
+                }
+            }
+            Ok(x) => trace!("message from device during configuration {:?}", x),
+            Err(e) => trace!("failed to parse message from device: {:?}", e),
+        }
+    }
+}
+
+/// Write the configuration values from a config file directly to the device, waiting for
+/// the device to acknowledge each chunk before continuing.
+async fn apply_config(port: &mut DevicePort, writer: &mut DeviceWriter, path: &Path) -> Result<()> {
+    info!("reading config file");
+    let file = tokio::fs::read(path)
+        .await
+        .context("failed to read config file")?;
+
+    let keys: Vec<Value> = serde_json::from_slice(&file).context("failed to parse config file")?;
+
+    let mut i = 0;
+    for v in keys.chunks(64) {
+        i += v.len();
+        info!("writing up to `{i}` configuration values");
+        let msg = Ubx::Cfg(Cfg::ValSet(ValSet {
+            version: 0,
+            res1: [0; 2],
+            values: v.into(),
+            layers: BitLayer::Ram.into(),
+        }));
+        let bytes = msg.parse_to_vec().unwrap();
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            writer
+                .write_all(&bytes)
+                .await
+                .context("error writing to device")?;
+            writer.flush().await.context("error writing to device")?;
+
+            match tokio::time::timeout(CONFIG_ACK_TIMEOUT, wait_for_config_ack(port)).await {
+                Ok(Ok(true)) => {
+                    info!("recieved acknowledgement");
+                    break;
+                }
+                Ok(Ok(false)) => bail!("device did not acknowledge config"),
+                Ok(Err(e)) => return Err(e),
+                Err(_) if attempt < CONFIG_RETRIES => {
+                    warn!("timeout waiting for acknowledgement, retrying");
+                }
+                Err(_) => bail!("timeout waiting for acknowledgement"),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The read half of a serial port.
+type DevicePort = ParseIterator<Box<dyn AsyncRead + Unpin + Send>>;
+/// The write half of a serial port.
+type DeviceWriter = Box<dyn AsyncWrite + Unpin + Send>;
+
+/// Open a serial port, a path of `-` uses stdin and stdout as the device.
+fn open_port(path: &str, baud: u32) -> Result<(DevicePort, DeviceWriter)> {
+    if path == "-" {
+        return Ok((
+            ParseIterator::new(Box::new(tokio::io::stdin())),
+            Box::new(tokio::io::stdout()),
+        ));
+    }
+
+    let port = device::open_serial(path, baud).context("failed to open serial port")?;
+    let (read, write) = tokio::io::split(port);
+    Ok((ParseIterator::new(Box::new(read)), Box::new(write)))
+}
+
+/// Messages waiting to be written to a device, rtcm messages are written before any other
+/// message as corrections are only useful to the device for a short time.
+#[derive(Default)]
+struct PriorityMsgQueue {
+    rtcm: VecDeque<Vec<u8>>,
+    other: VecDeque<Vec<u8>>,
+}
+
+impl PriorityMsgQueue {
+    fn len(&self) -> usize {
+        self.rtcm.len() + self.other.len()
+    }
+
+    /// Queue the messages in a frame. Rtcm messages are taken out of the frame, so corrections
+    /// skip ahead even if the frame starts with an other message.
+    ///
+    /// Once the queue holds [`WRITE_QUEUE_LIMIT`] messages the oldest rtcm message is dropped,
+    /// or the oldest other message if no rtcm message is waiting. Returns whether a message was
+    /// dropped.
+    fn push(&mut self, frame: Vec<u8>) -> bool {
+        let mut other = Vec::new();
+        let mut rest = frame.as_slice();
+        while !rest.is_empty() {
+            let usage = GpsMsg::message_usage(rest);
+            // Bytes which are not a complete message are kept up to the next prefix.
+            let len = usage
+                .or_else(|| GpsMsg::find_prefix(&rest[1..]).map(|x| x + 1))
+                .unwrap_or(rest.len());
+            let (msg, tail) = rest.split_at(len);
+            if usage.is_some() && msg::Rtcm::contains_prefix(msg) {
+                self.rtcm.push_back(msg.to_vec());
+            } else {
+                other.extend_from_slice(msg);
+            }
+            rest = tail;
+        }
+        if !other.is_empty() {
+            self.other.push_back(other);
+        }
+
+        let mut dropped = false;
+        while self.len() > WRITE_QUEUE_LIMIT {
+            if self.rtcm.pop_front().is_none() {
+                self.other.pop_front();
+            }
+            dropped = true;
+        }
+        dropped
+    }
+
+    /// Take the next message to write, rtcm messages are taken before any other message.
+    fn pop(&mut self) -> Option<Vec<u8>> {
+        self.rtcm.pop_front().or_else(|| self.other.pop_front())
+    }
+}
+
+/// Writes frames to a serial port.
+struct DeviceSink {
+    writer: DeviceWriter,
+    /// Bytes accepted by the sink which have not been written to the port yet.
+    buffer: Vec<u8>,
+}
+
+impl DeviceSink {
+    fn new(writer: DeviceWriter) -> Self {
+        DeviceSink {
+            writer,
+            buffer: Vec::new(),
+        }
+    }
+
+    fn poll_write_buffer(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        while !self.buffer.is_empty() {
+            let n = ready!(Pin::new(&mut self.writer).poll_write(cx, &self.buffer))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.buffer.drain(..n);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Sink<Vec<u8>> for DeviceSink {
+    type Error = anyhow::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        self.get_mut().poll_write_buffer(cx).map_err(Into::into)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Vec<u8>) -> Result<()> {
+        self.get_mut().buffer.extend_from_slice(&item);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_buffer(cx))?;
+        Pin::new(&mut this.writer)
+            .poll_flush(cx)
+            .map_err(Into::into)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_buffer(cx))?;
+        Pin::new(&mut this.writer)
+            .poll_shutdown(cx)
+            .map_err(Into::into)
+    }
+}
+
+impl Sink<GpsMsg> for DeviceSink {
+    type Error = anyhow::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        Sink::<Vec<u8>>::poll_ready(self, cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: GpsMsg) -> Result<()> {
+        self.start_send(item.parse_to_vec()?)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        Sink::<Vec<u8>>::poll_flush(self, cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        Sink::<Vec<u8>>::poll_close(self, cx)
+    }
+}
+
+/// A sink which limits the rate at which rtcm messages are written to the inner sink with a
+/// token bucket, so a fast stream of corrections doesn't overwhelm the device.
+///
+/// Every rtcm message takes a token, other messages and raw frames pass through without one.
+/// The message isn't known yet in `poll_ready`, so any message waits while there is no token.
+struct RtcmRateLimiter<S> {
+    inner: S,
+    rate: f32,
+    burst: f32,
+    tokens: f32,
+    last: Instant,
+    sleep: Pin<Box<Sleep>>,
+}
+
+impl<S> RtcmRateLimiter<S> {
+    /// Allow `max_messages_per_second` on average with up to `burst` messages at once.
+    fn new(inner: S, max_messages_per_second: f32, burst: usize) -> Self {
+        let last = Instant::now();
+        RtcmRateLimiter {
+            inner,
+            rate: max_messages_per_second,
+            burst: burst as f32,
+            tokens: burst as f32,
+            last,
+            sleep: Box::pin(tokio::time::sleep_until(last)),
+        }
+    }
+
+    /// Add the tokens for the time since the last refill, returns how long to wait for the next
+    /// token if there is none.
+    fn refill(&mut self, now: Instant) -> StdResult<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f32();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last = now;
+        if self.tokens >= 1.0 {
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f32((1.0 - self.tokens) / self.rate))
+        }
+    }
+
+    /// The number of rtcm messages kept waiting, older messages are dropped as corrections are
+    /// only useful for a short time.
+    fn queue_limit(&self) -> usize {
+        self.burst as usize
+    }
+}
+
+impl<S: Sink<Vec<u8>, Error = anyhow::Error> + Unpin> Sink<GpsMsg> for RtcmRateLimiter<S> {
+    type Error = anyhow::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        while let Err(wait) = this.refill(Instant::now()) {
+            this.sleep.as_mut().reset(Instant::now() + wait);
+            ready!(this.sleep.poll_unpin(cx));
+        }
+        this.inner.poll_ready_unpin(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: GpsMsg) -> Result<()> {
+        let this = self.get_mut();
+        if let GpsMsg::Rtcm3(_) = item {
+            this.tokens -= 1.0;
+        }
+        this.inner.start_send_unpin(item.parse_to_vec()?)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        self.get_mut().inner.poll_flush_unpin(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        self.get_mut().inner.poll_close_unpin(cx)
+    }
+}
+
+impl<S: Sink<Vec<u8>, Error = anyhow::Error> + Unpin> Sink<Vec<u8>> for RtcmRateLimiter<S> {
+    type Error = anyhow::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        self.get_mut().inner.poll_ready_unpin(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Vec<u8>) -> Result<()> {
+        self.get_mut().inner.start_send_unpin(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        self.get_mut().inner.poll_flush_unpin(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        self.get_mut().inner.poll_close_unpin(cx)
+    }
+}
+
+fn parse_rate(v: &str) -> StdResult<f32, String> {
+    match v.parse::<f32>() {
+        Ok(x) if x.is_finite() && x > 0.0 => Ok(x),
+        _ => Err("expected a positive number".to_string()),
+    }
+}
+
+/// Try to open a serial port every second until it succeeds or the timeout passes, for devices
+/// which are not yet available when the server starts.
+async fn wait_for_port(
+    path: &str,
+    baud: u32,
+    timeout: Option<Duration>,
+) -> Result<(DevicePort, DeviceWriter)> {
+    let deadline = timeout.map(|x| Instant::now() + x);
+    let mut last_log = None;
+    loop {
+        let e = match open_port(path, baud) {
+            Ok(x) => return Ok(x),
+            Err(e) => e,
+        };
+        if deadline.is_some_and(|x| Instant::now() >= x) {
+            return Err(e.context("timed out waiting for serial port"));
+        }
+        if log_due(&mut last_log) {
+            info!("waiting for serial port `{path}`: {e}");
+        }
+        tokio::time::sleep(WAIT_SERIAL_INTERVAL).await;
+    }
+}
+
+/// Whether waiting for a serial port should be logged again, at most once every
+/// [`WAIT_SERIAL_LOG_INTERVAL`] so a missing device doesn't flood the log.
+fn log_due(last_log: &mut Option<Instant>) -> bool {
+    let now = Instant::now();
+    if last_log.is_none_or(|x| now - x >= WAIT_SERIAL_LOG_INTERVAL) {
+        *last_log = Some(now);
+        true
+    } else {
+        false
+    }
+}
+
+/// Spawn a task which writes all messages send over the returned channel to the device.
+/// The task quits when the channel is closed or when writing to the device fails.
+///
+/// The channel is read while writing, so messages wait in a [`PriorityMsgQueue`] which drops
+/// the oldest rtcm message once full.
+fn spawn_writer(
+    name: String,
+    writer: DeviceWriter,
+    rtcm_rate_limit: Option<f32>,
+) -> Sender<Vec<u8>> {
+    let (sender, recv) = mpsc::channel::<Vec<u8>>(WRITE_QUEUE_LIMIT);
+    let sink = DeviceSink::new(writer);
+    match rtcm_rate_limit {
+        Some(x) => {
+            let limiter = RtcmRateLimiter::new(sink, x, (x.ceil() as usize).max(1));
+            let queue_limit = limiter.queue_limit();
+            tokio::spawn(write_device(name, recv, limiter, Some(queue_limit)))
+        }
+        None => tokio::spawn(write_device(name, recv, sink, None)),
+    };
+    sender
+}
+
+/// Write the messages recieved from the channel to the sink until either fails.
+///
+/// Rtcm messages are send to the sink as [`GpsMsg`], so a rate limiting sink counts every
+/// message. At most `rtcm_queue_limit` rtcm messages are kept waiting, older messages are dropped.
+async fn write_device<S>(
+    name: String,
+    mut recv: mpsc::Receiver<Vec<u8>>,
+    mut sink: S,
+    rtcm_queue_limit: Option<usize>,
+) where
+    S: Sink<Vec<u8>, Error = anyhow::Error> + Sink<GpsMsg, Error = anyhow::Error> + Unpin,
+{
+    let mut queue = PriorityMsgQueue::default();
+    let mut closed = false;
+    let push = |queue: &mut PriorityMsgQueue, x| {
+        if queue.push(x) {
+            warn!("too many messages waiting for device `{name}`, dropping message");
+        }
+    };
+    loop {
+        // Take all messages which are already waiting so rtcm messages can skip ahead.
+        while let Ok(x) = recv.try_recv() {
+            push(&mut queue, x);
+        }
+        if let Some(limit) = rtcm_queue_limit {
+            while queue.rtcm.len() > limit {
+                warn!("rtcm messages for device `{name}` exceed the rate limit, dropping message");
+                queue.rtcm.pop_front();
+            }
+        }
+        let Some(x) = queue.pop() else {
+            match recv.recv().await {
+                Some(x) => push(&mut queue, x),
+                None => break,
+            }
+            continue;
+        };
+
+        let mut write = Box::pin(
+            async {
+                if !msg::Rtcm::contains_prefix(&x) {
+                    return sink.send(x).await;
+                }
+                match GpsMsg::parse_read(&x) {
+                    Ok((_, msg)) => sink.send(msg).await,
+                    Err(e) => {
+                        warn!("dropping invalid rtcm message for device `{name}`: {e}");
+                        Ok(())
+                    }
+                }
+            }
+            .fuse(),
+        );
+        // Keep taking messages while writing so the channel doesn't fill up behind a slow
+        // port, the queue decides which messages are dropped. Once the channel is closed it
+        // would return immediately, so only the write is awaited.
+        let res = if closed {
+            write.await
+        } else {
+            loop {
+                futures::select! {
+                    res = write => break res,
+                    x = recv.recv().fuse() => match x {
+                        Some(x) => push(&mut queue, x),
+                        None => {
+                            closed = true;
+                            break write.await;
+                        }
+                    },
+                }
+            }
+        };
+        if let Err(e) = res {
+            error!("error writing to device `{name}`: {e}");
+            break;
+        }
+    }
+}
+
+/// The protocol of messages matched by a route.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Protocol {
+    Ubx,
+    Rtcm,
+    Nmea,
+    Any,
+}
+
+impl Protocol {
+    fn matches(self, frame: &[u8]) -> bool {
+        match self {
+            Protocol::Ubx => Ubx::contains_prefix(frame),
+            Protocol::Rtcm => msg::Rtcm::contains_prefix(frame),
+            Protocol::Nmea => msg::Nmea::contains_prefix(frame),
+            Protocol::Any => true,
+        }
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "ubx" => Ok(Protocol::Ubx),
+            "rtcm" => Ok(Protocol::Rtcm),
+            "nmea" => Ok(Protocol::Nmea),
+            "any" => Ok(Protocol::Any),
+            x => bail!("unknown protocol `{x}`, expected one of `ubx`, `rtcm`, `nmea` or `any`"),
+        }
+    }
+}
+
+/// Narrows the messages of a protocol matched by a route.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RouteFilter {
+    /// A ubx message class, optionally only a single message id of that class.
+    Ubx { class: u8, id: Option<u8> },
+    /// A single rtcm message type.
+    Rtcm(u16),
+    /// All rtcm multiple signal messages, message types 1071 to 1137.
+    Msm,
+}
+
+impl RouteFilter {
+    /// Parse a single filter for the given protocol. Ubx filters are a class, like `0x02`, or a
+    /// class and id, like `0x02.0x15`. Rtcm filters are a message type, like `4072`, or `msm`.
+    fn parse(filter: &str, protocol: Protocol) -> Result<Self> {
+        fn number<T: FromStr>(
+            x: &str,
+            from_hex: fn(&str) -> StdResult<T, ParseIntError>,
+        ) -> Option<T> {
+            match x.strip_prefix("0x") {
+                Some(x) => from_hex(x).ok(),
+                None => x.parse().ok(),
+            }
+        }
+
+        let filter = filter.trim();
+        match protocol {
+            Protocol::Ubx => {
+                let (class, id) = match filter.split_once('.') {
+                    Some((class, id)) => (class, Some(id)),
+                    None => (filter, None),
+                };
+                let parse = |x: &str| {
+                    number(x.trim(), |x| u8::from_str_radix(x, 16))
+                        .ok_or_else(|| anyhow!("invalid ubx filter `{filter}`"))
+                };
+                Ok(RouteFilter::Ubx {
+                    class: parse(class)?,
+                    id: id.map(parse).transpose()?,
+                })
+            }
+            Protocol::Rtcm if filter == "msm" => Ok(RouteFilter::Msm),
+            Protocol::Rtcm => number(filter, |x| u16::from_str_radix(x, 16))
+                .map(RouteFilter::Rtcm)
+                .ok_or_else(|| anyhow!("invalid rtcm filter `{filter}`")),
+            Protocol::Nmea | Protocol::Any => bail!("protocol does not support filters"),
+        }
+    }
+
+    fn matches(self, frame: &[u8]) -> bool {
+        match self {
+            RouteFilter::Ubx { class, id } => MessageId::from_frame(frame)
+                .is_some_and(|x| x.class == class && id.is_none_or(|id| x.id == id)),
+            RouteFilter::Rtcm(kind) => msg::Rtcm::kind_from_frame(frame) == Some(kind),
+            RouteFilter::Msm => {
+                msg::Rtcm::kind_from_frame(frame).is_some_and(|x| (1071..=1137).contains(&x))
+            }
+        }
+    }
+}
+
+/// A rule which writes messages from one device directly to an other device.
+#[derive(Debug, PartialEq, Eq)]
+struct Route {
+    from: usize,
+    protocol: Protocol,
+    /// The route matches a message if any of the filters do, or if there are no filters.
+    filters: Vec<RouteFilter>,
+    to: usize,
+}
+
+impl Route {
+    /// Parse a route of the form `FROM:PROTOCOL[:FILTER,...]->TO` where `FROM` and `TO` are the
+    /// names of devices, for example `base:rtcm:4072,msm->rover`.
+    fn parse(route: &str, devices: &[&str]) -> Result<Self> {
+        let (from, to) = route
+            .split_once("->")
+            .ok_or_else(|| anyhow!("route `{route}` is missing `->`"))?;
+        let (from, protocol) = from
+            .split_once(':')
+            .ok_or_else(|| anyhow!("route `{route}` is missing a protocol"))?;
+        let (protocol, filters) = match protocol.split_once(':') {
+            Some((protocol, filters)) => (protocol, Some(filters)),
+            None => (protocol, None),
+        };
+
+        let find = |name: &str| {
+            devices
+                .iter()
+                .position(|x| *x == name.trim())
+                .ok_or_else(|| anyhow!("route `{route}` refers to unknown device `{name}`"))
+        };
+        let from = find(from)?;
+        let to = find(to)?;
+        if from == to {
+            bail!("route `{route}` routes a device to itself");
+        }
+
+        let protocol = protocol.trim().parse()?;
+        let filters = filters
+            .into_iter()
+            .flat_map(|x| x.split(','))
+            .map(|x| RouteFilter::parse(x, protocol))
+            .collect::<Result<_>>()
+            .with_context(|| format!("invalid filter in route `{route}`"))?;
+
+        Ok(Route {
+            from,
+            protocol,
+            filters,
+            to,
+        })
+    }
+
+    fn matches(&self, frame: &[u8]) -> bool {
+        self.protocol.matches(frame)
+            && (self.filters.is_empty() || self.filters.iter().any(|x| x.matches(frame)))
+    }
+}
+
+/// A set of messages by their ubx class and id, nmea talker id or rtcm message type.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct MessageSet {
+    ubx: Vec<(u8, u8)>,
+    nmea: Vec<String>,
+    rtcm: Vec<u16>,
+}
+
+impl MessageSet {
+    fn contains(&self, frame: &[u8]) -> bool {
+        if let Some(id) = MessageId::from_frame(frame) {
+            self.ubx.contains(&(id.class, id.id))
+        } else if let Some(kind) = msg::Rtcm::kind_from_frame(frame) {
+            self.rtcm.contains(&kind)
+        } else if msg::Nmea::contains_prefix(frame) {
+            // The talker id follows the `$`, i.e. `GN` in `$GNGGA`.
+            frame
+                .get(1..3)
+                .is_some_and(|x| self.nmea.iter().any(|t| t.as_bytes() == x))
+        } else {
+            false
+        }
+    }
+}
+
+/// Which messages from the devices are forwarded to clients, read from `--forward-config`.
+///
+/// A message is forwarded if it is in the allow set, or if there is no allow set, and it is not in
+/// the deny set. For example `{"allow": {"ubx": [[1, 7]]}}` only forwards NAV-PVT and
+/// `{"deny": {"nmea": ["GP"], "rtcm": [1230]}}` forwards everything except GPS NMEA sentences and
+/// RTCM 1230.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ForwardPolicy {
+    allow: Option<MessageSet>,
+    deny: MessageSet,
+}
+
+impl ForwardPolicy {
+    fn load(path: &Path) -> Result<Self> {
+        let file = std::fs::read(path)
+            .with_context(|| format!("failed to read forward config `{}`", path.display()))?;
+        // An empty file forwards everything.
+        if file.iter().all(|x| x.is_ascii_whitespace()) {
+            return Ok(ForwardPolicy::default());
+        }
+        serde_json::from_slice(&file)
+            .with_context(|| format!("failed to parse forward config `{}`", path.display()))
+    }
+
+    fn allows(&self, frame: &[u8]) -> bool {
+        self.allow.as_ref().is_none_or(|x| x.contains(frame)) && !self.deny.contains(frame)
+    }
+}
+
+/// Whether a frame read from a device is send to the clients of the device.
+fn forwarded(frame: &[u8], rtcm_only: bool, forward_policy: &ForwardPolicy) -> bool {
+    (!rtcm_only || Protocol::Rtcm.matches(frame)) && forward_policy.allows(frame)
+}
+
+/// Navigation messages of the current epoch which have not been broadcast yet.
+#[derive(Default)]
+struct EpochBatch {
+    buffer: Vec<u8>,
+    count: usize,
+    deadline: Option<Instant>,
+}
+
+impl EpochBatch {
+    fn push(&mut self, frame: &[u8]) {
+        self.buffer.extend_from_slice(frame);
+        self.count += 1;
+        self.deadline
+            .get_or_insert_with(|| Instant::now() + BATCH_TIMEOUT);
+    }
+
+    fn take(&mut self) -> Vec<u8> {
+        trace!("sending batch of {} messages", self.count);
+        self.count = 0;
+        self.deadline = None;
+        std::mem::take(&mut self.buffer)
+    }
+}
+
+/// A serial device served by the server together with the clients connected to it.
+struct Device {
+    name: String,
+    path: String,
+    baud: u32,
+    port: Option<DevicePort>,
+    writer: Option<Sender<Vec<u8>>>,
+    /// Messages for the device recieved while the port was closed.
+    pending: VecDeque<Vec<u8>>,
+    connections: ConnectionPool,
+    /// Reset the port if no message was read from the device within this duration.
+    watchdog: Option<Duration>,
+    last_read: Instant,
+    /// Navigation messages are collected into a single frame per epoch if set.
+    batch: Option<EpochBatch>,
+    dedup: Option<MessageDedup>,
+    /// Watches for the serial port being removed and added again.
+    watcher: Option<DeviceWatcher>,
+    /// The file the raw messages from the device are written to.
+    record: Option<RotatingWriter>,
+    /// When the device was first opened.
+    started: Instant,
+    /// When RTCM corrections were last recieved from a client.
+    last_rtcm: Option<Instant>,
+    /// The maximum number of rtcm messages per second written to the device.
+    rtcm_rate_limit: Option<f32>,
+}
+
+enum DeviceEvent {
+    /// A message was read from the serial port.
+    Device(Option<parse::Result<GpsMsg>>),
+    /// A message was recieved from one of the connections of the device.
+    Connection(Vec<u8>),
+    /// A message was recieved from a client using a different transport, like bluetooth or
+    /// websockets.
+    Transport(Vec<u8>),
+    /// No message was read from the device within the watchdog duration.
+    Timeout,
+    /// The end of the epoch was not recieved within the batch timeout.
+    BatchTimeout,
+    /// A device file was added to or removed from the directory of the serial port.
+    Hotplug(Option<device::DeviceEvent>),
+}
+
+impl Device {
+    fn close_port(&mut self) {
+        self.port.take();
+        self.writer.take();
     }
-    if GpsMsg::contains_prefix(b) {
-        return;
+
+    async fn reset_port(&mut self) -> Result<()> {
+        self.close_port();
+        tokio::time::sleep(Duration::from_secs_f32(0.5)).await;
+        self.last_read = Instant::now();
+        let (port, writer) = open_port(&self.path, self.baud)?;
+        self.port = Some(port);
+        self.writer = Some(spawn_writer(
+            self.name.clone(),
+            writer,
+            self.rtcm_rate_limit,
+        ));
+        if !self.pending.is_empty() {
+            info!(
+                "writing {} buffered messages to device `{}`",
+                self.pending.len(),
+                self.name
+            );
+            while let Some(x) = self.pending.pop_front() {
+                self.write(x);
+            }
+        }
+        Ok(())
     }
-    let mut idx = 1;
-    while b.len() > idx {
-        if GpsMsg::contains_prefix(&b[idx..]) {
-            warn!("skipped over {idx} bytes");
-            b.shift(idx);
-            return;
+
+    /// Reopen the port after no messages were read within the watchdog duration.
+    async fn watchdog_reset(&mut self) {
+        warn!(
+            "no messages from device `{}` within watchdog time, resetting port",
+            self.name
+        );
+        if let Err(e) = self.reset_port().await {
+            error!("failed to reopen port of device `{}`: {:?}", self.name, e);
+        }
+    }
+
+    /// Queue a message to be written to the device.
+    /// If the port is closed the message is buffered until the port is reopened.
+    fn write(&mut self, x: Vec<u8>) {
+        let x = match self.writer {
+            Some(ref w) => match w.try_send(x) {
+                Ok(()) => return,
+                Err(TrySendError::Full(_)) => {
+                    warn!(
+                        "writer of device `{}` is behind, dropping message",
+                        self.name
+                    );
+                    return;
+                }
+                Err(TrySendError::Closed(x)) => {
+                    // The writer quit because writing to the device failed.
+                    self.writer = None;
+                    x
+                }
+            },
+            None => x,
+        };
+        if self.pending.len() < PENDING_LIMIT {
+            self.pending.push_back(x);
+        } else {
+            warn!("port of device `{}` is closed, dropping message", self.name);
+        }
+    }
+
+    async fn next_event(&mut self) -> DeviceEvent {
+        let watchdog = self.watchdog.map(|x| self.last_read + x);
+        let read = self.port.as_mut().map(|x| x.next());
+        let mut device_future = Box::pin(async move {
+            let read = match read {
+                Some(x) => x,
+                None => {
+                    // The port is closed, only the watchdog can reopen it.
+                    match watchdog {
+                        Some(deadline) => tokio::time::sleep_until(deadline).await,
+                        None => futures::future::pending().await,
+                    }
+                    return DeviceEvent::Timeout;
+                }
+            };
+            if let Some(deadline) = watchdog {
+                tokio::time::timeout_at(deadline, read)
+                    .await
+                    .map_or(DeviceEvent::Timeout, DeviceEvent::Device)
+            } else {
+                DeviceEvent::Device(read.await)
+            }
+        })
+        .fuse();
+        let mut connection_future = self.connections.next();
+        let batch_deadline = self.batch.as_ref().and_then(|x| x.deadline);
+        let mut batch_future = Box::pin(async move {
+            match batch_deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => futures::future::pending().await,
+            }
+        })
+        .fuse();
+        let watcher = self.watcher.as_mut();
+        let mut watcher_future = Box::pin(async move {
+            match watcher {
+                Some(x) => x.next().await,
+                None => futures::future::pending().await,
+            }
+        })
+        .fuse();
+        futures::select! {
+            x = device_future => x,
+            x = connection_future => DeviceEvent::Connection(x.unwrap()),
+            _ = batch_future => DeviceEvent::BatchTimeout,
+            x = watcher_future => DeviceEvent::Hotplug(x),
         }
-        idx += 1;
     }
-    b.clear();
 }
 
+/// Handle a message from a client, `default` is the device which recieves the message if
+/// it is not a server message.
+///
+/// Returns the reply to a server request, which should only be send to the client the request
+/// came from.
 async fn handle_incomming(
-    port_path: &String,
-    port_baud: u32,
-    port: &mut Option<SerialStream>,
+    devices: &mut [Device],
+    default: usize,
     x: Vec<u8>,
-) -> Result<()> {
+) -> Result<Option<Vec<u8>>> {
     if let Ok((_, x)) = msg::Server::parse_read(&x) {
+        let device = match devices.get_mut(x.device as usize) {
+            Some(x) => x,
+            None => {
+                warn!("server message for unknown device `{}`", x.device);
+                return Ok(None);
+            }
+        };
         match x.msg {
             msg::server::ServerMsg::Quit => {
                 info!("quiting");
-                return Ok(());
+                return Ok(None);
             }
             msg::server::ServerMsg::ResetPort => {
-                port.take();
-
-                tokio::time::sleep(Duration::from_secs_f32(0.5)).await;
-
-                let port_builder = tokio_serial::new(port_path, port_baud)
-                    .data_bits(DataBits::Eight)
-                    .parity(Parity::None)
-                    .stop_bits(StopBits::One)
-                    .timeout(Duration::from_secs(1));
-
-                *port =
-                    Some(SerialStream::open(&port_builder).context("failed to open serial port")?);
+                info!("resetting port of device `{}`", device.name);
+                if let Err(e) = device.reset_port().await {
+                    error!("failed to reopen port of device `{}`: {:?}", device.name, e);
+                }
+            }
+            msg::server::ServerMsg::Stats => {
+                let stats = device
+                    .connections
+                    .connections()
+                    .map(|x| x.stats())
+                    .collect();
+                let bytes = msg::Server {
+                    msg: msg::server::ServerMsg::StatsResponse(stats),
+                    device: x.device,
+                }
+                .parse_to_vec()?;
+                return Ok(Some(bytes));
+            }
+            msg::server::ServerMsg::StatusRequest => {
+                let bytes = msg::Server {
+                    msg: msg::server::ServerMsg::Status {
+                        device: device.path.clone(),
+                        baud: device.baud,
+                        clients: device.connections.connections().count() as u32,
+                        ntrip: device
+                            .last_rtcm
+                            .is_some_and(|x| x.elapsed() < NTRIP_ACTIVE_TIMEOUT),
+                        uptime_secs: device.started.elapsed().as_secs(),
+                    },
+                    device: x.device,
+                }
+                .parse_to_vec()?;
+                return Ok(Some(bytes));
             }
+            msg::server::ServerMsg::StatsResponse(_) | msg::server::ServerMsg::Status { .. } => {}
         }
     } else {
-        port.as_mut()
-            .unwrap()
-            .write_all(&x)
-            .await
-            .context("error writing to device")?;
-        port.as_mut()
-            .unwrap()
-            .flush()
-            .await
-            .context("error writing to device")?;
+        if msg::Rtcm::contains_prefix(&x) {
+            devices[default].last_rtcm = Some(Instant::now());
+        }
+        devices[default].write(x);
     }
 
-    Ok(())
+    Ok(None)
+}
+
+#[cfg(feature = "mdns")]
+fn advertise(devices: &[Device], port: u16) -> Result<Vec<gps::mdns::Advertisement>> {
+    devices
+        .iter()
+        .enumerate()
+        .map(|(idx, x)| gps::mdns::Advertisement::new(&x.name, port + idx as u16))
+        .collect()
+}
+
+#[cfg(not(feature = "mdns"))]
+fn advertise(_: &[Device], _: u16) -> Result<Vec<()>> {
+    bail!("the server was built without mdns support, enable the `mdns` feature")
+}
+
+/// A pool of clients using a transport other than plain tcp.
+trait Transport: Stream<Item = Vec<u8>> + Sink<Vec<u8>, Error = ()> + Unpin {}
+
+impl<T: Stream<Item = Vec<u8>> + Sink<Vec<u8>, Error = ()> + Unpin> Transport for T {}
+
+#[cfg(feature = "websocket")]
+async fn serve_websocket(address: &str, port: u16) -> Result<Box<dyn Transport>> {
+    let listener = TcpListener::bind((address, port))
+        .await
+        .context("failed to create websocket server")?;
+    info!("serving websockets on port {port}");
+    Ok(Box::new(gps::connection::WebSocketPool::new(listener)))
+}
+
+#[cfg(not(feature = "websocket"))]
+async fn serve_websocket(_: &str, _: u16) -> Result<Box<dyn Transport>> {
+    bail!("the server was built without websocket support, enable the `websocket` feature")
+}
+
+#[cfg(feature = "rest")]
+fn serve_rest(address: &str, port: u16) -> Result<SharedState> {
+    let addr = SocketAddr::new(address.parse().context("invalid rest api address")?, port);
+    let state = SharedState::default();
+    let server = gps::rest::bind(addr, state.clone())?;
+    info!("serving rest api on port {port}");
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            error!("{:?}", e);
+        }
+    });
+    Ok(state)
 }
 
-async fn run() -> Result<()> {
-    let matches = Command::new("gps server")
+#[cfg(not(feature = "rest"))]
+fn serve_rest(_: &str, _: u16) -> Result<SharedState> {
+    bail!("the server was built without rest api support, enable the `rest` feature")
+}
+
+#[cfg(feature = "rest")]
+fn serve_health(address: &str, port: u16) -> Result<SharedHealth> {
+    let addr = SocketAddr::new(
+        address.parse().context("invalid health check address")?,
+        port,
+    );
+    let health = SharedHealth::default();
+    let server = gps::rest::bind_health(addr, health.clone())?;
+    info!("serving health check on port {port}");
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            error!("{:?}", e);
+        }
+    });
+    Ok(health)
+}
+
+#[cfg(not(feature = "rest"))]
+fn serve_health(_: &str, _: u16) -> Result<SharedHealth> {
+    bail!("the server was built without health check support, enable the `rest` feature")
+}
+
+fn command() -> Command<'static> {
+    Command::new("gps server")
         .version("0.1")
         .arg(
             arg!(
-                -s --serial <PATH> "Set the serial port"
+                -s --serial <PATH> "Set the serial port, can be given multiple times as `[NAME=]PATH`, `-` uses stdin and stdout"
+            )
+            .required(false)
+            .action(ArgAction::Append)
+            .default_value("/dev/ttyACM0"),
+        )
+        .arg(
+            arg!(
+                -r --baud <BOUD> "Set the baud rate for each serial port, the last value is used for the remaining ports"
+            )
+            .required(false)
+            .requires("serial")
+            .action(ArgAction::Append)
+            .default_value("9600")
+            .value_parser(value_parser!(u32)),
+        )
+        .arg(
+            arg!(
+                -p --port <PORT> "Set the port to host the server on, additional devices are hosted on the following ports"
+            )
+            .required(false)
+            .default_value("9165")
+            .value_parser(value_parser!(u16)),
+        )
+        .arg(
+            arg!(
+                -c --connect <ADDRESS> "Connect to an other server."
+            )
+            .required(false),
+        )
+        .arg(
+            arg!(
+                [address] "The address to host the server on"
+            )
+            .required(false)
+            .default_value("0.0.0.0"),
+        )
+        .arg(
+            arg!(
+                -b --bluetooth "enable the bluetooth server"
+            )
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                -t --bluetooth_client "enable the bluetooth client"
+            )
+            .action(ArgAction::SetTrue),
+        )
+        .group(ArgGroup::new("bluetooth-flags").args(&["bluetooth", "bluetooth_client"]))
+        .arg(
+            arg!(
+                -D --deamon "run the server as a deamon"
+            )
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --pidfile <PATH> "Write the pid of the deamon to the given file"
+            )
+            .required(false)
+            .requires("deamon")
+            .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(
+                --config <PATH> "Apply a configuration file to the device on startup, can be given once for each serial port"
+            )
+            .required(false)
+            .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(
+                --"config-optional" "continue starting the server if applying the configuration fails"
+            )
+            .requires("config")
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --record <PATH> "Write the raw messages from the device to a file, can be given once for each serial port"
             )
             .required(false)
-            .default_value("/dev/ttyACM0"),
+            .action(ArgAction::Append)
+            .value_parser(value_parser!(PathBuf)),
         )
         .arg(
             arg!(
-                -r --baud <BOUD> "Set the baud rate for the serial port"
+                --"record-max-size" <BYTES> "The size in bytes after which a recording is rotated"
             )
             .required(false)
-            .requires("serial")
-            .default_value("9600")
-            .value_parser(value_parser!(u32)),
+            .requires("record")
+            .value_parser(value_parser!(u64)),
         )
         .arg(
             arg!(
-                -p --port <PORT> "Set the port to host the server on"
+                --"record-keep" <COUNT> "The number of rotated recordings which are kept"
             )
             .required(false)
-            .default_value("9165")
+            .requires("record-max-size")
+            .default_value("5")
+            .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            arg!(
+                --route <ROUTE> "Write messages from one device directly to an other, given as `FROM:PROTOCOL[:FILTER,...]->TO`, like `base:rtcm:4072,msm->rover`"
+            )
+            .required(false)
+            .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(
+                --mdns "Advertise the server on the local network over mdns as `_gps-server._tcp` and `_ubxgps._tcp`"
+            )
+            .alias("advertise")
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --rest "Serve the latest state of the first device over a http api"
+            )
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"rest-port" <PORT> "The port to host the http api on"
+            )
+            .required(false)
+            .requires("rest")
+            .default_value("8080")
             .value_parser(value_parser!(u16)),
         )
         .arg(
             arg!(
-                -c --connect <ADDRESS> "Connect to an other server."
+                --health "Serve a health check at `/health` over http"
             )
-            .required(false),
+            .action(ArgAction::SetTrue),
         )
         .arg(
             arg!(
-                [address] "The address to host the server on"
+                --"health-port" <PORT> "The port to host the health check on"
             )
             .required(false)
-            .default_value("0.0.0.0"),
+            .requires("health")
+            .default_value("8088")
+            .value_parser(value_parser!(u16)),
         )
         .arg(
             arg!(
-                -b --bluetooth "enable the bluetooth server"
+                --"ws-port" <PORT> "Accept websocket connections for the first device on the given port"
+            )
+            .required(false)
+            .value_parser(value_parser!(u16)),
+        )
+        .arg(
+            arg!(
+                --"max-connections" <COUNT> "The maximum number of clients per device"
+            )
+            .required(false)
+            .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            arg!(
+                --watchdog <SECONDS> "Reset a serial port if no messages are read from the device within the given time"
+            )
+            .required(false)
+            .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            arg!(
+                --"wait-serial" [SECONDS] "Wait for serial ports which can't be opened on startup, for at most the given time if any"
+            )
+            .min_values(0)
+            .max_values(1)
+            .require_equals(true)
+            .value_parser(value_parser!(f32)),
+        )
+        .arg(
+            arg!(
+                --watch "Reopen a serial port when its device file is removed and added again"
             )
             .action(ArgAction::SetTrue),
         )
         .arg(
             arg!(
-                -t --bluetooth_client "enable the bluetooth client"
+                --"batch-epoch" "Send the navigation messages of an epoch to clients in a single frame"
             )
             .action(ArgAction::SetTrue),
         )
-        .group(ArgGroup::new("bluetooth-flags").args(&["bluetooth", "bluetooth_client"]))
         .arg(
             arg!(
-                -D --deamon "run the server as a deamon"
+                --dedup "Drop navigation messages which are repeated within the same epoch"
+            )
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"rtcm-only" "Only send rtcm messages to clients, for example for relaying corrections over a radio"
+            )
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"rtcm-rate-limit" <HZ> "The maximum number of rtcm messages per second written to a device, excess corrections are dropped"
+            )
+            .required(false)
+            .value_parser(parse_rate),
+        )
+        .arg(
+            arg!(
+                --"forward-config" <PATH> "A json file with the messages which are forwarded to clients"
+            )
+            .required(false)
+            .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(
+                --"hexdump-errors" "log a hex dump of device messages which fail to parse"
             )
             .action(ArgAction::SetTrue),
         )
-        .get_matches();
+        .subcommand(gps::completions::command())
+        .args(gps::logging::args())
+}
 
+/// Run the server, relative paths in the arguments are resolved against `cwd` as the working
+/// directory is changed when running as a deamon.
+async fn run(matches: ArgMatches, cwd: &Path) -> Result<()> {
     let address = matches.get_one::<String>("address").unwrap();
     let server_port = *matches.get_one::<u16>("port").unwrap();
 
-    let port_path = matches.get_one::<String>("serial").unwrap();
-    let port_baud = *matches.get_one::<u32>("baud").unwrap();
+    let serial = matches.get_many::<String>("serial").unwrap();
+    let bauds: Vec<u32> = matches.get_many::<u32>("baud").unwrap().copied().collect();
+    let configs: Vec<PathBuf> = matches
+        .get_many::<String>("config")
+        .map(|x| x.map(|x| cwd.join(x)).collect())
+        .unwrap_or_default();
+    let records: Vec<PathBuf> = matches
+        .get_many::<PathBuf>("record")
+        .map(|x| x.map(|x| cwd.join(x)).collect())
+        .unwrap_or_default();
     let bluetooth = *matches.get_one::<bool>("bluetooth").unwrap();
     let bluetooth_client = *matches.get_one::<bool>("bluetooth_client").unwrap();
+    let hexdump_errors = *matches.get_one::<bool>("hexdump-errors").unwrap();
+    let batch_epoch = *matches.get_one::<bool>("batch-epoch").unwrap();
+    let dedup = *matches.get_one::<bool>("dedup").unwrap();
+    let watch = *matches.get_one::<bool>("watch").unwrap();
+    let wait_serial = matches.contains_id("wait-serial").then(|| {
+        matches
+            .get_one::<f32>("wait-serial")
+            .map(|x| Duration::from_secs_f32(*x))
+    });
+    let rtcm_only = *matches.get_one::<bool>("rtcm-only").unwrap();
+    let rtcm_rate_limit = matches.get_one::<f32>("rtcm-rate-limit").copied();
+    let forward_policy = matches
+        .get_one::<PathBuf>("forward-config")
+        .map(|x| ForwardPolicy::load(&cwd.join(x)))
+        .transpose()?
+        .unwrap_or_default();
+    let watchdog = matches
+        .get_one::<f32>("watchdog")
+        .map(|x| Duration::from_secs_f32(*x));
+
+    if serial.len() > u8::MAX as usize {
+        bail!("too many serial ports");
+    }
+    if configs.len() > serial.len() {
+        bail!("more configuration files than serial ports");
+    }
+    if records.len() > serial.len() {
+        bail!("more recordings than serial ports");
+    }
 
     let mut bluetooth = if bluetooth {
         Some(BluetoothServer::new().await?)
@@ -168,120 +1363,906 @@ async fn run() -> Result<()> {
         .transpose()
         .context("error parsing connection address")?;
 
-    let port = tokio_serial::new(port_path, port_baud)
-        .data_bits(DataBits::Eight)
-        .parity(Parity::None)
-        .stop_bits(StopBits::One)
-        .timeout(Duration::from_secs(1));
+    let mut devices = Vec::new();
+    for (idx, serial) in serial.enumerate() {
+        let (name, path) = match serial.split_once('=') {
+            Some((name, path)) => (name.to_string(), path),
+            None => (idx.to_string(), serial.as_str()),
+        };
+        let path = cwd.join(path).to_string_lossy().into_owned();
+        let baud = bauds.get(idx).copied().unwrap_or(*bauds.last().unwrap());
 
-    let mut port = Some(SerialStream::open(&port).context("failed to open serial port")?);
+        let watcher = if watch {
+            let path = Path::new(&path);
+            let dir = match path.parent() {
+                Some(x) if !x.as_os_str().is_empty() => x,
+                _ => Path::new("."),
+            };
+            let file_name = path
+                .file_name()
+                .and_then(|x| x.to_str())
+                .unwrap_or_default();
+            Some(
+                DeviceWatcher::watch(dir, &[file_name])
+                    .with_context(|| format!("failed to watch serial port of device `{name}`"))?,
+            )
+        } else {
+            None
+        };
 
-    let listener = TcpListener::bind((address.as_str(), server_port))
-        .await
-        .context("failed to create server")?;
+        let port = match wait_serial {
+            Some(timeout) => wait_for_port(&path, baud, timeout).await,
+            None => open_port(&path, baud),
+        };
+        let mut port = match port {
+            Ok(x) => Some(x),
+            Err(e) if watch => {
+                warn!("failed to open serial port for device `{name}`, waiting for it to be added: {e:?}");
+                None
+            }
+            Err(e) => {
+                return Err(e.context(format!("failed to open serial port for device `{name}`")))
+            }
+        };
 
-    let mut outgoing_connection = OutgoingConnection::new(connection_address);
+        if let (Some((port, writer)), Some(config)) = (port.as_mut(), configs.get(idx)) {
+            if let Err(e) = apply_config(port, writer, config).await {
+                if !*matches.get_one::<bool>("config-optional").unwrap() {
+                    return Err(
+                        e.context(format!("failed to apply configuration to device `{name}`"))
+                    );
+                }
+                error!("failed to apply configuration to device `{name}`: {:?}", e);
+            }
+        }
 
-    let mut connections = ConnectionPool::new(listener);
+        let tcp_port = server_port
+            .checked_add(idx as u16)
+            .ok_or_else(|| anyhow!("no port available for device `{name}`"))?;
+        let listener = TcpListener::bind((address.as_str(), tcp_port))
+            .await
+            .context("failed to create server")?;
+        info!("serving device `{name}` from `{path}` on port {tcp_port}");
+
+        let record = records
+            .get(idx)
+            .map(|path| {
+                // Without a maximum size the recording is never rotated.
+                let max_size = matches
+                    .get_one::<u64>("record-max-size")
+                    .copied()
+                    .unwrap_or(u64::MAX);
+                let keep = *matches.get_one::<usize>("record-keep").unwrap();
+                RotatingWriter::with_keep(path, max_size, keep)
+                    .with_context(|| format!("failed to open recording for device `{name}`"))
+            })
+            .transpose()?;
 
-    if *matches.get_one::<bool>("deamon").unwrap() {
-        gps::deamonize()
-            .map_err(|_| anyhow!("deamon creation error"))
-            .context("failed to create a deamon")?;
+        let (port, writer) = match port {
+            Some((port, writer)) => (
+                Some(port),
+                Some(spawn_writer(name.clone(), writer, rtcm_rate_limit)),
+            ),
+            None => (None, None),
+        };
+        let mut connections = ConnectionPool::new(listener);
+        connections.set_max_connections(matches.get_one::<usize>("max-connections").copied());
+        devices.push(Device {
+            name,
+            path,
+            baud,
+            port,
+            writer,
+            pending: VecDeque::new(),
+            connections,
+            watchdog,
+            last_read: Instant::now(),
+            batch: batch_epoch.then(EpochBatch::default),
+            dedup: dedup.then(|| MessageDedup::new(DEDUP_WINDOW)),
+            watcher,
+            record,
+            started: Instant::now(),
+            last_rtcm: None,
+            rtcm_rate_limit,
+        });
     }
 
-    let mut port_read_buffer = [0u8; 4096];
-    let mut pending_read_bytes = Vec::new();
+    let _advertisements = if *matches.get_one::<bool>("mdns").unwrap() {
+        advertise(&devices, server_port)?
+    } else {
+        Vec::new()
+    };
+
+    let state = if *matches.get_one::<bool>("rest").unwrap() {
+        Some(serve_rest(
+            address,
+            *matches.get_one::<u16>("rest-port").unwrap(),
+        )?)
+    } else {
+        None
+    };
+
+    let health = if *matches.get_one::<bool>("health").unwrap() {
+        Some(serve_health(
+            address,
+            *matches.get_one::<u16>("health-port").unwrap(),
+        )?)
+    } else {
+        None
+    };
+
+    let mut websocket = match matches.get_one::<u16>("ws-port") {
+        Some(port) => Some(serve_websocket(address, *port).await?),
+        None => None,
+    };
+
+    let names = devices.iter().map(|x| x.name.as_str()).collect::<Vec<_>>();
+    let routes = matches
+        .get_many::<String>("route")
+        .into_iter()
+        .flatten()
+        .map(|x| Route::parse(x, &names))
+        .collect::<Result<Vec<_>>>()
+        .context("invalid route")?;
+
+    let mut outgoing_connection = OutgoingConnection::new(connection_address);
+
+    let mut terminate =
+        signal(SignalKind::terminate()).context("failed to install signal handler")?;
+    let mut interrupt =
+        signal(SignalKind::interrupt()).context("failed to install signal handler")?;
 
     info!("entering server loop");
     loop {
-        let mut outgoing_connection_future = Box::pin(outgoing_connection.next());
-        let mut device_future = Box::pin(port.as_mut().unwrap().read(&mut port_read_buffer).fuse());
-        let mut connection_future = connections.next();
+        if let Some(health) = health.as_ref() {
+            health.set_connections(
+                devices
+                    .iter()
+                    .map(|x| x.connections.connection_count())
+                    .sum(),
+            );
+        }
 
-        futures::select! {
-            x = device_future => {
-                let x = x?;
-                pending_read_bytes.extend(&port_read_buffer[..x]);
-                find_message(&mut pending_read_bytes);
-                while let Some(x) = GpsMsg::message_usage(&pending_read_bytes){
-                    trace!("found message with length {}",x);
-
-                    let mut buf = pending_read_bytes.split_off(x);
-                    std::mem::swap(&mut buf,&mut pending_read_bytes);
-                    trace!("message from device {:?}",GpsMsg::parse_read(&buf));
-
-                    outgoing_connection.try_send_message(&buf).await;
+        let event = {
+            let mut outgoing_connection_future = Box::pin(outgoing_connection.next());
+            let mut device_future = futures::future::select_all(
+                devices
+                    .iter_mut()
+                    .enumerate()
+                    .map(|(idx, x)| Box::pin(async move { (idx, x.next_event().await) })),
+            )
+            .fuse();
+
+            futures::select! {
+                ((idx, event), _, _) = device_future => (idx, event),
+                x = async {
                     if let Some(x) = bluetooth.as_mut(){
-                        trace!("sending message to bluetooth clients");
-                        x.send(buf.clone()).await.unwrap()
+                        x.next().await
+                    }else{
+                        futures::future::pending().await
                     }
+                }.fuse() => {
+                    let x = match x {
+                        None => {
+                            bail!("bluetooth connection failed")
+                        }
+                        Some(x) => x,
+                    };
+                    trace!("message from bluetooth {:?}",GpsMsg::parse_read(&x));
+                    (0, DeviceEvent::Transport(x))
+                },
+                x = async {
                     if let Some(x) = bluetooth_client.as_mut(){
-                        trace!("sending message to bluetooth server");
-                        x.send(buf.clone()).await.unwrap();
+                        x.next().await
+                    }else{
+                        futures::future::pending().await
                     }
-                    connections.send(buf.clone()).await.unwrap();
-                    connections.flush().await.unwrap();
-                    find_message(&mut pending_read_bytes);
+                }.fuse() => {
+                    let x = match x {
+                        None => {
+                            bail!("bluetooth connection failed")
+                        }
+                        Some(Ok(x)) => x,
+                        Some(Err(e)) => {
+                            error!("error reading from bluetooth connection: {e}");
+                            continue;
+                        }
+                    };
+                    trace!("message from bluetooth {:?}",GpsMsg::parse_read(&x));
+                    (0, DeviceEvent::Transport(x))
+                },
+                x = async {
+                    if let Some(x) = websocket.as_mut(){
+                        x.next().await
+                    }else{
+                        futures::future::pending().await
+                    }
+                }.fuse() => {
+                    let x = x.unwrap();
+                    trace!("message from websocket {:?}",GpsMsg::parse_read(&x));
+                    (0, DeviceEvent::Transport(x))
+                },
+                x = outgoing_connection_future => {
+                    let x = x.unwrap();
+                    trace!("message from outgoing {:?}",GpsMsg::parse_read(&x));
+                    (0, DeviceEvent::Transport(x))
+                },
+                _ = terminate.recv().fuse() => {
+                    info!("recieved terminate signal, quiting");
+                    break;
+                },
+                _ = interrupt.recv().fuse() => {
+                    info!("recieved interrupt signal, quiting");
+                    break;
+                },
+            }
+        };
+
+        let (idx, buf) = match event {
+            (idx, DeviceEvent::Device(Some(Err(e)))) if e.is::<io::Error>() => {
+                let device = &mut devices[idx];
+                // The port is reopened once it is added again or the watchdog expires.
+                if device.watcher.is_none() && device.watchdog.is_none() {
+                    return Err(e).context(format!(
+                        "failed to read from serial port of device `{}`",
+                        device.name
+                    ));
                 }
-            },
-            x = async {
-                if let Some(x) = bluetooth.as_mut(){
-                    x.next().await
-                }else{
-                    futures::future::pending().await
+                error!(
+                    "failed to read from serial port of device `{}`, closing port: {e}",
+                    device.name
+                );
+                device.close_port();
+                continue;
+            }
+            (idx, DeviceEvent::Device(Some(msg))) => {
+                devices[idx].last_read = Instant::now();
+                let buf = devices[idx].port.as_ref().unwrap().last_frame().to_vec();
+
+                if let Some(record) = devices[idx].record.as_mut() {
+                    if let Err(e) = record.write_all(&buf) {
+                        error!(
+                            "failed to write recording of device `{}`, stopping recording: {e}",
+                            devices[idx].name
+                        );
+                        devices[idx].record = None;
+                    }
                 }
-            }.fuse() => {
-                let x = match x {
-                    None => {
-                        bail!("bluetooth connection failed")
+
+                // Routing happens here, before anything else is done with the frame. The frame is
+                // queued on the writer task of the target device, like every other write, so a slow
+                // port never blocks reading. Queued rtcm is written before other messages.
+                for route in routes.iter().filter(|x| x.from == idx && x.matches(&buf)) {
+                    trace!(
+                        "routing message from device `{}` to `{}`",
+                        devices[idx].name,
+                        devices[route.to].name
+                    );
+                    devices[route.to].write(buf.clone());
+                }
+
+                let device = &mut devices[idx];
+                trace!(
+                    "found message with length {} from device `{}`",
+                    buf.len(),
+                    device.name
+                );
+                if let (Some(dedup), Ok(x)) = (device.dedup.as_mut(), msg.as_ref()) {
+                    if dedup.is_duplicate(x, &buf) {
+                        trace!("dropping duplicate message from device `{}`", device.name);
+                        continue;
                     }
-                    Some(x) => x,
-                };
-                trace!("message from bluetooth {:?}",GpsMsg::parse_read(&x));
-                handle_incomming(&port_path,port_baud,&mut port,x).await?;
-            },
-            x = async {
-                if let Some(x) = bluetooth_client.as_mut(){
-                    x.next().await
-                }else{
-                    futures::future::pending().await
                 }
-            }.fuse() => {
-                let x = match x {
-                    None => {
-                        bail!("bluetooth connection failed")
+                match msg {
+                    Ok(x) => {
+                        trace!(
+                            "message from device: {}\n{}",
+                            x,
+                            gps::logging::trace_hexdump(&buf)
+                        );
+                        if let (0, Some(state)) = (idx, state.as_ref()) {
+                            state.lock().unwrap().update(&x);
+                        }
                     }
-                    Some(Ok(x)) => x,
-                    Some(Err(e)) => {
-                        error!("error reading from bluetooth connection: {e}");
-                        continue;
+                    Err(e) if hexdump_errors => {
+                        let at = ErrorOffset::of(&e)
+                            .map(|x| format!(" at byte {x:#x}"))
+                            .unwrap_or_default();
+                        warn!(
+                            "failed to parse message from device{at}: {:?}\n{}",
+                            e,
+                            gps::hexdump(&buf)
+                        );
                     }
-                };
-                trace!("message from bluetooth {:?}",GpsMsg::parse_read(&x));
-                handle_incomming(&port_path,port_baud,&mut port,x).await?;
-            },
-            x = outgoing_connection_future => {
-                let x = x.unwrap();
-                trace!("message from outgoing {:?}",GpsMsg::parse_read(&x));
-                handle_incomming(&port_path,port_baud,&mut port,x).await?;
-            },
-            x = connection_future => {
-                let x = x.unwrap();
-                trace!("message from connection {:?}",GpsMsg::parse_read(&x));
-                handle_incomming(&port_path,port_baud,&mut port,x).await?;
+                    Err(e) => trace!("failed to parse message from device: {:?}", e),
+                }
+
+                // Routes have their own protocol filter so they still recieve everything.
+                if !forwarded(&buf, rtcm_only, &forward_policy) {
+                    trace!("not forwarding message from device `{}`", device.name);
+                    continue;
+                }
+
+                // Only navigation messages are batched so that for example rtcm messages and
+                // acknowledgements are never delayed.
+                let id = MessageId::from_frame(&buf).filter(|x| x.class == MessageId::NAV_CLASS);
+                match (device.batch.as_mut(), id) {
+                    (Some(batch), Some(id)) => {
+                        batch.push(&buf);
+                        if id != MessageId::NAV_EOE {
+                            continue;
+                        }
+                        (idx, batch.take())
+                    }
+                    _ => (idx, buf),
+                }
+            }
+            (idx, DeviceEvent::BatchTimeout) => {
+                trace!("batch timeout for device `{}`", devices[idx].name);
+                (idx, devices[idx].batch.as_mut().unwrap().take())
+            }
+            (idx, DeviceEvent::Timeout) => {
+                devices[idx].watchdog_reset().await;
+                continue;
+            }
+            (idx, DeviceEvent::Device(None)) if devices[idx].watcher.is_some() => {
+                let device = &mut devices[idx];
+                warn!(
+                    "serial port of device `{}` closed, waiting for it to be added again",
+                    device.name
+                );
+                device.close_port();
+                continue;
+            }
+            (idx, DeviceEvent::Device(None)) => {
+                bail!("serial port of device `{}` closed", devices[idx].name)
+            }
+            (idx, DeviceEvent::Hotplug(x)) => {
+                let device = &mut devices[idx];
+                // Only the directory of the port is watched so comparing names suffices.
+                let is_port = |x: &Path| x.file_name() == Path::new(&device.path).file_name();
+                match x {
+                    Some(device::DeviceEvent::Added(path)) if is_port(&path) => {
+                        info!("serial port of device `{}` added, reopening", device.name);
+                        if let Err(e) = device.reset_port().await {
+                            error!("failed to reopen port of device `{}`: {:?}", device.name, e);
+                        }
+                    }
+                    Some(device::DeviceEvent::Removed(path)) if is_port(&path) => {
+                        warn!("serial port of device `{}` removed", device.name);
+                        device.close_port();
+                    }
+                    Some(_) => {}
+                    None => {
+                        error!("stopped watching serial port of device `{}`", device.name);
+                        device.watcher = None;
+                    }
+                }
+                continue;
+            }
+            (idx, DeviceEvent::Connection(x)) => {
+                trace!(
+                    "message for device `{}` {:?}",
+                    devices[idx].name,
+                    GpsMsg::parse_read(&x)
+                );
+                if let Some(reply) = handle_incomming(&mut devices, idx, x).await? {
+                    let connections = &mut devices[idx].connections;
+                    connections.reply(reply);
+                    connections.flush().await.unwrap();
+                }
+                continue;
+            }
+            (idx, DeviceEvent::Transport(x)) => {
+                trace!(
+                    "message for device `{}` {:?}",
+                    devices[idx].name,
+                    GpsMsg::parse_read(&x)
+                );
+                if handle_incomming(&mut devices, idx, x).await?.is_some() {
+                    warn!("server requests are only replied to over tcp connections");
+                }
+                continue;
+            }
+        };
+
+        let device = &mut devices[idx];
+        // The outgoing connection, bluetooth and websockets are only attached to the first device.
+        if idx == 0 {
+            outgoing_connection.try_send_message(&buf).await;
+            if let Some(x) = bluetooth.as_mut() {
+                trace!("sending message to bluetooth clients");
+                x.send(buf.clone()).await.unwrap()
+            }
+            if let Some(x) = bluetooth_client.as_mut() {
+                trace!("sending message to bluetooth server");
+                x.send(buf.clone()).await.unwrap();
+            }
+            if let Some(x) = websocket.as_mut() {
+                x.send(buf.clone()).await.unwrap();
             }
         }
+        device.connections.send(buf).await.unwrap();
+        device.connections.flush().await.unwrap();
+    }
+
+    if let Some(x) = bluetooth {
+        x.shutdown().await;
     }
+    Ok(())
 }
 
 fn main() -> Result<()> {
-    env_logger::init_from_env(
-        env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "info"),
-    );
+    let matches = command().get_matches();
+
+    if let Some((gps::completions::SUBCOMMAND, m)) = matches.subcommand() {
+        gps::completions::generate(command(), m);
+        return Ok(());
+    }
+
+    gps::logging::init_from_matches(&matches).context("failed to initialize logging")?;
+
+    let cwd = std::env::current_dir().context("failed to get the working directory")?;
+    // Only the forking thread survives in the deamon, so this has to happen before the runtime
+    // and anything else which starts a thread.
+    let _pidfile = if *matches.get_one::<bool>("deamon").unwrap() {
+        gps::deamonize(matches.get_one::<PathBuf>("pidfile").map(|x| x.as_path()))
+            .context("failed to create a deamon")?
+    } else {
+        None
+    };
 
     tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()?
-        .block_on(run())
+        .block_on(run(matches, &cwd))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::CStr, fs::File, io::Read, os::unix::fs::symlink, os::unix::io::FromRawFd};
+
+    use super::*;
+    use gps::msg::ubx::nav::Nav;
+
+    /// An rtcm 1005 message.
+    const RTCM_FRAME: [u8; 12] = [
+        0xd3, 0x00, 0x06, 0x3e, 0xd0, 0x00, 0x03, 0x04, 0x05, 0xf9, 0xdc, 0x38,
+    ];
+
+    fn rtcm() -> GpsMsg {
+        GpsMsg::Rtcm3(msg::Rtcm::parse_read(&RTCM_FRAME).unwrap().1)
+    }
+
+    fn assert_elapsed(start: Instant, millis: u64) {
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed >= Duration::from_millis(millis) && elapsed < Duration::from_millis(millis + 5),
+            "expected {millis}ms to elapse, took {elapsed:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn waiting_for_a_port_is_logged_once_per_interval() {
+        tokio::time::pause();
+        let mut last_log = None;
+        assert!(log_due(&mut last_log));
+        assert!(!log_due(&mut last_log));
+
+        tokio::time::advance(WAIT_SERIAL_LOG_INTERVAL - Duration::from_secs(1)).await;
+        assert!(!log_due(&mut last_log));
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(log_due(&mut last_log));
+        assert!(!log_due(&mut last_log));
+    }
+
+    #[tokio::test]
+    async fn waiting_for_a_missing_port_times_out() {
+        tokio::time::pause();
+        let start = Instant::now();
+        let e = wait_for_port(
+            "/dev/gps-server-missing",
+            9600,
+            Some(Duration::from_secs(3)),
+        )
+        .await
+        .err()
+        .unwrap();
+        assert_eq!(e.to_string(), "timed out waiting for serial port");
+        assert_elapsed(start, 3000);
+    }
+
+    #[tokio::test]
+    async fn rate_limiter_enforces_rate_and_burst() {
+        tokio::time::pause();
+        let (send, recv) = futures::channel::mpsc::unbounded();
+        let mut limiter = RtcmRateLimiter::new(send.sink_map_err(anyhow::Error::from), 2.0, 3);
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.send(rtcm()).await.unwrap();
+        }
+        assert_elapsed(start, 0);
+        limiter.send(rtcm()).await.unwrap();
+        assert_elapsed(start, 500);
+        // Raw frames don't take a token.
+        limiter.send(vec![0xb5, 0x62]).await.unwrap();
+        assert_elapsed(start, 500);
+        limiter.send(rtcm()).await.unwrap();
+        assert_elapsed(start, 1000);
+
+        // Tokens don't build up beyond the burst.
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.send(rtcm()).await.unwrap();
+        }
+        assert_elapsed(start, 0);
+        limiter.send(rtcm()).await.unwrap();
+        assert_elapsed(start, 500);
+
+        drop(limiter);
+        assert_eq!(recv.count().await, 10);
+    }
+
+    #[tokio::test]
+    async fn writer_takes_a_token_per_rtcm_message() {
+        tokio::time::pause();
+        let (send, mut recv) = futures::channel::mpsc::unbounded();
+        let limiter = RtcmRateLimiter::new(send.sink_map_err(anyhow::Error::from), 2.0, 3);
+        let (sender, channel) = mpsc::channel(WRITE_QUEUE_LIMIT);
+        tokio::spawn(write_device("test".to_string(), channel, limiter, Some(3)));
+
+        // The first frame holding three messages takes all tokens.
+        let start = Instant::now();
+        sender.send(RTCM_FRAME.repeat(3)).await.unwrap();
+        for _ in 0..3 {
+            assert_eq!(recv.next().await.unwrap(), RTCM_FRAME);
+        }
+        assert_elapsed(start, 0);
+        sender.send(RTCM_FRAME.repeat(2)).await.unwrap();
+        assert_eq!(recv.next().await.unwrap(), RTCM_FRAME);
+        assert_elapsed(start, 500);
+        assert_eq!(recv.next().await.unwrap(), RTCM_FRAME);
+        assert_elapsed(start, 1000);
+    }
+
+    #[tokio::test]
+    async fn writer_finishes_queued_writes_after_channel_closes() {
+        tokio::time::pause();
+        let (send, recv) = futures::channel::mpsc::unbounded();
+        let limiter = RtcmRateLimiter::new(send.sink_map_err(anyhow::Error::from), 2.0, 1);
+        let (sender, channel) = mpsc::channel(WRITE_QUEUE_LIMIT);
+        let writer = tokio::spawn(write_device("test".to_string(), channel, limiter, None));
+
+        // The second message waits for a token while the channel is closed.
+        sender.send(RTCM_FRAME.repeat(2)).await.unwrap();
+        sender.send(NAV_FRAME.to_vec()).await.unwrap();
+        drop(sender);
+        let start = Instant::now();
+        writer.await.unwrap();
+        assert_elapsed(start, 500);
+        assert_eq!(
+            recv.collect::<Vec<_>>().await,
+            [RTCM_FRAME.to_vec(), RTCM_FRAME.to_vec(), NAV_FRAME.to_vec()]
+        );
+    }
+
+    #[tokio::test]
+    async fn messages_during_reset_are_written_after_reopening() {
+        tokio::time::pause();
+        let (master, pty) = pty();
+        // The port is opened through a link, so it can be made to disappear.
+        let dir = std::env::temp_dir().join(format!("gps-server-reset-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let link = dir.join("port");
+        std::fs::remove_file(&link).ok();
+        symlink(&pty, &link).unwrap();
+        let mut devices = vec![device(link.to_str().unwrap()).await];
+        let reset = msg::Server {
+            msg: msg::server::ServerMsg::ResetPort,
+            device: 0,
+        }
+        .parse_to_vec()
+        .unwrap();
+
+        // A message right after the reset goes to the reopened port.
+        let reply = handle_incomming(&mut devices, 0, reset.clone())
+            .await
+            .unwrap();
+        assert!(reply.is_none());
+        handle_incomming(&mut devices, 0, NAV_FRAME.to_vec())
+            .await
+            .unwrap();
+        assert_eq!(read_pty(&master, NAV_FRAME.len()).await, NAV_FRAME);
+
+        // While the port can't be reopened messages are kept until it can.
+        std::fs::remove_file(&link).unwrap();
+        handle_incomming(&mut devices, 0, reset.clone())
+            .await
+            .unwrap();
+        assert!(devices[0].port.is_none());
+        handle_incomming(&mut devices, 0, NAV_FRAME.to_vec())
+            .await
+            .unwrap();
+        assert_eq!(devices[0].pending.len(), 1);
+
+        symlink(&pty, &link).unwrap();
+        handle_incomming(&mut devices, 0, reset).await.unwrap();
+        assert!(devices[0].port.is_some());
+        assert!(devices[0].pending.is_empty());
+        assert_eq!(read_pty(&master, NAV_FRAME.len()).await, NAV_FRAME);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// A frame with a NAV-EOE message.
+    const NAV_FRAME: [u8; 12] = [
+        0xb5, 0x62, 0x01, 0x61, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x66, 0xc7,
+    ];
+
+    /// A pseudo terminal standing in for a serial port. Returns the controlling side, which
+    /// acts as the receiver, and the path of the port.
+    fn pty() -> (File, String) {
+        unsafe {
+            let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+            assert!(
+                fd >= 0,
+                "failed to open pty: {}",
+                io::Error::last_os_error()
+            );
+            let master = File::from_raw_fd(fd);
+            assert_eq!(libc::grantpt(fd), 0);
+            assert_eq!(libc::unlockpt(fd), 0);
+            let path = CStr::from_ptr(libc::ptsname(fd))
+                .to_str()
+                .unwrap()
+                .to_string();
+            (master, path)
+        }
+    }
+
+    /// Read a number of bytes written to the port from the controlling side of a pty.
+    async fn read_pty(master: &File, len: usize) -> Vec<u8> {
+        let mut master = master.try_clone().unwrap();
+        tokio::task::spawn_blocking(move || {
+            let mut buffer = vec![0; len];
+            master.read_exact(&mut buffer).unwrap();
+            buffer
+        })
+        .await
+        .unwrap()
+    }
+
+    /// A device with the given serial port opened, without any clients.
+    async fn device(path: &str) -> Device {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (port, writer) = open_port(path, 9600).unwrap();
+        Device {
+            name: "test".to_string(),
+            path: path.to_string(),
+            baud: 9600,
+            port: Some(port),
+            writer: Some(spawn_writer("test".to_string(), writer, None)),
+            pending: VecDeque::new(),
+            connections: ConnectionPool::new(listener),
+            watchdog: None,
+            last_read: Instant::now(),
+            batch: None,
+            dedup: None,
+            watcher: None,
+            record: None,
+            started: Instant::now(),
+            last_rtcm: None,
+            rtcm_rate_limit: None,
+        }
+    }
+
+    fn is_eoe(event: DeviceEvent) -> bool {
+        matches!(
+            event,
+            DeviceEvent::Device(Some(Ok(GpsMsg::Ubx(Ubx::Nav(Nav::Eoe(_))))))
+        )
+    }
+
+    #[tokio::test]
+    async fn watchdog_reopens_a_silent_port() {
+        tokio::time::pause();
+        let (mut master, path) = pty();
+        let mut device = device(&path).await;
+        device.watchdog = Some(Duration::from_secs(2));
+
+        let start = Instant::now();
+        assert!(matches!(device.next_event().await, DeviceEvent::Timeout));
+        assert_elapsed(start, 2000);
+        device.watchdog_reset().await;
+        assert!(device.port.is_some());
+        assert!(device.last_read - start >= Duration::from_millis(2500));
+
+        // The device is read again from the reopened port.
+        device.watchdog = None;
+        master.write_all(&NAV_FRAME).unwrap();
+        assert!(is_eoe(device.next_event().await));
+    }
+
+    #[tokio::test]
+    async fn rtcm_only_forwards_just_rtcm() {
+        let (mut master, path) = pty();
+        let mut device = device(&path).await;
+        let nmea = b"$GNGGA,,,,,,0,00,99.99,,,,,,*56\r\n";
+        master
+            .write_all(&[&NAV_FRAME[..], nmea, &RTCM_FRAME, &NAV_FRAME, &RTCM_FRAME].concat())
+            .unwrap();
+
+        let policy = ForwardPolicy::default();
+        let mut forwarded_frames = Vec::new();
+        for _ in 0..5 {
+            assert!(matches!(
+                device.next_event().await,
+                DeviceEvent::Device(Some(Ok(_)))
+            ));
+            let frame = device.port.as_ref().unwrap().last_frame();
+            assert!(forwarded(frame, false, &policy));
+            if forwarded(frame, true, &policy) {
+                forwarded_frames.push(frame.to_vec());
+            }
+        }
+        assert_eq!(forwarded_frames, [RTCM_FRAME.to_vec(), RTCM_FRAME.to_vec()]);
+    }
+
+    #[test]
+    fn rtcm_is_written_before_other_messages() {
+        let mut queue = PriorityMsgQueue::default();
+        queue.push(NAV_FRAME.to_vec());
+        queue.push(RTCM_FRAME.to_vec());
+        // Rtcm after a navigation message in the same frame skips ahead as well.
+        queue.push([NAV_FRAME, RTCM_FRAME].concat());
+        queue.push(NAV_FRAME.to_vec());
+
+        assert_eq!(queue.pop(), Some(RTCM_FRAME.to_vec()));
+        assert_eq!(queue.pop(), Some(RTCM_FRAME.to_vec()));
+        assert_eq!(queue.pop(), Some(NAV_FRAME.to_vec()));
+        assert_eq!(queue.pop(), Some(NAV_FRAME.to_vec()));
+        assert_eq!(queue.pop(), Some(NAV_FRAME.to_vec()));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn write_queue_keeps_bytes_which_are_not_a_message() {
+        let mut queue = PriorityMsgQueue::default();
+        let frame = [&b"junk"[..], &RTCM_FRAME, &NAV_FRAME, &RTCM_FRAME[..5]].concat();
+        queue.push(frame);
+
+        assert_eq!(queue.pop(), Some(RTCM_FRAME.to_vec()));
+        let other = [&b"junk"[..], &NAV_FRAME, &RTCM_FRAME[..5]].concat();
+        assert_eq!(queue.pop(), Some(other));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn full_write_queue_drops_oldest_rtcm() {
+        // Only the length in the header is used to split frames, so the crc doesn't matter.
+        let rtcm = |i: u8| vec![0xd3, 0x00, 0x00, i, 0x00, 0x00];
+        let mut queue = PriorityMsgQueue::default();
+        assert!(!queue.push(NAV_FRAME.to_vec()));
+        for i in 1..WRITE_QUEUE_LIMIT {
+            assert!(!queue.push(rtcm(i as u8)));
+        }
+        assert!(queue.push(rtcm(0xff)));
+        assert_eq!(queue.len(), WRITE_QUEUE_LIMIT);
+        assert_eq!(queue.pop(), Some(rtcm(2)));
+        assert_eq!(queue.other.front(), Some(&NAV_FRAME.to_vec()));
+        assert_eq!(queue.rtcm.back(), Some(&rtcm(0xff)));
+    }
+
+    #[test]
+    fn full_write_queue_without_rtcm_drops_oldest_message() {
+        let mut queue = PriorityMsgQueue::default();
+        for i in 0..=WRITE_QUEUE_LIMIT {
+            queue.push(vec![0xb5, 0x62, i as u8]);
+        }
+        assert_eq!(queue.len(), WRITE_QUEUE_LIMIT);
+        assert_eq!(queue.pop(), Some(vec![0xb5, 0x62, 1]));
+    }
+
+    #[test]
+    fn parse_route() {
+        let devices = ["base", "rover"];
+        assert_eq!(
+            Route::parse("base:rtcm->rover", &devices).unwrap(),
+            Route {
+                from: 0,
+                protocol: Protocol::Rtcm,
+                filters: Vec::new(),
+                to: 1,
+            }
+        );
+        assert_eq!(
+            Route::parse("base:rtcm:4072, msm->rover", &devices).unwrap(),
+            Route {
+                from: 0,
+                protocol: Protocol::Rtcm,
+                filters: vec![RouteFilter::Rtcm(4072), RouteFilter::Msm],
+                to: 1,
+            }
+        );
+        assert_eq!(
+            Route::parse("rover:ubx:0x02,0x01.7->base", &devices).unwrap(),
+            Route {
+                from: 1,
+                protocol: Protocol::Ubx,
+                filters: vec![
+                    RouteFilter::Ubx {
+                        class: 0x02,
+                        id: None
+                    },
+                    RouteFilter::Ubx {
+                        class: 0x01,
+                        id: Some(0x07)
+                    }
+                ],
+                to: 0,
+            }
+        );
+        assert!(Route::parse("base:rtcm:msm4->rover", &devices).is_err());
+        assert!(Route::parse("base:ubx:0x100->rover", &devices).is_err());
+        assert!(Route::parse("base:nmea:GP->rover", &devices).is_err());
+        assert!(Route::parse("base:rtcm->base", &devices).is_err());
+        assert!(Route::parse("base:rtcm->other", &devices).is_err());
+    }
+
+    #[test]
+    fn route_filters_messages() {
+        // Only the header is looked at, so a frame doesn't need a valid payload.
+        let rtcm = |kind: u16| vec![0xd3, 0x00, 0x02, (kind >> 4) as u8, (kind << 4) as u8];
+        let ubx = |class, id| vec![0xb5, 0x62, class, id, 0, 0];
+
+        let route = Route::parse("base:rtcm:4072,msm->rover", &["base", "rover"]).unwrap();
+        assert!(route.matches(&rtcm(4072)));
+        assert!(route.matches(&rtcm(1077)));
+        assert!(route.matches(&rtcm(1127)));
+        assert!(!route.matches(&rtcm(1005)));
+        assert!(!route.matches(&rtcm(1230)));
+        assert!(!route.matches(&ubx(0x02, 0x15)));
+
+        let route = Route::parse("base:ubx:0x02,0x01.0x07->rover", &["base", "rover"]).unwrap();
+        assert!(route.matches(&ubx(0x02, 0x15)));
+        assert!(route.matches(&ubx(0x01, 0x07)));
+        assert!(!route.matches(&ubx(0x01, 0x61)));
+        assert!(!route.matches(&rtcm(4072)));
+
+        let route = Route::parse("base:rtcm->rover", &["base", "rover"]).unwrap();
+        assert!(route.matches(&rtcm(1230)));
+        assert!(!route.matches(&ubx(0x01, 0x07)));
+    }
+
+    #[test]
+    fn forward_policy_with_only_nav_pvt_allowed() {
+        let dir = std::env::temp_dir().join(format!("gps-server-forward-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("forward.json");
+        std::fs::write(&path, r#"{"allow": {"ubx": [[1, 7]]}}"#).unwrap();
+        let policy = ForwardPolicy::load(&path).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        let pvt = GpsMsg::Ubx(Ubx::Nav(Nav::Pvt(Default::default())))
+            .parse_to_vec()
+            .unwrap();
+        assert!(policy.allows(&pvt));
+        assert!(!policy.allows(&NAV_FRAME));
+        assert!(!policy.allows(&[0xb5, 0x62, 0x01, 0x14, 0, 0]));
+        assert!(!policy.allows(&RTCM_FRAME));
+        assert!(!policy.allows(b"$GNGGA,,,,,,0,00,99.99,,,,,,*56\r\n"));
+    }
+
+    #[test]
+    fn forward_policy_denies_listed_messages() {
+        let policy: ForwardPolicy =
+            serde_json::from_str(r#"{"deny": {"nmea": ["GP"], "rtcm": [1005]}}"#).unwrap();
+        assert!(policy.allows(&NAV_FRAME));
+        assert!(!policy.allows(&RTCM_FRAME));
+        assert!(!policy.allows(b"$GPGGA,,,,,,0,00,99.99,,,,,,*48\r\n"));
+        assert!(policy.allows(b"$GNGGA,,,,,,0,00,99.99,,,,,,*56\r\n"));
+
+        assert!(ForwardPolicy::default().allows(&RTCM_FRAME));
+    }
 }
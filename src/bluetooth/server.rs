@@ -70,6 +70,32 @@ impl BluetoothServer {
         })
     }
 
+    /// Stop advertising and close all connections.
+    ///
+    /// Dropping the server also removes the advertisement but it does so from a background task
+    /// which might not run before the process exits, leaving the advertisement until bluez times
+    /// it out.
+    pub async fn shutdown(self) {
+        let BluetoothServer {
+            adapter,
+            advert_handle,
+            listener,
+            streams,
+            ..
+        } = self;
+        drop(streams);
+        drop(listener);
+        drop(advert_handle);
+
+        // Let the background task send the request to remove the advertisement, bluez handles
+        // requests in order so it is removed once the next request returns.
+        tokio::task::yield_now().await;
+        if let Err(e) = adapter.is_powered().await {
+            error!("error removing bluetooth advertisement: {e}");
+        }
+        info!("bluetooth advertisement removed");
+    }
+
     pub fn poll_accept(&mut self, cx: &mut Context) -> Result<()> {
         loop {
             match self.listener.poll_accept(cx) {
@@ -0,0 +1,31 @@
+//! Generation of shell completion scripts shared by the binaries.
+
+use std::{io::stdout, path::Path};
+
+use clap::{arg, value_parser, ArgMatches, Command};
+use clap_complete::Shell;
+
+/// The name of the subcommand which prints the completion script.
+pub const SUBCOMMAND: &str = "generate-completions";
+
+/// The subcommand printing the completion script for a shell.
+pub fn command() -> Command<'static> {
+    Command::new(SUBCOMMAND)
+        .about("Print the completion script for a shell")
+        .arg(
+            arg!(<SHELL> "The shell to generate the script for").value_parser(value_parser!(Shell)),
+        )
+}
+
+/// Print the completion script of the given command for the shell in the subcommand matches.
+pub fn generate(mut cmd: Command, matches: &ArgMatches) {
+    let shell = *matches.get_one::<Shell>("SHELL").unwrap();
+    // Completions are registered for the name the binary is invoked with.
+    let name = std::env::args()
+        .next()
+        .as_deref()
+        .and_then(|x| Path::new(x).file_name())
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_else(|| cmd.get_name().to_string());
+    clap_complete::generate(shell, &mut cmd, name, &mut stdout());
+}
@@ -8,10 +8,11 @@ use std::{
 use crate::VecExt;
 
 use anyhow::Error;
-use futures::{Sink, Stream};
+use futures::{Sink, SinkExt, Stream};
+use log::warn;
 use pin_project::pin_project;
 use tokio::{
-    io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf},
+    io::{AsyncRead, AsyncWrite, ReadBuf},
     net::TcpStream,
 };
 
@@ -21,8 +22,27 @@ pub use pool::ConnectionPool;
 pub mod outgoing;
 pub use outgoing::OutgoingConnection;
 
+#[cfg(feature = "websocket")]
+pub mod websocket;
+#[cfg(feature = "websocket")]
+pub use websocket::WebSocketPool;
+
+/// The length of the header in front of every frame, a `u32` length of the frame followed by a
+/// `u64` sequence number which is incremented for every frame send over the connection.
+pub const HEADER_LEN: usize = 12;
+
+fn header(len: usize, seq: u64) -> Result<[u8; HEADER_LEN], IoError> {
+    let len = u32::try_from(len).map_err(|e| IoError::new(std::io::ErrorKind::InvalidData, e))?;
+    let mut header = [0u8; HEADER_LEN];
+    header[..4].copy_from_slice(&len.to_le_bytes());
+    header[4..].copy_from_slice(&seq.to_le_bytes());
+    Ok(header)
+}
+
 pub struct MessageStream<T> {
     pending: Option<u32>,
+    next_seq: Option<u64>,
+    dropped: u64,
     buffer: Vec<u8>,
     pub source: T,
 }
@@ -31,10 +51,29 @@ impl<T> MessageStream<T> {
     pub fn new(t: T) -> Self {
         MessageStream {
             pending: None,
+            next_seq: None,
+            dropped: 0,
             buffer: Vec::new(),
             source: t,
         }
     }
+
+    /// The number of frames the other side send but which were never recieved, detected from
+    /// gaps in the sequence numbers.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    fn check_seq(&mut self, seq: u64) {
+        if let Some(next) = self.next_seq {
+            if seq > next {
+                let count = seq - next;
+                warn!("sequence gap in recieved frames, {count} frames were dropped");
+                self.dropped += count;
+            }
+        }
+        self.next_seq = Some(seq.wrapping_add(1));
+    }
 }
 
 impl<T: AsyncRead + Unpin> Stream for MessageStream<T> {
@@ -44,12 +83,13 @@ impl<T: AsyncRead + Unpin> Stream for MessageStream<T> {
         let this = &mut *self;
 
         loop {
-            if this.pending.is_none() && this.buffer.len() >= 4 {
-                let array = <[u8; 4]>::try_from(&this.buffer[..4]).unwrap();
-                let len = u32::from_le_bytes(array);
-                // shift the len bytes out
-                this.buffer.shift(4);
-                this.pending = Some(len);
+            if this.pending.is_none() && this.buffer.len() >= HEADER_LEN {
+                let len = <[u8; 4]>::try_from(&this.buffer[..4]).unwrap();
+                let seq = <[u8; 8]>::try_from(&this.buffer[4..HEADER_LEN]).unwrap();
+                // shift the header bytes out
+                this.buffer.shift(HEADER_LEN);
+                this.pending = Some(u32::from_le_bytes(len));
+                this.check_seq(u64::from_le_bytes(seq));
             }
 
             if let Some(pending) = this.pending.take() {
@@ -98,13 +138,21 @@ impl<T: AsyncWrite + Unpin> AsyncWrite for MessageStream<T> {
 
 pub enum WriteState {
     Ready,
-    WritingLength { written: usize, data: Vec<u8> },
-    WritingData { written: usize, data: Vec<u8> },
+    WritingHeader {
+        written: usize,
+        header: [u8; HEADER_LEN],
+        data: Vec<u8>,
+    },
+    WritingData {
+        written: usize,
+        data: Vec<u8>,
+    },
 }
 
 #[pin_project]
 pub struct MessageSink<T> {
     state: WriteState,
+    seq: u64,
     #[pin]
     pub source: T,
 }
@@ -113,9 +161,23 @@ impl<T> MessageSink<T> {
     pub fn new(t: T) -> Self {
         MessageSink {
             state: WriteState::Ready,
+            seq: 0,
             source: t,
         }
     }
+
+    /// Returns the sequence number for the next frame.
+    fn next_seq(&mut self) -> u64 {
+        let seq = self.seq;
+        self.seq = self.seq.wrapping_add(1);
+        seq
+    }
+
+    /// Skip the sequence number of the next frame, used when a frame is dropped instead of send
+    /// so the other side can detect the gap.
+    pub fn skip(&mut self) {
+        self.next_seq();
+    }
 }
 
 impl<T: AsyncWrite + Unpin> MessageSink<T> {
@@ -123,29 +185,41 @@ impl<T: AsyncWrite + Unpin> MessageSink<T> {
         loop {
             match std::mem::replace(&mut self.state, WriteState::Ready) {
                 WriteState::Ready => return Poll::Ready(Ok(())),
-                WriteState::WritingLength { mut written, data } => {
-                    let len = u32::try_from(data.len())
-                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-                    let buffer = len.to_le_bytes();
-
-                    match Pin::new(&mut self.source).poll_write(cx, &buffer[written..]) {
-                        Poll::Ready(Err(e)) => return Poll::Ready(Err(Error::from(e))),
-                        Poll::Pending => return Poll::Pending,
-                        Poll::Ready(Ok(x)) => {
-                            written += x;
-
-                            if written >= 4 {
-                                self.state = WriteState::WritingData { written: 0, data };
-                            } else {
-                                self.state = WriteState::WritingLength { written, data };
-                            }
+                WriteState::WritingHeader {
+                    mut written,
+                    header,
+                    data,
+                } => match Pin::new(&mut self.source).poll_write(cx, &header[written..]) {
+                    Poll::Ready(Err(e)) => return Poll::Ready(Err(Error::from(e))),
+                    Poll::Pending => {
+                        self.state = WriteState::WritingHeader {
+                            written,
+                            header,
+                            data,
+                        };
+                        return Poll::Pending;
+                    }
+                    Poll::Ready(Ok(x)) => {
+                        written += x;
+
+                        if written >= HEADER_LEN {
+                            self.state = WriteState::WritingData { written: 0, data };
+                        } else {
+                            self.state = WriteState::WritingHeader {
+                                written,
+                                header,
+                                data,
+                            };
                         }
                     }
-                }
+                },
                 WriteState::WritingData { mut written, data } => {
                     match Pin::new(&mut self.source).poll_write(cx, &data[written..]) {
                         Poll::Ready(Err(e)) => return Poll::Ready(Err(Error::from(e))),
-                        Poll::Pending => return Poll::Pending,
+                        Poll::Pending => {
+                            self.state = WriteState::WritingData { written, data };
+                            return Poll::Pending;
+                        }
                         Poll::Ready(Ok(x)) => {
                             written += x;
                             if written >= data.len() {
@@ -168,10 +242,12 @@ impl<T: AsyncWrite + Unpin> Sink<Vec<u8>> for MessageSink<T> {
     }
 
     fn start_send(mut self: Pin<&mut Self>, item: Vec<u8>) -> Result<(), Error> {
-        let mut this: &mut Self = &mut *self;
+        let this: &mut Self = &mut *self;
 
-        this.state = WriteState::WritingLength {
+        let seq = this.next_seq();
+        this.state = WriteState::WritingHeader {
             written: 0,
+            header: header(item.len(), seq)?,
             data: item,
         };
         Ok(())
@@ -223,14 +299,25 @@ impl Connection {
         }
     }
 
+    /// The number of frames from the other side which were dropped, see [`MessageStream::dropped`].
+    pub fn dropped(&self) -> u64 {
+        self.inner.source.dropped()
+    }
+
+    /// Drop the next frame, see [`MessageSink::skip`].
+    pub fn skip(&mut self) {
+        self.inner.skip()
+    }
+
+    /// Write a frame and flush the connection.
+    ///
+    /// The frame is send through the [`Sink`] implementation, so frames started with the sink
+    /// are finished before this frame is written. As this borrows the connection mutably no
+    /// other frame can be started until the returned future completes.
     pub async fn write_message(&mut self, data: &[u8]) -> Result<(), IoError> {
-        self.inner.source.flush().await?;
-        let len = u32::try_from(data.len())
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-        let len = len.to_le_bytes();
-        self.inner.source.write_all(&len).await?;
-        self.inner.source.write_all(data).await?;
-        self.inner.source.flush().await
+        SinkExt::send(self, data.to_vec())
+            .await
+            .map_err(|e| e.downcast::<IoError>().unwrap_or_else(IoError::other))
     }
 }
 
@@ -271,3 +358,40 @@ impl Sink<Vec<u8>> for Connection {
         this.inner.poll_close(cx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// A connection and a stream reading the frames written to it.
+    async fn pair() -> (Connection, MessageStream<TcpStream>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stream, accepted) = tokio::join!(TcpStream::connect(addr), listener.accept());
+        (
+            Connection::new(stream.unwrap()),
+            MessageStream::new(accepted.unwrap().0),
+        )
+    }
+
+    #[tokio::test]
+    async fn write_message_and_sink_keep_frame_order() {
+        let (mut connection, mut stream) = pair().await;
+
+        connection.write_message(&[0]).await.unwrap();
+        connection.send(vec![1]).await.unwrap();
+        // A frame started with the sink but not yet flushed is finished first.
+        connection.feed(vec![2, 2]).await.unwrap();
+        connection.write_message(&[3, 3, 3]).await.unwrap();
+        connection.send(vec![4]).await.unwrap();
+        connection.write_message(&[]).await.unwrap();
+
+        for expected in [&[0][..], &[1], &[2, 2], &[3, 3, 3], &[4], &[]] {
+            assert_eq!(stream.next().await.unwrap().unwrap(), expected);
+        }
+        assert_eq!(stream.dropped(), 0);
+    }
+}
@@ -3,7 +3,7 @@ use std::{
     net::SocketAddr,
     pin::Pin,
     task::{Context, Poll},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use futures::{stream::FusedStream, Future, FutureExt, Stream, StreamExt};
@@ -12,16 +12,50 @@ use tokio::{net::TcpStream, time::Sleep};
 
 use super::Connection;
 
+/// How often failing to connect is logged while the server stays unreachable.
+const FAILURE_LOG_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Limits logging of repeated failures to connect so a server which is down for a long time
+/// doesn't flood the log.
+#[derive(Default)]
+struct FailureLog {
+    last: Option<Instant>,
+}
+
+impl FailureLog {
+    /// Returns whether a failure at `now` should be logged, the first failure is always logged
+    /// and after that at most one per interval.
+    fn should_log(&mut self, now: Instant) -> bool {
+        if self
+            .last
+            .is_some_and(|x| now.duration_since(x) < FAILURE_LOG_INTERVAL)
+        {
+            return false;
+        }
+        self.last = Some(now);
+        true
+    }
+
+    fn reset(&mut self) {
+        self.last = None;
+    }
+}
+
 pub enum OutgoingConnectionState {
     Start,
     Waiting(Pin<Box<Sleep>>),
     Connecting(Pin<Box<dyn Future<Output = Result<TcpStream>>>>),
     Connected(Pin<Box<Connection>>),
+    /// The maximum number of retries was reached.
+    Exhausted,
 }
 
 pub struct OutgoingConnection {
     connection: OutgoingConnectionState,
     address: Option<SocketAddr>,
+    max_retries: Option<u32>,
+    retries: u32,
+    failures: FailureLog,
 }
 
 impl OutgoingConnection {
@@ -29,6 +63,55 @@ impl OutgoingConnection {
         OutgoingConnection {
             connection: OutgoingConnectionState::Start,
             address,
+            max_retries: None,
+            retries: 0,
+            failures: FailureLog::default(),
+        }
+    }
+
+    /// Create a connection which stops retrying after `max` failed attempts to connect in a row.
+    /// The stream returns `None` once the limit is reached.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero, at least one attempt to connect is always made.
+    pub fn new_with_limit(address: SocketAddr, max: u32) -> Self {
+        assert!(
+            max > 0,
+            "the maximum number of retries must be at least one"
+        );
+        OutgoingConnection {
+            max_retries: Some(max),
+            ..Self::new(Some(address))
+        }
+    }
+
+    /// Find the address of a server advertised over mdns.
+    #[cfg(feature = "mdns")]
+    pub async fn discover_mdns() -> anyhow::Result<SocketAddr> {
+        crate::mdns::discover().await
+    }
+
+    /// Whether the connection to the server is currently open.
+    pub fn is_connected(&self) -> bool {
+        matches!(self.connection, OutgoingConnectionState::Connected(_))
+    }
+
+    /// The number of failed attempts to connect since the last successfull connection.
+    pub fn retry_count(&self) -> u32 {
+        self.retries
+    }
+
+    /// Wait before trying to connect again or give up if the maximum number of retries is
+    /// reached.
+    fn retry(&mut self) {
+        self.retries += 1;
+        if self.max_retries.is_some_and(|max| self.retries >= max) {
+            error!("giving up connecting after {} attempts", self.retries);
+            self.connection = OutgoingConnectionState::Exhausted;
+        } else {
+            let wait = tokio::time::sleep(Duration::from_secs_f32(0.5));
+            self.connection = OutgoingConnectionState::Waiting(Box::pin(wait));
         }
     }
 
@@ -50,7 +133,7 @@ impl OutgoingConnection {
 
 impl FusedStream for OutgoingConnection {
     fn is_terminated(&self) -> bool {
-        false
+        matches!(self.connection, OutgoingConnectionState::Exhausted)
     }
 }
 
@@ -58,7 +141,7 @@ impl Stream for OutgoingConnection {
     type Item = Vec<u8>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let mut this: &mut Self = &mut *self;
+        let this: &mut Self = &mut *self;
 
         loop {
             match this.connection {
@@ -80,18 +163,34 @@ impl Stream for OutgoingConnection {
                     Poll::Ready(Ok(x)) => {
                         if let Err(e) = x.set_nodelay(true) {
                             error!("error setting connection to nodelay {e}");
-                            let wait = tokio::time::sleep(Duration::from_secs_f32(0.5));
-                            this.connection = OutgoingConnectionState::Waiting(Box::pin(wait));
+                            this.retry();
                         } else {
+                            if this.retries > 0 {
+                                info!(
+                                    "connected to outgoing server after {} attempts",
+                                    this.retries + 1
+                                );
+                            }
+                            this.retries = 0;
+                            this.failures.reset();
                             let connection = Connection::new(x);
                             this.connection =
                                 OutgoingConnectionState::Connected(Box::pin(connection));
                         }
                     }
                     Poll::Ready(Err(e)) => {
-                        error!("error connecting to outgoing server {}", e);
-                        let wait = tokio::time::sleep(Duration::from_secs_f32(0.5));
-                        this.connection = OutgoingConnectionState::Waiting(Box::pin(wait));
+                        if this.failures.should_log(Instant::now()) {
+                            if this.retries == 0 {
+                                error!("error connecting to outgoing server {}", e);
+                            } else {
+                                error!(
+                                    "still failing to connect to outgoing server ({} attempts): {}",
+                                    this.retries + 1,
+                                    e
+                                );
+                            }
+                        }
+                        this.retry();
                     }
                     Poll::Pending => return Poll::Pending,
                 },
@@ -109,7 +208,45 @@ impl Stream for OutgoingConnection {
                     Poll::Ready(Some(Ok(x))) => return Poll::Ready(Some(x)),
                     Poll::Pending => return Poll::Pending,
                 },
+                OutgoingConnectionState::Exhausted => return Poll::Ready(None),
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// An address on which connections are refused.
+    async fn refusing_address() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    #[tokio::test]
+    async fn stream_ends_after_max_retries() {
+        tokio::time::pause();
+        let mut connection = OutgoingConnection::new_with_limit(refusing_address().await, 3);
+        assert!(!connection.is_terminated());
+        assert_eq!(connection.next().await, None);
+        assert_eq!(connection.retry_count(), 3);
+        assert!(connection.is_terminated());
+        assert!(!connection.is_connected());
+    }
+
+    #[tokio::test]
+    async fn single_retry_ends_after_first_attempt() {
+        let mut connection = OutgoingConnection::new_with_limit(refusing_address().await, 1);
+        assert_eq!(connection.next().await, None);
+        assert_eq!(connection.retry_count(), 1);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "at least one")]
+    async fn zero_retries_is_rejected() {
+        OutgoingConnection::new_with_limit(refusing_address().await, 0);
+    }
+}
@@ -1,61 +1,199 @@
 use std::{
+    collections::VecDeque,
+    net::SocketAddr,
     pin::Pin,
     result::Result as StdResult,
     task::{Context, Poll},
+    time::Instant,
 };
 
 use futures::{stream::FusedStream, Sink, Stream};
-use log::{error, info, trace};
+use log::{error, info, trace, warn};
 use tokio::net::TcpListener;
 
 use super::Connection;
+use crate::msg::server::ConnectionStats;
+
+/// Information about a connection in a [`ConnectionPool`].
+#[derive(Debug, Clone)]
+pub struct ConnectionMeta {
+    pub remote_addr: SocketAddr,
+    pub connected_at: Instant,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+}
+
+impl ConnectionMeta {
+    fn new(remote_addr: SocketAddr) -> Self {
+        ConnectionMeta {
+            remote_addr,
+            connected_at: Instant::now(),
+            bytes_sent: 0,
+            bytes_recv: 0,
+        }
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        ConnectionStats {
+            remote_addr: self.remote_addr.to_string(),
+            connected_secs: self.connected_at.elapsed().as_secs(),
+            bytes_sent: self.bytes_sent,
+            bytes_recv: self.bytes_recv,
+        }
+    }
+}
+
+/// A connection in a [`ConnectionPool`] with the frames still waiting to be send to it.
+struct Client {
+    connection: Pin<Box<Connection>>,
+    meta: ConnectionMeta,
+    queue: VecDeque<Vec<u8>>,
+}
+
+impl Client {
+    /// Queue a frame, dropping the oldest queued frame if the queue is full. Returns whether a
+    /// frame was dropped.
+    fn push(&mut self, frame: Vec<u8>, queue_len: usize) -> bool {
+        let full = self.queue.len() >= queue_len;
+        if full {
+            // Drop the oldest frame, it is the next to be send so skipping its sequence number
+            // puts the gap where the frame would have been.
+            self.queue.pop_front();
+            self.connection.skip();
+            warn!(
+                "connection from {} can't keep up, dropping a frame",
+                self.meta.remote_addr
+            );
+        }
+        self.queue.push_back(frame);
+        full
+    }
+}
 
 pub struct ConnectionPool {
     listener: TcpListener,
-    connections: Vec<Pin<Box<Connection>>>,
-    send: Option<(usize, Vec<u8>)>,
+    connections: Vec<Client>,
+    max_connections: Option<usize>,
+    queue_len: usize,
+    dropped: u64,
+    /// The connection the last frame was recieved from.
+    last: Option<SocketAddr>,
 }
 
 impl ConnectionPool {
+    /// The default number of frames queued for a connection before frames are dropped.
+    pub const DEFAULT_QUEUE_LEN: usize = 64;
+
     pub fn new(listener: TcpListener) -> Self {
         ConnectionPool {
             listener,
             connections: Vec::new(),
-            send: None,
+            max_connections: None,
+            queue_len: Self::DEFAULT_QUEUE_LEN,
+            dropped: 0,
+            last: None,
+        }
+    }
+
+    /// The number of frames which were never delivered to a connection, either because the
+    /// connection fell too far behind or because sending to it failed.
+    pub fn dropped_count(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Set the maximum number of connections, new connections are rejected once the limit is
+    /// reached. `None`, the default, allows any number of connections.
+    pub fn set_max_connections(&mut self, max: Option<usize>) {
+        self.max_connections = max;
+    }
+
+    /// Set the number of frames which are queued for a connection which can't keep up. Once the
+    /// queue is full the oldest frame is dropped, which the client sees as a gap in the sequence
+    /// numbers. A slow connection thus never holds up the other connections.
+    pub fn set_queue_len(&mut self, len: usize) {
+        self.queue_len = len.max(1);
+    }
+
+    /// Returns the information of all the current connections.
+    pub fn connections(&self) -> impl Iterator<Item = &ConnectionMeta> {
+        self.connections.iter().map(|x| &x.meta)
+    }
+
+    pub fn connection_count(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// Queue a frame for only the connection the last frame returned by the stream was recieved
+    /// from, used to reply to requests. The frame is dropped if that connection was closed.
+    ///
+    /// The frame is send the next time the pool is flushed or polled.
+    pub fn reply(&mut self, frame: Vec<u8>) {
+        let queue_len = self.queue_len;
+        let client = self
+            .connections
+            .iter_mut()
+            .find(|x| Some(x.meta.remote_addr) == self.last);
+        match client {
+            Some(client) => {
+                if client.push(frame, queue_len) {
+                    self.dropped += 1;
+                }
+            }
+            None => warn!("connection closed before it could be replied to"),
         }
     }
 
+    fn remove(&mut self, idx: usize) {
+        let client = self.connections.swap_remove(idx);
+        self.dropped += client.queue.len() as u64;
+        let meta = client.meta;
+        info!(
+            "connection from {} closed after {:?}, sent {} bytes, recieved {} bytes",
+            meta.remote_addr,
+            meta.connected_at.elapsed(),
+            meta.bytes_sent,
+            meta.bytes_recv
+        );
+    }
+
+    /// Start sending queued frames to every connection which is ready. Returns ready once all
+    /// queues are empty.
     fn poll_flush_out(&mut self, cx: &mut Context<'_>) -> Poll<()> {
         trace!("ConnectionPool::poll_flush_out");
-        loop {
-            if let Some((idx, data)) = self.send.as_mut() {
-                match self.connections[*idx].as_mut().poll_ready(cx) {
-                    Poll::Ready(Err(e)) => {
-                        error!("error sending to connection: {}", e);
-                        self.connections.swap_remove(*idx);
-                        if *idx == 0 {
-                            self.send = None;
-                        } else {
-                            *idx -= 1;
-                        }
-                    }
+        let mut ready = true;
+        // reverse to make swap remove work
+        for i in (0..self.connections.len()).rev() {
+            let client = &mut self.connections[i];
+            while !client.queue.is_empty() {
+                match client.connection.as_mut().poll_ready(cx) {
                     Poll::Ready(Ok(())) => {
-                        if let Err(e) = self.connections[*idx].as_mut().start_send(data.clone()) {
+                        let data = client.queue.pop_front().unwrap();
+                        let len = data.len() as u64;
+                        if let Err(e) = client.connection.as_mut().start_send(data) {
                             error!("error sending to connection: {}", e);
-                            self.connections.swap_remove(*idx);
-                        }
-                        if *idx == 0 {
-                            self.send = None;
-                        } else {
-                            *idx -= 1;
+                            self.dropped += 1;
+                            self.remove(i);
+                            break;
                         }
+                        client.meta.bytes_sent += len;
+                    }
+                    Poll::Ready(Err(e)) => {
+                        error!("error sending to connection: {}", e);
+                        self.remove(i);
+                        break;
+                    }
+                    Poll::Pending => {
+                        ready = false;
+                        break;
                     }
-                    Poll::Pending => return Poll::Pending,
                 }
-            } else {
-                return Poll::Ready(());
             }
         }
+        if ready {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
     }
 }
 
@@ -73,15 +211,33 @@ impl Stream for ConnectionPool {
 
         trace!("ConnectionPoll::poll_next");
 
+        // Continue sending to connections which couldn't keep up before.
+        let _ = this.poll_flush_out(cx);
+
         loop {
             match this.listener.poll_accept(cx) {
                 Poll::Ready(Ok((x, addr))) => {
+                    if this
+                        .max_connections
+                        .is_some_and(|max| this.connections.len() >= max)
+                    {
+                        warn!(
+                            "rejecting connection from {}, maximum of {} connections reached",
+                            addr,
+                            this.connections.len()
+                        );
+                        continue;
+                    }
                     info!("new connection from {}", addr);
                     if let Err(e) = x.set_nodelay(true) {
                         error!("error setting no delay for connection {e}");
                         continue;
                     }
-                    this.connections.push(Box::pin(Connection::new(x)));
+                    this.connections.push(Client {
+                        connection: Box::pin(Connection::new(x)),
+                        meta: ConnectionMeta::new(addr),
+                        queue: VecDeque::new(),
+                    });
                     continue;
                 }
                 Poll::Ready(Err(e)) => {
@@ -92,15 +248,19 @@ impl Stream for ConnectionPool {
 
             // reverse to make swap remove work
             for i in (0..this.connections.len()).rev() {
-                match this.connections[i].as_mut().poll_next(cx) {
-                    Poll::Ready(Some(Ok(x))) => return Poll::Ready(Some(x)),
+                match this.connections[i].connection.as_mut().poll_next(cx) {
+                    Poll::Ready(Some(Ok(x))) => {
+                        this.connections[i].meta.bytes_recv += x.len() as u64;
+                        this.last = Some(this.connections[i].meta.remote_addr);
+                        return Poll::Ready(Some(x));
+                    }
                     Poll::Ready(Some(Err(e))) => {
                         error!("error from connection {:?}", e);
-                        this.connections.swap_remove(i);
+                        this.remove(i);
                     }
                     Poll::Ready(None) => {
                         info!("connection quit");
-                        this.connections.swap_remove(i);
+                        this.remove(i);
                     }
                     Poll::Pending => {}
                 }
@@ -119,14 +279,18 @@ impl Sink<Vec<u8>> for ConnectionPool {
         cx: &mut Context<'_>,
     ) -> Poll<StdResult<(), Self::Error>> {
         trace!("ConnectionPool::poll_ready");
-        self.poll_flush_out(cx).map(Ok)
+        // Frames are queued per connection so the pool is always ready.
+        let _ = self.poll_flush_out(cx);
+        Poll::Ready(Ok(()))
     }
 
     fn start_send(mut self: Pin<&mut Self>, item: Vec<u8>) -> StdResult<(), Self::Error> {
         trace!("ConnectionPool::start_send");
         let this: &mut Self = &mut *self;
-        if !this.connections.is_empty() {
-            this.send = Some((this.connections.len() - 1, item));
+        for client in this.connections.iter_mut() {
+            if client.push(item.clone(), this.queue_len) {
+                this.dropped += 1;
+            }
         }
         Ok(())
     }
@@ -137,22 +301,19 @@ impl Sink<Vec<u8>> for ConnectionPool {
     ) -> Poll<StdResult<(), Self::Error>> {
         trace!("ConnectionPool::poll_flush");
         let this: &mut Self = &mut *self;
-        match this.poll_flush_out(cx) {
-            Poll::Pending => Poll::Pending,
-            Poll::Ready(_) => {
-                for c in (0..this.connections.len()).rev() {
-                    match this.connections[c].as_mut().poll_flush(cx) {
-                        Poll::Ready(Ok(())) => {}
-                        Poll::Pending => return Poll::Pending,
-                        Poll::Ready(Err(e)) => {
-                            error!("error connection {e}");
-                            this.connections.swap_remove(c);
-                        }
-                    }
+        // Flushing doesn't wait for connections which can't keep up, their frames stay queued
+        // and are send the next time the pool is polled.
+        let _ = this.poll_flush_out(cx);
+        for c in (0..this.connections.len()).rev() {
+            match this.connections[c].connection.as_mut().poll_flush(cx) {
+                Poll::Ready(Ok(())) | Poll::Pending => {}
+                Poll::Ready(Err(e)) => {
+                    error!("error connection {e}");
+                    this.remove(c);
                 }
-                return Poll::Ready(Ok(()));
             }
         }
+        Poll::Ready(Ok(()))
     }
 
     fn poll_close(
@@ -164,14 +325,14 @@ impl Sink<Vec<u8>> for ConnectionPool {
             Poll::Pending => Poll::Pending,
             Poll::Ready(_) => {
                 for c in (0..this.connections.len()).rev() {
-                    match this.connections[c].as_mut().poll_close(cx) {
+                    match this.connections[c].connection.as_mut().poll_close(cx) {
                         Poll::Ready(Ok(())) => {
-                            this.connections.pop();
+                            this.remove(c);
                         }
                         Poll::Pending => return Poll::Pending,
                         Poll::Ready(Err(e)) => {
                             error!("error connection {e}");
-                            this.connections.pop();
+                            this.remove(c);
                         }
                     }
                 }
@@ -180,3 +341,146 @@ impl Sink<Vec<u8>> for ConnectionPool {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::{SinkExt, StreamExt};
+    use tokio::net::TcpStream;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn counters() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut pool = ConnectionPool::new(listener);
+
+        let mut client = Connection::new(TcpStream::connect(addr).await.unwrap());
+        client.write_message(b"hello").await.unwrap();
+        assert_eq!(pool.next().await.unwrap(), b"hello");
+        assert_eq!(pool.connection_count(), 1);
+        let meta = pool.connections().next().unwrap();
+        assert_eq!(meta.bytes_recv, 5);
+        assert_eq!(meta.bytes_sent, 0);
+
+        pool.send(b"world!".to_vec()).await.unwrap();
+        pool.send(b"again".to_vec()).await.unwrap();
+        assert_eq!(client.next().await.unwrap().unwrap(), b"world!");
+        assert_eq!(client.next().await.unwrap().unwrap(), b"again");
+        let meta = pool.connections().next().unwrap();
+        assert_eq!(meta.bytes_sent, 11);
+        assert_eq!(meta.stats().bytes_sent, 11);
+        assert_eq!(meta.stats().bytes_recv, 5);
+
+        // Closing the client removes the connection the next time the pool is polled.
+        drop(client);
+        let next = tokio::time::timeout(Duration::from_millis(100), pool.next()).await;
+        assert!(next.is_err());
+        assert_eq!(pool.connection_count(), 0);
+        assert_eq!(pool.dropped_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn slow_connection_drops_frames() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut pool = ConnectionPool::new(listener);
+        pool.set_queue_len(4);
+
+        let mut client = Connection::new(TcpStream::connect(addr).await.unwrap());
+        client.write_message(b"hello").await.unwrap();
+        pool.next().await.unwrap();
+
+        // The client doesn't read so the socket buffers fill up and frames start being dropped.
+        let frame = |i: u64| {
+            let mut frame = vec![0u8; 1 << 20];
+            frame[..8].copy_from_slice(&i.to_le_bytes());
+            frame
+        };
+        let mut sent = 0;
+        while pool.dropped_count() == 0 {
+            assert!(sent < 1024, "no frames dropped");
+            pool.send(frame(sent)).await.unwrap();
+            sent += 1;
+        }
+        for _ in 0..8 {
+            pool.send(frame(sent)).await.unwrap();
+            sent += 1;
+        }
+
+        let reader = tokio::spawn(async move {
+            let mut recieved = 0;
+            let mut last = None;
+            while let Some(frame) = client.next().await {
+                let i = u64::from_le_bytes(frame.unwrap()[..8].try_into().unwrap());
+                assert!(last.is_none_or(|x| x < i));
+                last = Some(i);
+                recieved += 1;
+            }
+            (recieved, last, client.dropped())
+        });
+        pool.close().await.unwrap();
+
+        let (recieved, last, dropped) = reader.await.unwrap();
+        assert_eq!(last, Some(sent - 1));
+        assert_eq!(dropped, pool.dropped_count());
+        assert_eq!(recieved + dropped, sent);
+    }
+
+    #[tokio::test]
+    async fn reply_is_only_send_to_sender() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut pool = ConnectionPool::new(listener);
+
+        let mut a = Connection::new(TcpStream::connect(addr).await.unwrap());
+        let mut b = Connection::new(TcpStream::connect(addr).await.unwrap());
+        b.write_message(b"first").await.unwrap();
+        assert_eq!(pool.next().await.unwrap(), b"first");
+        a.write_message(b"request").await.unwrap();
+        assert_eq!(pool.next().await.unwrap(), b"request");
+
+        pool.reply(b"reply".to_vec());
+        pool.send(b"everyone".to_vec()).await.unwrap();
+        assert_eq!(a.next().await.unwrap().unwrap(), b"reply");
+        assert_eq!(a.next().await.unwrap().unwrap(), b"everyone");
+        assert_eq!(b.next().await.unwrap().unwrap(), b"everyone");
+    }
+
+    #[tokio::test]
+    async fn connections_over_maximum_are_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut pool = ConnectionPool::new(listener);
+        pool.set_max_connections(Some(2));
+
+        let mut a = Connection::new(TcpStream::connect(addr).await.unwrap());
+        let mut b = Connection::new(TcpStream::connect(addr).await.unwrap());
+        a.write_message(b"a").await.unwrap();
+        assert_eq!(pool.next().await.unwrap(), b"a");
+        b.write_message(b"b").await.unwrap();
+        assert_eq!(pool.next().await.unwrap(), b"b");
+        assert_eq!(pool.connection_count(), 2);
+
+        // The third connection is closed as soon as it is accepted.
+        let mut c = Connection::new(TcpStream::connect(addr).await.unwrap());
+        let next = tokio::time::timeout(Duration::from_millis(100), pool.next()).await;
+        assert!(next.is_err());
+        assert!(matches!(c.next().await, None | Some(Err(_))));
+        assert_eq!(pool.connection_count(), 2);
+
+        // Once a connection closes there is room for a new one.
+        drop(a);
+        let next = tokio::time::timeout(Duration::from_millis(100), pool.next()).await;
+        assert!(next.is_err());
+        assert_eq!(pool.connection_count(), 1);
+        let mut d = Connection::new(TcpStream::connect(addr).await.unwrap());
+        d.write_message(b"d").await.unwrap();
+        assert_eq!(pool.next().await.unwrap(), b"d");
+        assert_eq!(pool.connection_count(), 2);
+        b.write_message(b"b").await.unwrap();
+        assert_eq!(pool.next().await.unwrap(), b"b");
+    }
+}
@@ -0,0 +1,360 @@
+use std::{
+    net::SocketAddr,
+    pin::Pin,
+    result::Result as StdResult,
+    task::{Context, Poll},
+};
+
+use futures::{stream::FusedStream, Sink, Stream};
+use log::{error, info, trace};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc::{self, UnboundedReceiver},
+};
+use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+
+use crate::{msg::GpsMsg, parse::ParseData};
+
+type WebSocket = WebSocketStream<TcpStream>;
+
+/// A pool of websocket connections with the same interface as [`super::ConnectionPool`].
+///
+/// Frames send to the pool are parsed and every message is send to the clients as a json text
+/// message. Text messages from clients are parsed as json messages and binary messages are passed
+/// on as is.
+pub struct WebSocketPool {
+    accepted: UnboundedReceiver<(WebSocket, SocketAddr)>,
+    connections: Vec<(Pin<Box<WebSocket>>, SocketAddr)>,
+    /// The connection index and index of the next message to send to that connection.
+    send: Option<(usize, usize, Vec<Message>)>,
+}
+
+impl WebSocketPool {
+    /// Create a pool accepting connections from the given listener.
+    ///
+    /// Spawns a task which performs the websocket handshake of new connections.
+    pub fn new(listener: TcpListener) -> Self {
+        let (send, accepted) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                let (stream, addr) = match listener.accept().await {
+                    Ok(x) => x,
+                    Err(e) => {
+                        error!("error accepting websocket connection {e}");
+                        continue;
+                    }
+                };
+                let send = send.clone();
+                tokio::spawn(async move {
+                    match tokio_tungstenite::accept_async(stream).await {
+                        Ok(x) => {
+                            send.send((x, addr)).ok();
+                        }
+                        Err(e) => error!("websocket handshake with {addr} failed: {e}"),
+                    }
+                });
+            }
+        });
+        WebSocketPool {
+            accepted,
+            connections: Vec::new(),
+            send: None,
+        }
+    }
+
+    pub fn connection_count(&self) -> usize {
+        self.connections.len()
+    }
+
+    fn remove(&mut self, idx: usize) {
+        let (_, addr) = self.connections.swap_remove(idx);
+        info!("websocket connection from {addr} closed");
+    }
+
+    fn encode(frame: &[u8]) -> Vec<Message> {
+        GpsMsg::parse_frame(frame)
+            .filter_map(|x| match x {
+                Ok(x) => Some(x),
+                Err(e) => {
+                    trace!("not sending unparsable message to websocket: {e}");
+                    None
+                }
+            })
+            .filter_map(|x| match serde_json::to_string(&x) {
+                Ok(x) => Some(Message::Text(x)),
+                Err(e) => {
+                    error!("error serializing message {e}");
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn decode(msg: Message) -> Option<Vec<u8>> {
+        match msg {
+            Message::Binary(x) => Some(x),
+            Message::Text(x) => {
+                let msg = match serde_json::from_str::<GpsMsg>(&x) {
+                    Ok(x) => x,
+                    Err(e) => {
+                        error!("error deserializing websocket message {e}");
+                        return None;
+                    }
+                };
+                let mut buffer = Vec::new();
+                msg.parse_write(&mut buffer).ok()?;
+                Some(buffer)
+            }
+            _ => None,
+        }
+    }
+
+    /// Move on to the next connection which needs to recieve the current messages.
+    fn next_connection(&mut self) {
+        match self.send.as_mut() {
+            Some((0, _, _)) | None => self.send = None,
+            Some((idx, msg, _)) => {
+                *idx -= 1;
+                *msg = 0;
+            }
+        }
+    }
+
+    fn poll_flush_out(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        loop {
+            let (idx, msg, data) = match self.send.as_mut() {
+                Some((idx, msg, data)) => (*idx, *msg, data),
+                None => return Poll::Ready(()),
+            };
+            if msg >= data.len() {
+                self.next_connection();
+                continue;
+            }
+            let data = data[msg].clone();
+            match self.connections[idx].0.as_mut().poll_ready(cx) {
+                Poll::Ready(Ok(())) => {
+                    if let Err(e) = self.connections[idx].0.as_mut().start_send(data) {
+                        error!("error sending to websocket connection: {}", e);
+                        self.remove(idx);
+                        self.next_connection();
+                    } else {
+                        self.send.as_mut().unwrap().1 += 1;
+                    }
+                }
+                Poll::Ready(Err(e)) => {
+                    error!("error sending to websocket connection: {}", e);
+                    self.remove(idx);
+                    self.next_connection();
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl FusedStream for WebSocketPool {
+    fn is_terminated(&self) -> bool {
+        false
+    }
+}
+
+impl Stream for WebSocketPool {
+    type Item = Vec<u8>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this: &mut Self = &mut self;
+
+        while let Poll::Ready(Some((x, addr))) = this.accepted.poll_recv(cx) {
+            info!("new websocket connection from {}", addr);
+            this.connections.push((Box::pin(x), addr));
+        }
+
+        // reverse to make swap remove work
+        for i in (0..this.connections.len()).rev() {
+            loop {
+                match this.connections[i].0.as_mut().poll_next(cx) {
+                    Poll::Ready(Some(Ok(Message::Close(_)))) | Poll::Ready(None) => {
+                        this.remove(i);
+                        break;
+                    }
+                    Poll::Ready(Some(Ok(x))) => {
+                        if let Some(x) = WebSocketPool::decode(x) {
+                            return Poll::Ready(Some(x));
+                        }
+                    }
+                    Poll::Ready(Some(Err(e))) => {
+                        error!("error from websocket connection {:?}", e);
+                        this.remove(i);
+                        break;
+                    }
+                    Poll::Pending => break,
+                }
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+impl Sink<Vec<u8>> for WebSocketPool {
+    type Error = ();
+
+    fn poll_ready(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<StdResult<(), Self::Error>> {
+        self.poll_flush_out(cx).map(Ok)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Vec<u8>) -> StdResult<(), Self::Error> {
+        let this: &mut Self = &mut self;
+        if !this.connections.is_empty() {
+            this.send = Some((this.connections.len() - 1, 0, WebSocketPool::encode(&item)));
+        }
+        Ok(())
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<StdResult<(), Self::Error>> {
+        let this: &mut Self = &mut self;
+        if this.poll_flush_out(cx).is_pending() {
+            return Poll::Pending;
+        }
+        for c in (0..this.connections.len()).rev() {
+            match this.connections[c].0.as_mut().poll_flush(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => {
+                    error!("error websocket connection {e}");
+                    this.remove(c);
+                }
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<StdResult<(), Self::Error>> {
+        let this: &mut Self = &mut self;
+        if this.poll_flush_out(cx).is_pending() {
+            return Poll::Pending;
+        }
+        for c in (0..this.connections.len()).rev() {
+            match this.connections[c].0.as_mut().poll_close(cx) {
+                Poll::Ready(Ok(())) => this.remove(c),
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => {
+                    error!("error websocket connection {e}");
+                    this.remove(c);
+                }
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::{future, SinkExt, StreamExt};
+    use tokio::{
+        io::{AsyncRead, AsyncWrite},
+        time::timeout,
+    };
+
+    use super::*;
+    use crate::msg::{
+        ubx::nav::{Eoe, Nav},
+        Ubx,
+    };
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    async fn pool() -> (WebSocketPool, SocketAddr) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        (WebSocketPool::new(listener), addr)
+    }
+
+    async fn connect(addr: SocketAddr) -> WebSocketStream<impl AsyncRead + AsyncWrite + Unpin> {
+        tokio_tungstenite::connect_async(format!("ws://{addr}"))
+            .await
+            .unwrap()
+            .0
+    }
+
+    fn eoe() -> GpsMsg {
+        GpsMsg::Ubx(Ubx::Nav(Nav::Eoe(Eoe { i_tow: 1000 })))
+    }
+
+    #[tokio::test]
+    async fn client_messages_are_decoded() {
+        let (mut pool, addr) = pool().await;
+        let mut client = connect(addr).await;
+        let frame = eoe().parse_to_vec().unwrap();
+
+        client
+            .send(Message::Text("not a message".to_string()))
+            .await
+            .unwrap();
+        client
+            .send(Message::Text(serde_json::to_string(&eoe()).unwrap()))
+            .await
+            .unwrap();
+        client
+            .send(Message::Binary(vec![0xb5, 0x62]))
+            .await
+            .unwrap();
+
+        let next = timeout(TIMEOUT, pool.next()).await.unwrap();
+        assert_eq!(next, Some(frame));
+        let next = timeout(TIMEOUT, pool.next()).await.unwrap();
+        assert_eq!(next, Some(vec![0xb5, 0x62]));
+        assert_eq!(pool.connection_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn frames_are_send_as_json_per_message() {
+        let (mut pool, addr) = pool().await;
+        let mut client = connect(addr).await;
+
+        // The connection is added to the pool once the pool is polled after the handshake.
+        client.send(Message::Binary(vec![0])).await.unwrap();
+        timeout(TIMEOUT, pool.next()).await.unwrap();
+
+        let mut frame = eoe().parse_to_vec().unwrap();
+        frame.extend_from_within(..);
+        pool.send(frame).await.unwrap();
+
+        let expected = Message::Text(serde_json::to_string(&eoe()).unwrap());
+        for _ in 0..2 {
+            let msg = timeout(TIMEOUT, client.next()).await.unwrap();
+            assert_eq!(msg.unwrap().unwrap(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn closed_connections_are_removed() {
+        let (mut pool, addr) = pool().await;
+        let mut client = connect(addr).await;
+        client.send(Message::Binary(vec![0])).await.unwrap();
+        timeout(TIMEOUT, pool.next()).await.unwrap();
+        assert_eq!(pool.connection_count(), 1);
+
+        client.close(None).await.unwrap();
+        let removed = future::poll_fn(|cx| {
+            let _ = pool.poll_next_unpin(cx);
+            if pool.connection_count() == 0 {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        });
+        timeout(TIMEOUT, removed).await.unwrap();
+    }
+}
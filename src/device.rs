@@ -0,0 +1,181 @@
+//! Opening serial devices and detecting them being plugged in and removed.
+
+use std::{
+    collections::VecDeque,
+    ffi::{CString, OsStr},
+    io,
+    os::unix::{
+        ffi::OsStrExt,
+        io::{AsRawFd, FromRawFd, OwnedFd},
+    },
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use futures::Stream;
+use log::error;
+use tokio::io::unix::AsyncFd;
+use tokio_serial::{DataBits, Parity, SerialStream, StopBits};
+
+/// The names of the device files usually created for usb serial devices.
+pub const SERIAL_PREFIXES: &[&str] = &["ttyACM", "ttyUSB"];
+
+const EVENT_HEADER_LEN: usize = std::mem::size_of::<libc::inotify_event>();
+
+/// Open a serial port with the 8N1 settings used by u-blox receivers.
+pub fn open_serial(path: &str, baud: u32) -> io::Result<SerialStream> {
+    let port = tokio_serial::new(path, baud)
+        .data_bits(DataBits::Eight)
+        .parity(Parity::None)
+        .stop_bits(StopBits::One)
+        .timeout(Duration::from_secs(1));
+    Ok(SerialStream::open(&port)?)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceEvent {
+    Added(PathBuf),
+    Removed(PathBuf),
+}
+
+/// A stream of devices appearing in and disappearing from a directory, using inotify.
+pub struct DeviceWatcher {
+    fd: AsyncFd<OwnedFd>,
+    dir: PathBuf,
+    prefixes: Vec<String>,
+    events: VecDeque<DeviceEvent>,
+}
+
+impl DeviceWatcher {
+    /// Watch `/dev` for usb serial devices.
+    pub fn new() -> io::Result<Self> {
+        Self::watch(Path::new("/dev"), SERIAL_PREFIXES)
+    }
+
+    /// Watch a directory for entries whose name starts with one of the given prefixes.
+    pub fn watch(dir: &Path, prefixes: &[&str]) -> io::Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let path = CString::new(dir.as_os_str().as_bytes())?;
+        let mask = libc::IN_CREATE | libc::IN_DELETE | libc::IN_MOVED_TO | libc::IN_MOVED_FROM;
+        if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), path.as_ptr(), mask) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(DeviceWatcher {
+            fd: AsyncFd::new(fd)?,
+            dir: dir.to_path_buf(),
+            prefixes: prefixes.iter().map(|x| x.to_string()).collect(),
+            events: VecDeque::new(),
+        })
+    }
+
+    fn read_events(&mut self, buffer: &[u8]) {
+        let mut b = buffer;
+        while b.len() >= EVENT_HEADER_LEN {
+            let event =
+                unsafe { std::ptr::read_unaligned(b.as_ptr() as *const libc::inotify_event) };
+            let end = (EVENT_HEADER_LEN + event.len as usize).min(b.len());
+            let name = &b[EVENT_HEADER_LEN..end];
+            b = &b[end..];
+
+            // The name is padded with null bytes.
+            let name = &name[..name.iter().position(|x| *x == 0).unwrap_or(name.len())];
+            let name = OsStr::from_bytes(name);
+            if !self
+                .prefixes
+                .iter()
+                .any(|x| name.as_bytes().starts_with(x.as_bytes()))
+            {
+                continue;
+            }
+
+            let path = self.dir.join(name);
+            if event.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0 {
+                self.events.push_back(DeviceEvent::Added(path));
+            } else if event.mask & (libc::IN_DELETE | libc::IN_MOVED_FROM) != 0 {
+                self.events.push_back(DeviceEvent::Removed(path));
+            }
+        }
+    }
+}
+
+impl Stream for DeviceWatcher {
+    type Item = DeviceEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this: &mut Self = &mut self;
+        let mut buffer = [0u8; 4096];
+        loop {
+            if let Some(x) = this.events.pop_front() {
+                return Poll::Ready(Some(x));
+            }
+
+            let mut guard = match ready!(this.fd.poll_read_ready(cx)) {
+                Ok(x) => x,
+                Err(e) => {
+                    error!("error waiting for inotify events: {e}");
+                    return Poll::Ready(None);
+                }
+            };
+            let res = guard.try_io(|fd| {
+                let len = unsafe {
+                    libc::read(
+                        fd.as_raw_fd(),
+                        buffer.as_mut_ptr() as *mut libc::c_void,
+                        buffer.len(),
+                    )
+                };
+                if len < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(len as usize)
+                }
+            });
+            match res {
+                Ok(Ok(len)) => this.read_events(&buffer[..len]),
+                Ok(Err(e)) => {
+                    error!("error reading inotify events: {e}");
+                    return Poll::Ready(None);
+                }
+                // Spurious wakeup, the readiness was cleared so poll again.
+                Err(_) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn matching_entries_are_reported() {
+        let dir = std::env::temp_dir().join(format!("gps-device-watch-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut watcher = DeviceWatcher::watch(&dir, SERIAL_PREFIXES).unwrap();
+
+        // Entries which don't look like a serial device are ignored.
+        std::fs::write(dir.join("ttyS0"), "").unwrap();
+        let port = dir.join("ttyACM0");
+        std::fs::write(&port, "").unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(5), watcher.next()).await;
+        assert_eq!(event.unwrap(), Some(DeviceEvent::Added(port.clone())));
+
+        std::fs::remove_file(dir.join("ttyS0")).unwrap();
+        std::fs::remove_file(&port).unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(5), watcher.next()).await;
+        assert_eq!(event.unwrap(), Some(DeviceEvent::Removed(port)));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
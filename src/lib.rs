@@ -1,9 +1,24 @@
 #![allow(dead_code)]
 
 pub mod bluetooth;
+pub mod completions;
 pub mod connection;
+pub mod device;
+pub mod logging;
+#[cfg(feature = "mdns")]
+pub mod mdns;
 pub mod msg;
 pub mod parse;
+#[cfg(feature = "rest")]
+pub mod rest;
+pub mod state;
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Seek, SeekFrom, Write},
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+};
 
 pub trait VecExt {
     fn shift(&mut self, by: usize);
@@ -18,24 +33,207 @@ impl<T: Copy> VecExt for Vec<T> {
     }
 }
 
-pub fn deamonize() -> Result<(), ()> {
-    let res = unsafe { libc::fork() };
-    match res {
-        -1 => return Err(()),
+/// Format a byte slice as a classic hex dump, 16 bytes per line with an offset
+/// column and a printable ascii column.
+pub fn hexdump(b: &[u8]) -> String {
+    use std::fmt::Write;
+
+    let mut res = String::new();
+    for (idx, line) in b.chunks(16).enumerate() {
+        if idx != 0 {
+            res.push('\n');
+        }
+        write!(res, "{:08x} ", idx * 16).unwrap();
+        for i in 0..16 {
+            if i == 8 {
+                res.push(' ');
+            }
+            match line.get(i) {
+                Some(x) => write!(res, " {:02x}", x).unwrap(),
+                None => res.push_str("   "),
+            }
+        }
+        res.push_str("  |");
+        for x in line.iter().copied() {
+            if x.is_ascii_graphic() || x == b' ' {
+                res.push(x as char);
+            } else {
+                res.push('.');
+            }
+        }
+        res.push('|');
+    }
+    res
+}
+
+/// A pidfile which is locked for as long as the process is running.
+/// The file is removed when dropped.
+pub struct PidFile {
+    file: File,
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Create and lock a pidfile. A pidfile which exists but is not locked was left behind by a
+    /// process which did not shut down cleanly and is reused.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let path = std::env::current_dir()?.join(path);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == -1 {
+            let error = io::Error::last_os_error();
+            if error.kind() == io::ErrorKind::WouldBlock {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!(
+                        "pidfile `{}` is locked by a running process",
+                        path.display()
+                    ),
+                ));
+            }
+            return Err(error);
+        }
+        Ok(PidFile { file, path })
+    }
+
+    /// Write the pid of the current process to the file.
+    pub fn write_pid(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        writeln!(self.file, "{}", std::process::id())?;
+        self.file.flush()
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+fn check(res: libc::c_int) -> io::Result<libc::c_int> {
+    if res == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(res)
+    }
+}
+
+/// Turn the current process into a deamon.
+///
+/// Forks twice to detach from the terminal, changes the working directory to `/`, and redirects
+/// stdin, stdout and stderr to `/dev/null`. Logs should be written to a log file, see
+/// [`logging::init`]. If a pidfile is given it is locked before forking and the returned guard
+/// should be kept alive until the process shuts down.
+pub fn deamonize(pidfile: Option<&Path>) -> io::Result<Option<PidFile>> {
+    let mut pidfile = pidfile.map(PidFile::create).transpose()?;
+
+    let null = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")?;
+
+    match check(unsafe { libc::fork() })? {
         0 => {}
         _ => std::process::exit(0),
     }
 
-    let res = unsafe { libc::setsid() };
-    match res {
-        -1 => return Err(()),
-        _ => {}
-    }
+    check(unsafe { libc::setsid() })?;
 
-    let res = unsafe { libc::fork() };
-    match res {
-        -1 => return Err(()),
-        0 => return Ok(()),
+    match check(unsafe { libc::fork() })? {
+        0 => {}
         _ => std::process::exit(0),
     }
+
+    std::env::set_current_dir("/")?;
+
+    check(unsafe { libc::dup2(null.as_raw_fd(), libc::STDIN_FILENO) })?;
+    check(unsafe { libc::dup2(null.as_raw_fd(), libc::STDOUT_FILENO) })?;
+    check(unsafe { libc::dup2(null.as_raw_fd(), libc::STDERR_FILENO) })?;
+
+    if let Some(x) = pidfile.as_mut() {
+        x.write_pid()?;
+    }
+
+    Ok(pidfile)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, path::PathBuf};
+
+    use super::{hexdump, PidFile};
+
+    /// The path of a pidfile in a new empty temporary directory.
+    fn pidfile_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gps-test-{}-{name}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("gps.pid")
+    }
+
+    #[test]
+    fn pidfile_holds_pid_until_dropped() {
+        let path = pidfile_path("pid");
+        let mut pidfile = PidFile::create(&path).unwrap();
+        pidfile.write_pid().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("{}\n", std::process::id())
+        );
+        drop(pidfile);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn stale_pidfile_is_reused() {
+        let path = pidfile_path("stale");
+        // Left behind by a process which didn't shut down cleanly, so nothing holds the lock.
+        std::fs::write(&path, "999999999999\n").unwrap();
+        let mut pidfile = PidFile::create(&path).unwrap();
+        pidfile.write_pid().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("{}\n", std::process::id())
+        );
+    }
+
+    #[test]
+    fn locked_pidfile_is_an_error() {
+        let path = pidfile_path("locked");
+        let pidfile = PidFile::create(&path).unwrap();
+        let e = PidFile::create(&path).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+        assert!(e.to_string().contains("locked by a running process"), "{e}");
+
+        drop(pidfile);
+        assert!(PidFile::create(&path).is_ok());
+    }
+
+    #[test]
+    fn hexdump_short() {
+        assert_eq!(
+            hexdump(b"\xb5\x62\x01\x07AB"),
+            "00000000  b5 62 01 07 41 42                                 |.b..AB|"
+        );
+    }
+
+    #[test]
+    fn hexdump_multiple_lines() {
+        let data: Vec<u8> = (0x30..0x48).collect();
+        assert_eq!(
+            hexdump(&data),
+            "00000000  30 31 32 33 34 35 36 37  38 39 3a 3b 3c 3d 3e 3f  |0123456789:;<=>?|\n\
+             00000010  40 41 42 43 44 45 46 47                           |@ABCDEFG|"
+        );
+    }
+
+    #[test]
+    fn hexdump_empty() {
+        assert_eq!(hexdump(&[]), "");
+    }
 }
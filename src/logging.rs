@@ -0,0 +1,185 @@
+//! Logging setup shared by the binaries.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::{arg, value_parser, Arg, ArgMatches};
+use env_logger::{Builder, Env, Target, WriteStyle};
+
+/// The number of rotated log files which are kept.
+const ROTATED_FILES: usize = 5;
+
+/// The maximum number of bytes of a frame included in trace logs.
+pub const TRACE_HEXDUMP_LIMIT: usize = 64;
+
+/// A log file which is moved to `PATH.1` once it grows beyond a maximum size.
+/// Older files are moved to `PATH.2` up to `PATH.5` by default, after which they are removed.
+pub struct RotatingWriter {
+    path: PathBuf,
+    max_size: u64,
+    keep: usize,
+    size: u64,
+    file: File,
+}
+
+impl RotatingWriter {
+    pub fn new(path: &Path, max_size: u64) -> io::Result<Self> {
+        Self::with_keep(path, max_size, ROTATED_FILES)
+    }
+
+    /// Create a writer which keeps the given number of rotated files instead of the default 5.
+    pub fn with_keep(path: &Path, max_size: u64, keep: usize) -> io::Result<Self> {
+        // Files are rotated by path, which has to keep working after the working directory is
+        // changed by `deamonize`.
+        let path = std::env::current_dir()?.join(path);
+        let file = OpenOptions::new().append(true).create(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingWriter {
+            path,
+            max_size,
+            keep,
+            size,
+            file,
+        })
+    }
+
+    fn rotated_path(&self, idx: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{idx}"));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            for idx in (1..self.keep).rev() {
+                let from = self.rotated_path(idx);
+                if from.exists() {
+                    std::fs::rename(from, self.rotated_path(idx + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        self.file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let len = self.file.write(buf)?;
+        self.size += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// The arguments used by [`init_from_matches`].
+pub fn args() -> [Arg<'static>; 2] {
+    [
+        arg!(
+            --"log-file" <PATH> "Write logs to the given file instead of stderr"
+        )
+        .required(false)
+        .value_parser(value_parser!(PathBuf)),
+        arg!(
+            --"log-max-size" <MB> "The size in megabytes after which the log file is rotated"
+        )
+        .required(false)
+        .requires("log-file")
+        .default_value("10")
+        .value_parser(value_parser!(u64)),
+    ]
+}
+
+/// Initialize the logger, writing to the given file or to stderr if no file is given.
+/// The log level is taken from `RUST_LOG` and defaults to `info`.
+pub fn init(log_file: Option<&Path>, max_size: u64) -> io::Result<()> {
+    let mut builder =
+        Builder::from_env(Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "info"));
+    builder.format_timestamp_millis();
+    if let Some(path) = log_file {
+        let writer = RotatingWriter::new(path, max_size)?;
+        builder
+            .target(Target::Pipe(Box::new(writer)))
+            .write_style(WriteStyle::Never)
+            // env_logger 0.9 only writes to a pipe target in test mode.
+            .is_test(true);
+    }
+    builder.try_init().map_err(io::Error::other)
+}
+
+/// Initialize the logger from the arguments returned by [`args`].
+pub fn init_from_matches(matches: &ArgMatches) -> io::Result<()> {
+    let max_size = *matches.get_one::<u64>("log-max-size").unwrap();
+    init(
+        matches.get_one::<PathBuf>("log-file").map(|x| x.as_path()),
+        max_size * 1024 * 1024,
+    )
+}
+
+/// A hex dump of the start of a frame, for including raw frames in trace logs.
+pub fn trace_hexdump(frame: &[u8]) -> String {
+    if frame.len() > TRACE_HEXDUMP_LIMIT {
+        format!(
+            "{}\n... {} more bytes",
+            crate::hexdump(&frame[..TRACE_HEXDUMP_LIMIT]),
+            frame.len() - TRACE_HEXDUMP_LIMIT
+        )
+    } else {
+        crate::hexdump(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotated_files_are_trimmed() {
+        let dir = std::env::temp_dir().join(format!("gps-test-{}-rotate", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("record.ubx");
+        let read = |suffix: &str| {
+            let mut name = path.clone().into_os_string();
+            name.push(suffix);
+            std::fs::read(name).ok()
+        };
+
+        let mut writer = RotatingWriter::with_keep(&path, 10, 2).unwrap();
+        for chunk in [b"aaaaaa", b"bbbbbb", b"cccccc", b"dddddd"] {
+            writer.write_all(chunk).unwrap();
+        }
+        writer.flush().unwrap();
+
+        assert_eq!(read("").unwrap(), b"dddddd");
+        assert_eq!(read(".1").unwrap(), b"cccccc");
+        assert_eq!(read(".2").unwrap(), b"bbbbbb");
+        assert_eq!(read(".3"), None);
+
+        // Without rotated files the full file is removed.
+        let mut writer = RotatingWriter::with_keep(&path, 10, 0).unwrap();
+        writer.write_all(b"eeeeee").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(read("").unwrap(), b"eeeeee");
+        assert_eq!(read(".1").unwrap(), b"cccccc");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
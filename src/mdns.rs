@@ -0,0 +1,166 @@
+//! Advertisement and discovery of servers over mDNS/DNS-SD.
+
+use std::{collections::HashMap, net::SocketAddr};
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+
+pub const SERVICE_TYPE: &str = "_gps-server._tcp.local.";
+/// The service type used by mobile clients, servers are advertised under both types.
+pub const UBXGPS_SERVICE_TYPE: &str = "_ubxgps._tcp.local.";
+
+/// The version of the protocol advertised in the TXT record of the service. Version 2 added the
+/// sequence number to the frame header.
+pub const PROTOCOL_VERSION: &str = "2";
+
+fn hostname() -> Result<String> {
+    let mut buffer = [0u8; 256];
+    if unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("failed to get hostname");
+    }
+    let len = buffer.iter().position(|x| *x == 0).unwrap_or(buffer.len());
+    Ok(String::from_utf8_lossy(&buffer[..len]).into_owned())
+}
+
+/// The services advertising a server hosted on the given port, one for every service type.
+fn services(name: &str, hostname: &str, port: u16) -> Result<Vec<ServiceInfo>> {
+    let mut properties = HashMap::new();
+    properties.insert("version".to_string(), PROTOCOL_VERSION.to_string());
+    properties.insert("device".to_string(), name.to_string());
+
+    [SERVICE_TYPE, UBXGPS_SERVICE_TYPE]
+        .into_iter()
+        .map(|ty| {
+            let service = ServiceInfo::new(
+                ty,
+                &format!("{hostname}-{name}"),
+                &format!("{hostname}.local."),
+                "",
+                port,
+                properties.clone(),
+            )
+            .context("invalid mdns service")?;
+            Ok(service.enable_addr_auto())
+        })
+        .collect()
+}
+
+/// An advertised server, the services are removed when dropped.
+pub struct Advertisement {
+    daemon: ServiceDaemon,
+    fullnames: Vec<String>,
+}
+
+impl Advertisement {
+    /// Advertise a server hosted on the given port. The name is added to the instance name to
+    /// distinguish multiple servers on the same host.
+    ///
+    /// The advertisement runs on a thread of the mdns daemon, which doesn't survive a fork, so a
+    /// deamon has to be advertised after [`crate::deamonize`].
+    pub fn new(name: &str, port: u16) -> Result<Self> {
+        let hostname = hostname()?;
+        let daemon = ServiceDaemon::new().context("failed to start mdns daemon")?;
+
+        let mut fullnames = Vec::new();
+        for service in services(name, &hostname, port)? {
+            let fullname = service.get_fullname().to_string();
+            daemon
+                .register(service)
+                .context("failed to register mdns service")?;
+            info!("advertising `{fullname}` over mdns");
+            fullnames.push(fullname);
+        }
+
+        Ok(Advertisement { daemon, fullnames })
+    }
+}
+
+impl Drop for Advertisement {
+    fn drop(&mut self) {
+        for fullname in &self.fullnames {
+            if let Err(e) = self.daemon.unregister(fullname) {
+                warn!("failed to unregister mdns service: {e}");
+            }
+        }
+        self.daemon.shutdown().ok();
+    }
+}
+
+/// Browse for servers and return the address of the first one found.
+pub async fn discover() -> Result<SocketAddr> {
+    let daemon = ServiceDaemon::new().context("failed to start mdns daemon")?;
+    let recv = daemon
+        .browse(SERVICE_TYPE)
+        .context("failed to browse for mdns services")?;
+
+    while let Ok(event) = recv.recv_async().await {
+        if let ServiceEvent::ServiceResolved(service) = event {
+            if let Some(addr) = service.get_addresses().iter().next() {
+                info!("discovered `{}` over mdns", service.get_fullname());
+                daemon.shutdown().ok();
+                return Ok(SocketAddr::new(*addr, service.get_port()));
+            }
+        }
+    }
+    bail!("mdns browsing stopped before a server was found")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn services_use_both_types_and_the_port() {
+        let services = services("rover", "host", 2101).unwrap();
+        let types = services.iter().map(|x| x.get_type()).collect::<Vec<_>>();
+        assert_eq!(types, ["_gps-server._tcp.local.", "_ubxgps._tcp.local."]);
+        for service in services {
+            assert_eq!(service.get_port(), 2101);
+            assert_eq!(
+                service.get_fullname(),
+                format!("host-rover.{}", service.get_type())
+            );
+            assert_eq!(
+                service.get_property_val_str("version"),
+                Some(PROTOCOL_VERSION)
+            );
+            assert_eq!(service.get_property_val_str("device"), Some("rover"));
+        }
+    }
+
+    /// Browse for the service with the given name, other servers on the network are ignored.
+    async fn resolve(ty: &str, fullname: &str) -> ServiceInfo {
+        let daemon = ServiceDaemon::new().unwrap();
+        let recv = daemon.browse(ty).unwrap();
+        while let Ok(event) = recv.recv_async().await {
+            match event {
+                ServiceEvent::ServiceResolved(x) if x.get_fullname() == fullname => {
+                    daemon.shutdown().ok();
+                    return x;
+                }
+                _ => {}
+            }
+        }
+        panic!("mdns browsing stopped before `{fullname}` was found")
+    }
+
+    #[tokio::test]
+    async fn advertised_services_are_resolved() {
+        let name = format!("test{}", std::process::id());
+        let advertisement = Advertisement::new(&name, 2101).unwrap();
+        for (ty, fullname) in [SERVICE_TYPE, UBXGPS_SERVICE_TYPE]
+            .into_iter()
+            .zip(&advertisement.fullnames)
+        {
+            let service = tokio::time::timeout(Duration::from_secs(5), resolve(ty, fullname))
+                .await
+                .expect("service not resolved");
+            assert_eq!(service.get_port(), 2101);
+            assert!(!service.get_addresses().is_empty());
+            assert_eq!(service.get_property_val_str("device"), Some(&*name));
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! A flat serialization of messages for consumers like python which don't deal well with the
+//! nested enum representation.
+
+use serde::{ser::Error, Serialize, Serializer};
+use serde_json::{Map, Value};
+
+use super::GpsMsg;
+
+/// Serializes a message as a single object with a `type` field naming the message, for example
+/// `{"type": "nav-pvt", "itow": ..., ...}` instead of `{"Ubx": {"Nav": {"Pvt": {...}}}}`.
+///
+/// The `Ubx` variant is left out of the name as it is by far the most common kind of message,
+/// other kinds are prefixed, i.e. `rtcm3` or `poll-nav-pvt`.
+/// Message data which is not an object is stored in a `value` field.
+pub struct FlatGpsMsg<'a>(pub &'a GpsMsg);
+
+impl FlatGpsMsg<'_> {
+    /// Convert a message into the flat json representation.
+    pub fn to_value(&self) -> serde_json::Result<Value> {
+        let mut value = serde_json::to_value(self.0)?;
+        let mut names = Vec::new();
+
+        // Enum variants are serialized as an object with a single capitalized key, walk down
+        // until we reach the message data.
+        loop {
+            match value {
+                Value::Object(ref mut map) if map.len() == 1 => {
+                    let key = map.keys().next().unwrap();
+                    if !key.starts_with(char::is_uppercase) {
+                        break;
+                    }
+                    let key = key.clone();
+                    value = map.remove(&key).unwrap();
+                    names.push(key);
+                }
+                Value::String(ref x) if x.starts_with(char::is_uppercase) => {
+                    names.push(x.clone());
+                    value = Value::Object(Map::new());
+                    break;
+                }
+                _ => break,
+            }
+        }
+
+        if let GpsMsg::Ubx(_) = self.0 {
+            names.remove(0);
+        } else if let GpsMsg::UbxPoll(_) = self.0 {
+            names[0] = "Poll".to_string();
+        }
+        let name = names
+            .iter()
+            .map(|x| kebab_case(x))
+            .collect::<Vec<_>>()
+            .join("-");
+
+        let mut map = match value {
+            Value::Object(x) => x,
+            x => {
+                let mut map = Map::new();
+                map.insert("value".to_string(), x);
+                map
+            }
+        };
+        map.insert("type".to_string(), Value::String(name));
+        Ok(Value::Object(map))
+    }
+}
+
+impl Serialize for FlatGpsMsg<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_value()
+            .map_err(S::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+fn kebab_case(name: &str) -> String {
+    let mut res = String::new();
+    for (idx, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if idx != 0 {
+                res.push('-');
+            }
+            res.extend(c.to_lowercase());
+        } else {
+            res.push(c);
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msg::{
+        ubx::nav::{PollNav, Pvt},
+        Nav, Ubx, UbxPoll,
+    };
+
+    #[test]
+    fn nav_pvt_fields_are_at_the_top_level() {
+        let msg = GpsMsg::Ubx(Ubx::Nav(Nav::Pvt(Pvt {
+            i_tow: 1000,
+            lon: 51_234_567,
+            lat: 520_123_456,
+            numsv: 12,
+            ..Default::default()
+        })));
+
+        let value = FlatGpsMsg(&msg).to_value().unwrap();
+        let map = value.as_object().unwrap();
+        assert_eq!(map["type"], "nav-pvt");
+        assert_eq!(map["i_tow"], 1000);
+        assert_eq!(map["lon"], 51_234_567);
+        assert_eq!(map["lat"], 520_123_456);
+        assert_eq!(map["numsv"], 12);
+        for key in ["Ubx", "Nav", "Pvt", "value"] {
+            assert!(!map.contains_key(key), "unexpected key `{}`", key);
+        }
+        assert_eq!(serde_json::to_value(FlatGpsMsg(&msg)).unwrap(), value);
+    }
+
+    #[test]
+    fn polls_are_prefixed() {
+        let msg = GpsMsg::UbxPoll(UbxPoll::Nav(PollNav::Pvt));
+        let value = FlatGpsMsg(&msg).to_value().unwrap();
+        assert_eq!(value, serde_json::json!({ "type": "poll-nav-pvt" }));
+    }
+}
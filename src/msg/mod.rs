@@ -1,10 +1,19 @@
-use std::io::Write;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    fmt,
+    hash::{Hash, Hasher},
+    io::Write,
+    mem::Discriminant,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
 
-use anyhow::{bail, Context};
+use anyhow::{bail, Context as ErrorContext};
+use futures::Stream;
 use serde::{Deserialize, Serialize};
 
 pub mod ubx;
-pub use ubx::{Ubx, UbxPoll};
+pub use ubx::{nav::Nav, Ubx, UbxPoll};
 
 pub mod rtcm;
 pub use rtcm::Rtcm;
@@ -15,9 +24,16 @@ pub use nmea::Nmea;
 pub mod server;
 pub use server::Server;
 
-use crate::parse::{ParseData, ParseError, Result as ParseResult};
+pub mod flat;
+pub use flat::FlatGpsMsg;
+
+use crate::{
+    parse::{ParseData, ParseError, Result as ParseResult},
+    VecExt,
+};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum GpsMsg {
     Ubx(Ubx),
     UbxPoll(UbxPoll),
@@ -31,10 +47,13 @@ impl GpsMsg {
         match Ubx::parse_read(b).map(|(a, b)| (a, GpsMsg::Ubx(b))) {
             Ok(x) => Ok(x),
             Err(e) => {
-                let error = e.downcast_ref::<ParseError>();
-                match error {
+                match ParseError::of(&e) {
                     Some(ParseError::Invalid) | Some(ParseError::InvalidLen) => {
-                        UbxPoll::parse_read(b).map(|(a, b)| (a, GpsMsg::UbxPoll(b)))
+                        // Keep the original error if the message isn't a poll either, it is more
+                        // likely to point at the actual problem.
+                        UbxPoll::parse_read(b)
+                            .map(|(a, b)| (a, GpsMsg::UbxPoll(b)))
+                            .map_err(|_| e)
                     }
                     _ => bail!(e),
                 }
@@ -43,6 +62,18 @@ impl GpsMsg {
     }
 }
 
+impl fmt::Display for GpsMsg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            GpsMsg::Ubx(ref x) => x.fmt(f),
+            GpsMsg::UbxPoll(ref x) => x.fmt(f),
+            GpsMsg::Rtcm3(ref x) => x.fmt(f),
+            GpsMsg::Nmea(ref x) => x.fmt(f),
+            GpsMsg::Server(ref x) => x.fmt(f),
+        }
+    }
+}
+
 impl ParseData for GpsMsg {
     fn parse_read(b: &[u8]) -> ParseResult<(&[u8], Self)> {
         if Ubx::contains_prefix(b) {
@@ -83,11 +114,104 @@ impl GpsMsg {
             || Server::contains_prefix(b)
     }
 
+    /// Returns the length of the message at the start of the buffer if the buffer contains
+    /// the complete message.
+    ///
+    /// Dispatches on the prefix in the same order as `parse_read` so the returned length is
+    /// always that of the message `parse_read` would parse.
     pub fn message_usage(b: &[u8]) -> Option<usize> {
-        Ubx::message_usage(b)
-            .or_else(|| Rtcm::message_usage(b))
-            .or_else(|| Nmea::message_usage(b))
-            .or_else(|| Server::message_usage(b))
+        if Ubx::contains_prefix(b) {
+            Ubx::message_usage(b)
+        } else if Rtcm::contains_prefix(b) {
+            Rtcm::message_usage(b)
+        } else if Nmea::contains_prefix(b) {
+            Nmea::message_usage(b)
+        } else if Server::contains_prefix(b) {
+            Server::message_usage(b)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the offset of the first message prefix in the buffer.
+    ///
+    /// Only offsets holding the first byte of one of the prefixes are checked, these are found
+    /// with memchr instead of trying every offset.
+    pub fn find_prefix(b: &[u8]) -> Option<usize> {
+        let find = |from: usize| {
+            memchr::memchr3(
+                Ubx::SYNC_CHAR,
+                Rtcm::RTCM_PREAMBLE,
+                Nmea::NMEA_PREAMBLE,
+                &b[from..],
+            )
+            .map(|x| x + from)
+        };
+        let find_server =
+            |from: usize| memchr::memchr(Server::PREFIX, &b[from..]).map(|x| x + from);
+
+        // memchr can only search for three bytes at once, so server messages are searched for
+        // separately.
+        let mut next = find(0);
+        let mut next_server = find_server(0);
+        loop {
+            let idx = match (next, next_server) {
+                (Some(a), Some(b)) => a.min(b),
+                (Some(x), None) | (None, Some(x)) => x,
+                (None, None) => return None,
+            };
+            if GpsMsg::contains_prefix(&b[idx..]) {
+                return Some(idx);
+            }
+            if next == Some(idx) {
+                next = find(idx + 1);
+            }
+            if next_server == Some(idx) {
+                next_server = find_server(idx + 1);
+            }
+        }
+    }
+
+    /// Drop bytes from the start of the buffer until it starts with the prefix of a message.
+    /// Returns the number of bytes skipped.
+    pub fn skip_to_prefix(b: &mut Vec<u8>) -> usize {
+        if b.len() < 2 {
+            return 0;
+        }
+        match GpsMsg::find_prefix(b) {
+            Some(0) => 0,
+            Some(idx) => {
+                b.shift(idx);
+                idx
+            }
+            None => {
+                let len = b.len();
+                b.clear();
+                len
+            }
+        }
+    }
+
+    /// Parse all the messages in a frame recieved from the server.
+    /// A frame can contain multiple messages when the server batches the messages of an epoch.
+    pub fn parse_frame(mut b: &[u8]) -> impl Iterator<Item = ParseResult<GpsMsg>> + '_ {
+        std::iter::from_fn(move || {
+            if b.is_empty() {
+                return None;
+            }
+            let len = GpsMsg::message_usage(b).unwrap_or(b.len()).min(b.len());
+            let (msg, rest) = b.split_at(len);
+            b = rest;
+            Some(GpsMsg::parse_read(msg).map(|x| x.1))
+        })
+    }
+
+    /// The GPS time of week of the epoch of a navigation message.
+    pub fn i_tow(&self) -> Option<u32> {
+        match *self {
+            GpsMsg::Ubx(Ubx::Nav(ref x)) => x.i_tow(),
+            _ => None,
+        }
     }
 
     fn into_server(self) -> Result<Server, Self> {
@@ -97,3 +221,407 @@ impl GpsMsg {
         }
     }
 }
+
+/// The kind of navigation message used for deduplication.
+pub type GpsMsgKind = Discriminant<Nav>;
+
+/// Keeps track of recently seen navigation messages to detect duplicates.
+///
+/// A message is a duplicate if a message of the same kind with the same time of week and the
+/// same content was seen before. Messages without a time of week are never duplicates. The
+/// remembered messages are forgotten at the end of every epoch, signaled by a NAV-EOE message.
+pub struct MessageDedup {
+    window: usize,
+    seen: HashMap<(GpsMsgKind, u32), u64>,
+    order: VecDeque<(GpsMsgKind, u32)>,
+}
+
+impl MessageDedup {
+    /// Create a new filter remembering up to `window` recent messages.
+    pub fn new(window: usize) -> Self {
+        MessageDedup {
+            window,
+            seen: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Forget all remembered messages.
+    pub fn reset(&mut self) {
+        self.seen.clear();
+        self.order.clear();
+    }
+
+    /// Returns true if the message is a duplicate of a recently seen message. The content is
+    /// compared using `frame`, the bytes the message was parsed from.
+    pub fn is_duplicate(&mut self, msg: &GpsMsg, frame: &[u8]) -> bool {
+        let (nav, i_tow) = match (msg, msg.i_tow()) {
+            (GpsMsg::Ubx(Ubx::Nav(nav)), Some(i_tow)) => (nav, i_tow),
+            _ => return false,
+        };
+        let mut hasher = DefaultHasher::new();
+        frame.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let key = (std::mem::discriminant(nav), i_tow);
+        if let Nav::Eoe(_) = nav {
+            // Only remember the end of epoch itself so a repeated one is still dropped.
+            if self.seen.get(&key) == Some(&hash) {
+                return true;
+            }
+            self.reset();
+        }
+        match self.seen.insert(key, hash) {
+            Some(x) => x == hash,
+            None => {
+                self.order.push_back(key);
+                if self.order.len() > self.window {
+                    let key = self.order.pop_front().unwrap();
+                    self.seen.remove(&key);
+                }
+                false
+            }
+        }
+    }
+}
+
+/// A stream of messages with duplicate navigation messages removed, see [`MessageDedup`].
+///
+/// Messages are compared by the bytes they serialize to. When the frames the messages are parsed
+/// from are available, use [`MessageDedup`] directly instead.
+pub struct GpsMsgDedup<S> {
+    inner: S,
+    dedup: MessageDedup,
+}
+
+impl<S> GpsMsgDedup<S> {
+    /// Create a new stream remembering up to `window` recent messages.
+    pub fn new(inner: S, window: usize) -> Self {
+        GpsMsgDedup {
+            inner,
+            dedup: MessageDedup::new(window),
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: Stream<Item = GpsMsg> + Unpin> Stream for GpsMsgDedup<S> {
+    type Item = GpsMsg;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this: &mut Self = &mut self;
+        loop {
+            let msg = match ready!(Pin::new(&mut this.inner).poll_next(cx)) {
+                Some(x) => x,
+                None => return Poll::Ready(None),
+            };
+            // Messages which can't be serialized are never duplicates.
+            match msg.parse_to_vec() {
+                Ok(frame) if this.dedup.is_duplicate(&msg, &frame) => continue,
+                _ => return Poll::Ready(Some(msg)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::{
+        msg::{
+            server::ServerMsg,
+            ubx::nav::{Clock, Eoe, FixType, PollNav, Pvt},
+        },
+        parse::ErrorOffset,
+    };
+
+    /// A rtcm 1005 message with a valid crc.
+    const RTCM_FRAME: [u8; 12] = [
+        0xd3, 0x00, 0x06, 0x3e, 0xd0, 0x00, 0x03, 0x04, 0x05, 0xf9, 0xdc, 0x38,
+    ];
+
+    /// Build a ubx frame with a valid checksum.
+    fn ubx_frame(class: u8, id: u8, payload: &[u8]) -> Vec<u8> {
+        let mut b = vec![0xb5, 0x62, class, id];
+        b.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+        b.extend_from_slice(payload);
+        let (mut ck_a, mut ck_b) = (0u8, 0u8);
+        for x in &b[2..] {
+            ck_a = ck_a.wrapping_add(*x);
+            ck_b = ck_b.wrapping_add(ck_a);
+        }
+        b.extend_from_slice(&[ck_a, ck_b]);
+        b
+    }
+
+    fn error_at(b: &[u8]) -> (ParseError, usize) {
+        let e = GpsMsg::parse_read(b).unwrap_err();
+        let kind = ParseError::of(&e).unwrap();
+        (kind, ErrorOffset::of(&e).unwrap())
+    }
+
+    fn messages() -> Vec<GpsMsg> {
+        vec![
+            GpsMsg::Ubx(Ubx::Nav(Nav::Eoe(Eoe { i_tow: 1234 }))),
+            GpsMsg::UbxPoll(UbxPoll::Nav(PollNav::Pvt)),
+            GpsMsg::Rtcm3(Rtcm::parse_read(&RTCM_FRAME).unwrap().1),
+            GpsMsg::Nmea(Nmea::build("GNGGA,,,,,,0,00,99.99,,,,,,")),
+            GpsMsg::Server(Server {
+                msg: ServerMsg::ResetPort,
+                device: 0,
+            }),
+            GpsMsg::Server(Server {
+                msg: ServerMsg::Status {
+                    device: "/dev/ttyACM0".to_string(),
+                    baud: 9600,
+                    clients: 2,
+                    ntrip: true,
+                    uptime_secs: 10,
+                },
+                device: 1,
+            }),
+        ]
+    }
+
+    /// A NAV-PVT message together with the frame it is parsed from.
+    fn pvt(i_tow: u32, lat: i32) -> (GpsMsg, Vec<u8>) {
+        let msg = GpsMsg::Ubx(Ubx::Nav(Nav::Pvt(Pvt {
+            i_tow,
+            lat,
+            ..Default::default()
+        })));
+        let frame = msg.parse_to_vec().unwrap();
+        (msg, frame)
+    }
+
+    #[test]
+    fn dedup_drops_repeated_message() {
+        let mut dedup = MessageDedup::new(4);
+        let (msg, frame) = pvt(1000, 10);
+        assert!(!dedup.is_duplicate(&msg, &frame));
+        assert!(dedup.is_duplicate(&msg, &frame));
+        assert!(dedup.is_duplicate(&msg, &frame));
+    }
+
+    #[test]
+    fn dedup_passes_different_messages() {
+        let mut dedup = MessageDedup::new(4);
+        let (msg, frame) = pvt(1000, 10);
+        assert!(!dedup.is_duplicate(&msg, &frame));
+        // The same time of week with different content.
+        let (msg, frame) = pvt(1000, 11);
+        assert!(!dedup.is_duplicate(&msg, &frame));
+        // The same content at a different time of week.
+        let (msg, frame) = pvt(2000, 11);
+        assert!(!dedup.is_duplicate(&msg, &frame));
+        // A different kind of message at the same time of week.
+        let clock = GpsMsg::Ubx(Ubx::Nav(Nav::Clock(Clock {
+            i_tow: 2000,
+            ..Default::default()
+        })));
+        let frame = clock.parse_to_vec().unwrap();
+        assert!(!dedup.is_duplicate(&clock, &frame));
+        // Messages without a time of week are never duplicates.
+        let rtcm = GpsMsg::Rtcm3(Rtcm::parse_read(&RTCM_FRAME).unwrap().1);
+        assert!(!dedup.is_duplicate(&rtcm, &RTCM_FRAME));
+        assert!(!dedup.is_duplicate(&rtcm, &RTCM_FRAME));
+    }
+
+    #[test]
+    fn dedup_forgets_messages_outside_the_window() {
+        let mut dedup = MessageDedup::new(2);
+        for i_tow in [1000, 2000, 3000] {
+            let (msg, frame) = pvt(i_tow, 10);
+            assert!(!dedup.is_duplicate(&msg, &frame));
+        }
+        let (msg, frame) = pvt(1000, 10);
+        assert!(!dedup.is_duplicate(&msg, &frame));
+        let (msg, frame) = pvt(3000, 10);
+        assert!(dedup.is_duplicate(&msg, &frame));
+    }
+
+    #[tokio::test]
+    async fn dedup_stream_forwards_one_frame_per_epoch() {
+        let (pvt, _) = pvt(1000, 10);
+        let eoe = GpsMsg::Ubx(Ubx::Nav(Nav::Eoe(Eoe { i_tow: 1000 })));
+        let messages = [
+            pvt.clone(),
+            pvt.clone(),
+            eoe.clone(),
+            eoe.clone(),
+            // The cache is reset by the end of epoch, so the message is forwarded again.
+            pvt.clone(),
+        ];
+        let stream = GpsMsgDedup::new(futures::stream::iter(messages), 4);
+        let forwarded = stream
+            .map(|x| x.parse_to_vec().unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        let expected = [pvt.clone(), eoe, pvt].map(|x| x.parse_to_vec().unwrap());
+        assert_eq!(forwarded, expected);
+    }
+
+    #[test]
+    fn find_prefix_matches_naive_scan() {
+        let messages = messages()
+            .iter()
+            .map(|x| x.parse_to_vec().unwrap())
+            .collect::<Vec<_>>();
+        // A simple xorshift so the test doesn't need a random number crate.
+        let mut state = 0x2545_f491u32;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+
+        for _ in 0..2000 {
+            // Mostly prefix bytes and messages so prefixes which are not a message are common.
+            let mut b = Vec::new();
+            for _ in 0..next() % 16 {
+                match next() % 8 {
+                    0 => b.extend_from_slice(&messages[next() as usize % messages.len()]),
+                    1 | 2 => b.push(next() as u8),
+                    _ => b.push([0xb5, 0x62, 0xd3, b'$', b'%'][next() as usize % 5]),
+                }
+            }
+            let naive = (0..b.len()).find(|&i| GpsMsg::contains_prefix(&b[i..]));
+            assert_eq!(GpsMsg::find_prefix(&b), naive, "{b:x?}");
+        }
+    }
+
+    #[test]
+    fn message_usage_is_serialized_len() {
+        for msg in messages() {
+            let b = msg.parse_to_vec().unwrap();
+            assert_eq!(GpsMsg::message_usage(&b), Some(b.len()), "{msg}");
+
+            let mut longer = b.clone();
+            longer.extend_from_slice(&RTCM_FRAME);
+            assert_eq!(GpsMsg::message_usage(&longer), Some(b.len()), "{msg}");
+
+            assert_eq!(GpsMsg::message_usage(&b[..b.len() - 1]), None, "{msg}");
+        }
+    }
+
+    #[test]
+    fn error_offset_of_invalid_class() {
+        let b = ubx_frame(0x7f, 0x01, &[]);
+        assert_eq!(error_at(&b), (ParseError::InvalidClass(0x7f), 2));
+    }
+
+    #[test]
+    fn error_offset_of_invalid_len() {
+        // NAV-EOE has a payload of 4 bytes.
+        let b = ubx_frame(0x01, 0x61, &[0; 5]);
+        assert_eq!(error_at(&b), (ParseError::InvalidLen, 4));
+    }
+
+    #[test]
+    fn error_offset_of_invalid_poll_id() {
+        let b = ubx_frame(0x01, 0xee, &[]);
+        let e = UbxPoll::parse_read(&b).unwrap_err();
+        assert_eq!(ParseError::of(&e), Some(ParseError::InvalidMsg(0xee)));
+        assert_eq!(ErrorOffset::of(&e), Some(3));
+    }
+
+    #[test]
+    fn error_offset_of_invalid_field() {
+        // CFG-RST with an invalid reset mode.
+        let b = ubx_frame(0x06, 0x04, &[0x00, 0x00, 0x03, 0x00]);
+        assert_eq!(error_at(&b), (ParseError::Invalid, 8));
+    }
+
+    #[test]
+    fn invalid_field_is_named_in_the_error() {
+        let b = ubx_frame(0x06, 0x04, &[0x00, 0x00, 0x03, 0x00]);
+        let e = GpsMsg::parse_read(&b).unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<ParseError>(),
+            Some(&ParseError::InvalidField {
+                struct_name: "Rst",
+                field_name: "reset_mode",
+            })
+        );
+        let message = format!("{e:#}");
+        assert!(
+            message.contains("failed to parse field `reset_mode` of struct `Rst`"),
+            "{message}"
+        );
+        assert!(
+            message.contains("failed to parse enum `ResetMode`"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn display_summarizes_each_variant() {
+        let pvt = GpsMsg::Ubx(Ubx::Nav(Nav::Pvt(Pvt {
+            lat: 520_000_000,
+            lon: 45_000_000,
+            fix_type: FixType::Fix3D,
+            numsv: 9,
+            h_acc: 1500,
+            ..Default::default()
+        })));
+        assert_eq!(
+            pvt.to_string(),
+            "UBX-NAV-PVT: lat=52.0000000° lon=4.5000000° fix=Fix3D sats=9 acc=1.500m"
+        );
+
+        let eoe = GpsMsg::Ubx(Ubx::Nav(Nav::Eoe(Eoe { i_tow: 1234 })));
+        assert_eq!(eoe.to_string(), "UBX-NAV-EOE");
+
+        let poll = GpsMsg::UbxPoll(UbxPoll::Nav(PollNav::Pvt));
+        assert_eq!(poll.to_string(), "UBX-NAV-PVT poll");
+
+        let rtcm = GpsMsg::Rtcm3(Rtcm::parse_read(&RTCM_FRAME).unwrap().1);
+        assert_eq!(rtcm.to_string(), "RTCM3 type 1005 12 bytes");
+
+        let nmea = GpsMsg::Nmea(Nmea::build("GNGGA,,,,,,0,00,99.99,,,,,,"));
+        assert_eq!(nmea.to_string(), "$GNGGA,,,,,,0,00,99.99,,,,,,*56");
+
+        let server = GpsMsg::Server(Server {
+            msg: ServerMsg::ResetPort,
+            device: 1,
+        });
+        assert_eq!(server.to_string(), "SERVER ResetPort for device 1");
+    }
+
+    #[test]
+    fn display_truncates_long_nmea_sentences() {
+        let body = format!("GPTXT,{}", "ä".repeat(100));
+        let text = GpsMsg::Nmea(Nmea::build(&body)).to_string();
+        assert!(text.ends_with("..."), "{text}");
+        assert_eq!(text.chars().count(), 83);
+        assert!(text.starts_with("$GPTXT,ää"));
+    }
+
+    #[test]
+    fn display_of_parsed_frames() {
+        let frame = GpsMsg::Ubx(Ubx::Nav(Nav::Pvt(Pvt {
+            lat: -339_000_000,
+            lon: 1_512_000_000,
+            fix_type: FixType::Fix2D,
+            numsv: 4,
+            h_acc: 25,
+            ..Default::default()
+        })))
+        .parse_to_vec()
+        .unwrap();
+        let (_, pvt) = GpsMsg::parse_read(&frame).unwrap();
+        assert_eq!(
+            pvt.to_string(),
+            "UBX-NAV-PVT: lat=-33.9000000° lon=151.2000000° fix=Fix2D sats=4 acc=0.025m"
+        );
+
+        let (_, rtcm) = GpsMsg::parse_read(&RTCM_FRAME).unwrap();
+        assert_eq!(rtcm.to_string(), "RTCM3 type 1005 12 bytes");
+    }
+}
@@ -1,14 +1,47 @@
-use std::io::Write;
+use std::{fmt, io::Write};
 
 use serde::{Deserialize, Serialize};
 
 use crate::parse::{self, ParseData, ParseError, Result};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Nmea(String);
 
+/// The kinds of sentences commonly send by receivers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NmeaSentenceType {
+    Gga,
+    Rmc,
+    Gsv,
+    Gsa,
+    Vtg,
+    Gll,
+    Zda,
+    Gns,
+    Unknown,
+}
+
+impl NmeaSentenceType {
+    fn from_id(id: &str) -> Self {
+        match id {
+            "GGA" => Self::Gga,
+            "RMC" => Self::Rmc,
+            "GSV" => Self::Gsv,
+            "GSA" => Self::Gsa,
+            "VTG" => Self::Vtg,
+            "GLL" => Self::Gll,
+            "ZDA" => Self::Zda,
+            "GNS" => Self::Gns,
+            _ => Self::Unknown,
+        }
+    }
+}
+
 impl Nmea {
-    const NMEA_PREAMBLE: u8 = b'$';
+    pub(crate) const NMEA_PREAMBLE: u8 = b'$';
+    /// The maximum number of characters of the sentence shown by the `Display` implementation.
+    const DISPLAY_LIMIT: usize = 80;
 
     pub fn contains_prefix(b: &[u8]) -> bool {
         !b.is_empty() && b[0] == Self::NMEA_PREAMBLE
@@ -29,6 +62,67 @@ impl Nmea {
         }
         None
     }
+
+    /// The address field of the sentence, i.e. `GNGGA` in `$GNGGA,...`.
+    fn address(&self) -> Option<&str> {
+        let body = self.0.strip_prefix('$')?;
+        let end = body.find([',', '*']).unwrap_or(body.len());
+        Some(&body[..end])
+    }
+
+    /// The talker id of the sentence, i.e. `GN` in `$GNGGA,...`.
+    /// Returns `None` for sentences which don't have the usual five character address.
+    pub fn talker_id(&self) -> Option<&str> {
+        self.address()
+            .filter(|x| x.len() == 5 && x.is_ascii())
+            .map(|x| &x[..2])
+    }
+
+    /// The sentence id of the sentence, i.e. `GGA` in `$GNGGA,...`.
+    /// Returns `None` for sentences which don't have the usual five character address.
+    pub fn sentence_id(&self) -> Option<&str> {
+        self.address()
+            .filter(|x| x.len() == 5 && x.is_ascii())
+            .map(|x| &x[2..])
+    }
+
+    pub fn sentence_type(&self) -> NmeaSentenceType {
+        self.sentence_id()
+            .map_or(NmeaSentenceType::Unknown, NmeaSentenceType::from_id)
+    }
+
+    /// Compute the checksum of a sentence body, the part between `$` and `*`.
+    pub fn checksum(body: &str) -> u8 {
+        body.bytes().fold(0, |acc, x| acc ^ x)
+    }
+
+    /// Returns whether the sentence ends in a `*XX` checksum which matches its body.
+    pub fn checksum_valid(&self) -> bool {
+        let Some(sentence) = self.0.strip_prefix('$') else {
+            return false;
+        };
+        match sentence.trim_end_matches(['\r', '\n']).rsplit_once('*') {
+            Some((body, ck)) if ck.len() == 2 => {
+                u8::from_str_radix(ck, 16).ok() == Some(Self::checksum(body))
+            }
+            _ => false,
+        }
+    }
+
+    /// Build a sentence from its body, adding the preamble, checksum and line ending.
+    pub fn build(body: &str) -> Nmea {
+        Nmea(format!("${}*{:02X}\r\n", body, Self::checksum(body)))
+    }
+}
+
+impl fmt::Display for Nmea {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sentence = self.0.trim_end();
+        match sentence.char_indices().nth(Self::DISPLAY_LIMIT) {
+            Some((idx, _)) => write!(f, "{}...", &sentence[..idx]),
+            None => f.write_str(sentence),
+        }
+    }
 }
 
 impl ParseData for Nmea {
@@ -58,3 +152,72 @@ impl ParseData for Nmea {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_known_checksum() {
+        let nmea = Nmea::build("GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,");
+        assert_eq!(
+            nmea.0,
+            "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47\r\n"
+        );
+        assert!(nmea.checksum_valid());
+    }
+
+    #[test]
+    fn build_round_trip() {
+        let nmea = Nmea::build("PUBX,00");
+        let b = nmea.parse_to_vec().unwrap();
+        assert_eq!(b, b"$PUBX,00*33\r\n");
+
+        let (rest, parsed) = Nmea::parse_read(&b).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(parsed.0, nmea.0);
+        assert!(parsed.checksum_valid());
+    }
+
+    #[test]
+    fn checksum_invalid() {
+        assert!(!Nmea("$PUBX,00*34\r\n".to_string()).checksum_valid());
+        assert!(!Nmea("$PUBX,00\r\n".to_string()).checksum_valid());
+        assert!(!Nmea("PUBX,00*33\r\n".to_string()).checksum_valid());
+    }
+
+    #[test]
+    fn sentence_type_of_known_sentences() {
+        use NmeaSentenceType::*;
+
+        let known = [
+            ("GGA", Gga),
+            ("RMC", Rmc),
+            ("GSV", Gsv),
+            ("GSA", Gsa),
+            ("VTG", Vtg),
+            ("GLL", Gll),
+            ("ZDA", Zda),
+            ("GNS", Gns),
+        ];
+        for (id, ty) in known {
+            let nmea = Nmea::build(&format!("GN{id},,,"));
+            assert_eq!(nmea.talker_id(), Some("GN"));
+            assert_eq!(nmea.sentence_id(), Some(id));
+            assert_eq!(nmea.sentence_type(), ty, "{id}");
+        }
+    }
+
+    #[test]
+    fn sentence_type_of_other_sentences_is_unknown() {
+        let txt = Nmea::build("GPTXT,01,01,02,u-blox ag");
+        assert_eq!(txt.sentence_id(), Some("TXT"));
+        assert_eq!(txt.sentence_type(), NmeaSentenceType::Unknown);
+
+        // Proprietary sentences don't have the five character address.
+        let pubx = Nmea::build("PUBX,00");
+        assert_eq!(pubx.talker_id(), None);
+        assert_eq!(pubx.sentence_id(), None);
+        assert_eq!(pubx.sentence_type(), NmeaSentenceType::Unknown);
+    }
+}
@@ -1,9 +1,12 @@
+use std::fmt;
+
 use anyhow::bail;
 use serde::{Deserialize, Serialize};
 
 use crate::parse::{self, ParseData, ParseError};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Rtcm {
     pub kind: u16,
     pub data: Vec<u8>,
@@ -41,13 +44,27 @@ static TBL_CRC24: [u32; 256] = [
     0xD11CCE, 0x575035, 0x5BC9C3, 0xDD8538,
 ];
 
+impl fmt::Display for Rtcm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RTCM3 type {} {} bytes", self.kind, self.data.len())
+    }
+}
+
 impl Rtcm {
-    const RTCM_PREAMBLE: u8 = 0xd3;
+    pub(crate) const RTCM_PREAMBLE: u8 = 0xd3;
 
     pub fn contains_prefix(b: &[u8]) -> bool {
         !b.is_empty() && b[0] == Self::RTCM_PREAMBLE
     }
 
+    /// Returns the message type of the rtcm message in a raw frame.
+    pub fn kind_from_frame(b: &[u8]) -> Option<u16> {
+        if !Self::contains_prefix(b) || b.len() < 5 {
+            return None;
+        }
+        Some(Self::get_bits(b, 24, 12) as u16)
+    }
+
     fn crc24(b: &[u8]) -> u32 {
         let mut crc = 0;
         for &b in b {
@@ -1,26 +1,180 @@
+//! Messages for the server itself instead of the device.
+//!
+//! A server message starts with the `%` prefix followed by the kind of the message, the index of
+//! the device the message is for and the length of the payload as a `u16`. The payload is
+//! encoded like the payload of any other message. Version 1 of the protocol, as advertised over
+//! mdns, only had the prefix and the kind.
+
 use crate::{
-    impl_enum,
+    impl_enum, impl_struct,
     parse::{self, ParseData, ParseError},
+    pread,
 };
+use anyhow::bail;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+
+impl_struct! {
+/// Statistics of a single client connection of the server.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ConnectionStats {
+    remote_addr: String,
+    connected_secs: u64,
+    bytes_sent: u64,
+    bytes_recv: u64,
+}
+}
 
 impl_enum! {
-pub enum ServerMsg: u8 {
+pub enum ServerMsgKind: u8 {
     ResetPort = 0,
-    Quit = 1
+    Quit = 1,
+    Stats = 2,
+    StatsResponse = 3,
+    StatusRequest = 4,
+    Status = 5
 }
 }
 
+impl ServerMsgKind {
+    fn has_payload(self) -> bool {
+        matches!(self, ServerMsgKind::StatsResponse | ServerMsgKind::Status)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum ServerMsg {
+    ResetPort,
+    Quit,
+    /// Request the statistics of the connections of a device.
+    Stats,
+    /// The reply to a `Stats` message, send to the connection which requested it.
+    StatsResponse(Vec<ConnectionStats>),
+    /// Request the status of a device.
+    StatusRequest,
+    /// The reply to a `StatusRequest` message, send to the connection which requested it.
+    Status {
+        /// The path of the serial port of the device.
+        device: String,
+        baud: u32,
+        /// The number of connected clients.
+        clients: u32,
+        /// Whether RTCM corrections, i.e. from NTRIP, were recently recieved from a client.
+        ntrip: bool,
+        uptime_secs: u64,
+    },
+}
+
+impl ServerMsg {
+    pub fn kind(&self) -> ServerMsgKind {
+        match *self {
+            ServerMsg::ResetPort => ServerMsgKind::ResetPort,
+            ServerMsg::Quit => ServerMsgKind::Quit,
+            ServerMsg::Stats => ServerMsgKind::Stats,
+            ServerMsg::StatsResponse(_) => ServerMsgKind::StatsResponse,
+            ServerMsg::StatusRequest => ServerMsgKind::StatusRequest,
+            ServerMsg::Status { .. } => ServerMsgKind::Status,
+        }
+    }
+
+    /// Parse a message from its kind and the complete payload.
+    fn parse_payload(kind: ServerMsgKind, b: &[u8]) -> parse::Result<Self> {
+        let (b, msg) = match kind {
+            ServerMsgKind::ResetPort => (b, ServerMsg::ResetPort),
+            ServerMsgKind::Quit => (b, ServerMsg::Quit),
+            ServerMsgKind::Stats => (b, ServerMsg::Stats),
+            ServerMsgKind::StatsResponse => {
+                let (b, stats) = ParseData::parse_read(b)?;
+                (b, ServerMsg::StatsResponse(stats))
+            }
+            ServerMsgKind::StatusRequest => (b, ServerMsg::StatusRequest),
+            ServerMsgKind::Status => {
+                pread!(b => {
+                    device: String,
+                    baud: u32,
+                    clients: u32,
+                    ntrip: bool,
+                    uptime_secs: u64,
+                });
+                (
+                    b,
+                    ServerMsg::Status {
+                        device,
+                        baud,
+                        clients,
+                        ntrip,
+                        uptime_secs,
+                    },
+                )
+            }
+        };
+        if !b.is_empty() {
+            bail!(ParseError::InvalidLen);
+        }
+        Ok(msg)
+    }
+
+    fn write_payload<W: std::io::Write>(&self, b: &mut W) -> parse::Result<()> {
+        match *self {
+            ServerMsg::StatsResponse(ref x) => x.parse_write(b),
+            ServerMsg::Status {
+                ref device,
+                baud,
+                clients,
+                ntrip,
+                uptime_secs,
+            } => {
+                device.parse_write(b)?;
+                baud.parse_write(b)?;
+                clients.parse_write(b)?;
+                ntrip.parse_write(b)?;
+                uptime_secs.parse_write(b)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Server {
     pub msg: ServerMsg,
+    /// The index of the device the message is for, when the server serves multiple devices.
+    #[serde(default)]
+    pub device: u8,
 }
 
 impl Server {
     pub const PREFIX: u8 = b'%';
 
+    /// The length of the prefix, kind, device and payload length in front of the payload.
+    const HEADER_LEN: usize = 5;
+
+    /// Returns whether the buffer starts with the header of a server message.
+    ///
+    /// Checks as much of the message as is in the buffer, the payload is only checked once it
+    /// is complete. Used to reject stray `%` bytes in the data of other messages when
+    /// resynchronizing.
     pub fn contains_prefix(b: &[u8]) -> bool {
-        !b.is_empty() && b[0] == Self::PREFIX
+        if b.len() < 2 || b[0] != Self::PREFIX {
+            return false;
+        }
+        let Ok((_, kind)) = ServerMsgKind::parse_read(&b[1..]) else {
+            return false;
+        };
+        let Some(len) = b.get(3..Self::HEADER_LEN) else {
+            return true;
+        };
+        let len = u16::from_le_bytes([len[0], len[1]]) as usize;
+        if !kind.has_payload() {
+            return len == 0;
+        }
+        match b.get(Self::HEADER_LEN..Self::HEADER_LEN + len) {
+            Some(payload) => ServerMsg::parse_payload(kind, payload).is_ok(),
+            None => true,
+        }
     }
 
     pub fn message_usage(b: &[u8]) -> Option<usize> {
@@ -28,21 +182,180 @@ impl Server {
             return None;
         }
 
-        if b.len() < 2 {
+        if b.len() < Self::HEADER_LEN {
             return None;
         }
-        Some(2)
+        let len = Self::HEADER_LEN + u16::from_le_bytes([b[3], b[4]]) as usize;
+        if b.len() < len {
+            return None;
+        }
+        Some(len)
+    }
+}
+
+impl fmt::Display for Server {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.msg {
+            ServerMsg::StatsResponse(ref x) => {
+                write!(
+                    f,
+                    "SERVER stats for device {}: {} connections",
+                    self.device,
+                    x.len()
+                )
+            }
+            ServerMsg::Status {
+                ref device,
+                baud,
+                clients,
+                ntrip,
+                uptime_secs,
+            } => {
+                write!(
+                    f,
+                    "SERVER status for device {}: {device} at {baud} baud, {clients} clients, ntrip {}, up {uptime_secs}s",
+                    self.device,
+                    if ntrip { "active" } else { "inactive" }
+                )
+            }
+            ref x => write!(f, "SERVER {:?} for device {}", x, self.device),
+        }
     }
 }
 
 impl ParseData for Server {
     fn parse_read(b: &[u8]) -> crate::parse::Result<(&[u8], Self)> {
         let b = parse::tag(b, Server::PREFIX)?;
-        ServerMsg::parse_read(b).map(|(a, msg)| (a, Server { msg }))
+        pread!(b => {
+            kind: ServerMsgKind,
+            device: u8,
+            len: u16,
+        });
+        if b.len() < len as usize {
+            bail!(ParseError::NotEnoughData);
+        }
+        let (payload, b) = b.split_at(len as usize);
+        let msg = ServerMsg::parse_payload(kind, payload)?;
+        Ok((b, Server { msg, device }))
     }
 
     fn parse_write<W: std::io::Write>(&self, b: &mut W) -> crate::parse::Result<()> {
+        let mut payload = Vec::new();
+        self.msg.write_payload(&mut payload)?;
+        let len = u16::try_from(payload.len()).map_err(|_| ParseError::InvalidLen)?;
+
         Server::PREFIX.parse_write(b)?;
-        self.msg.parse_write(b)
+        self.msg.kind().parse_write(b)?;
+        self.device.parse_write(b)?;
+        len.parse_write(b)?;
+        b.write_all(&payload)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msg::GpsMsg;
+
+    fn messages() -> Vec<ServerMsg> {
+        vec![
+            ServerMsg::ResetPort,
+            ServerMsg::Quit,
+            ServerMsg::Stats,
+            ServerMsg::StatsResponse(vec![ConnectionStats {
+                remote_addr: "127.0.0.1:5000".to_string(),
+                connected_secs: 3,
+                bytes_sent: 100,
+                bytes_recv: 10,
+            }]),
+            ServerMsg::StatsResponse(Vec::new()),
+            ServerMsg::StatusRequest,
+            ServerMsg::Status {
+                device: "/dev/ttyACM0".to_string(),
+                baud: 9600,
+                clients: 1,
+                ntrip: false,
+                uptime_secs: 60,
+            },
+        ]
+    }
+
+    #[test]
+    fn round_trip() {
+        for msg in messages() {
+            let server = Server { msg, device: 2 };
+            let b = server.parse_to_vec().unwrap();
+            assert!(Server::contains_prefix(&b), "{server}");
+            assert_eq!(Server::message_usage(&b), Some(b.len()), "{server}");
+
+            let (rest, parsed) = Server::parse_read(&b).unwrap();
+            assert!(rest.is_empty());
+            assert_eq!(parsed.msg, server.msg);
+            assert_eq!(parsed.device, 2);
+        }
+    }
+
+    #[test]
+    fn encoding() {
+        let b = Server {
+            msg: ServerMsg::Quit,
+            device: 1,
+        }
+        .parse_to_vec()
+        .unwrap();
+        assert_eq!(b, b"%\x01\x01\x00\x00");
+
+        let b = Server {
+            msg: ServerMsg::StatsResponse(vec![ConnectionStats {
+                remote_addr: "a:1".to_string(),
+                connected_secs: 3,
+                bytes_sent: 100,
+                bytes_recv: 10,
+            }]),
+            device: 0,
+        }
+        .parse_to_vec()
+        .unwrap();
+        let mut expected = b"%\x03\x00\x1d\x00\x03\x00a:1".to_vec();
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.extend_from_slice(&100u64.to_le_bytes());
+        expected.extend_from_slice(&10u64.to_le_bytes());
+        assert_eq!(b, expected);
+    }
+
+    #[test]
+    fn partial_header_is_prefix() {
+        assert!(!Server::contains_prefix(b"%"));
+        assert!(Server::contains_prefix(b"%\x03"));
+        assert!(Server::contains_prefix(b"%\x03\x00\x20\x00"));
+        assert!(Server::contains_prefix(b"%\x03\x00\x20\x00\x0e"));
+    }
+
+    #[test]
+    fn stray_prefix_is_rejected() {
+        // Unknown kind.
+        assert!(!Server::contains_prefix(b"%\x06\x00\x00\x00"));
+        // A payload for a message without one.
+        assert!(!Server::contains_prefix(b"%\x00\x00\x01\x00"));
+        // A payload which is too short for the message.
+        assert!(!Server::contains_prefix(b"%\x05\x00\x01\x00\x00"));
+        // A payload which doesn't parse.
+        assert!(!Server::contains_prefix(
+            b"%\x03\x00\x04\x00\x00\x00\x00\x00"
+        ));
+    }
+
+    #[test]
+    fn resync_skips_stray_prefix() {
+        let mut b = b"\x01%\x03\x00\x04\x00\x00\x00\x00\x00".to_vec();
+        let frame = Server {
+            msg: ServerMsg::Quit,
+            device: 0,
+        }
+        .parse_to_vec()
+        .unwrap();
+        b.extend_from_slice(&frame);
+        assert_eq!(GpsMsg::find_prefix(&b), Some(10));
     }
 }
@@ -1,6 +1,6 @@
-use crate::parse::{self, ParseData, ParseError, Result, ResultExt};
+use crate::parse::{self, ErrorOffset, ParseData, ParseError, Result, ResultExt};
 use serde::{Deserialize, Serialize};
-use std::io::Write;
+use std::{fmt, io::Write};
 
 macro_rules! impl_class {
     (pub enum $class:ident: $pollname:ident{
@@ -8,12 +8,14 @@ macro_rules! impl_class {
     }) => {
 
         #[derive(Debug,serde::Serialize,serde::Deserialize, Clone)]
+        #[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
         pub enum $class {
             $($var($t),)*
             Unknown{ id: u8, payload:Vec<u8> }
         }
 
         #[derive(Debug,serde::Serialize,serde::Deserialize, Clone, Copy, Eq,PartialEq)]
+        #[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
         pub enum $pollname{
             $($var,)*
         }
@@ -24,14 +26,17 @@ macro_rules! impl_class {
                 use crate::parse::ResultExt;
                 use anyhow::Context as ErrorContext;
 
+                let start = b;
                 let (b,msg) = u8::parse_read(b)?;
                 match msg{
                     $($e => {
                         $(let b = crate::parse::tag(b,($len as u16))
                             .map_invalid(crate::parse::ParseError::InvalidLen)
+                            .map_err(|e| crate::parse::ErrorOffset::add(e, 1))
                             .context(concat!("invalid len for msg `",stringify!($t),"`"))
                             ?;)*
                         let (b,res) = <$t>::parse_read(b)
+                            .map_err(|e| crate::parse::ErrorOffset::add(e, start.len() - b.len()))
                             .context(concat!("failed to parse data for msg `",stringify!($t),"`"))
                         ?;
                         Ok((b,Self::$var(res)))
@@ -51,6 +56,7 @@ macro_rules! impl_class {
                 match *self{
                     $(Self::$var(ref x) => {
                         ($e as u8).parse_write(w)?;
+                        $(($len as u16).parse_write(w)?;)*
                         x.parse_write(w)
                     })*
                     Self::Unknown{ id, ref payload } => {
@@ -62,6 +68,25 @@ macro_rules! impl_class {
             }
         }
 
+        impl std::fmt::Display for $class{
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result{
+                let class = stringify!($class).to_uppercase();
+                match *self{
+                    $(Self::$var(_) => write!(f, "{}-{}", class, stringify!($var).to_uppercase()),)*
+                    Self::Unknown{ id, .. } => write!(f, "{}-0x{:02x}", class, id),
+                }
+            }
+        }
+
+        impl std::fmt::Display for $pollname{
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result{
+                let class = stringify!($class).to_uppercase();
+                match *self{
+                    $(Self::$var => write!(f, "{}-{}", class, stringify!($var).to_uppercase()),)*
+                }
+            }
+        }
+
         impl crate::parse::ParseData for $pollname{
             fn parse_read(b: &[u8]) -> crate::parse::Result<(&[u8],Self)>{
                 let (b,msg) = u8::parse_read(b)?;
@@ -69,7 +94,10 @@ macro_rules! impl_class {
                     $($e => {
                         Ok((b,Self::$var))
                     })*
-                    _ => Err(crate::parse::ParseError::Invalid.into()),
+                    x => Err(crate::parse::ErrorOffset::add(
+                        crate::parse::ParseError::InvalidMsg(x).into(),
+                        0,
+                    )),
                 }
             }
 
@@ -103,12 +131,16 @@ use rxm::{PollRxm, Rxm};
 pub mod inf;
 use inf::{Inf, PollInf};
 
+pub mod log;
+use log::{Log, PollLog};
+
 macro_rules! impl_ubx {
     (pub enum Ubx{
         $($var:ident($t:ty,$p:ty) = $class_id:expr,)*
     }) => {
 
         #[derive(Debug,Serialize,Deserialize, Clone)]
+        #[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
         pub enum Ubx{
             $(
                 $var($t),
@@ -154,18 +186,22 @@ macro_rules! impl_ubx {
                 let (b,class) = u8::parse_read(b)?;
                 match class{
                     $($class_id => {
-                        let (b,inner) = <$t>::parse_read(b)?;
+                        let (b,inner) = <$t>::parse_read(b)
+                            .map_err(|e| ErrorOffset::add(e, 3))?;
                         let c = &c[..c.len() - b.len()];
                         let (b,ck_a) = u8::parse_read(b)?;
                         let (b,ck_b) = u8::parse_read(b)?;
 
                         if !Ubx::checksum_valid(c,ck_a,ck_b) {
-                            return Err(anyhow::Error::from(ParseError::InvalidChecksum))
+                            return Err(ErrorOffset::add(ParseError::InvalidChecksum.into(), 2 + c.len()))
                                 .context("checksum failed for ubx message");
                         }
 
                         Ok((b,Ubx::$var(inner)))
                     },)*
+                    x if !Ubx::class_defined(x) => {
+                        Err(ErrorOffset::add(ParseError::InvalidClass(x).into(), 2))
+                    }
                     _ => {
                         let (b,msg) = u8::parse_read(b)?;
                         let (b,len) = u16::parse_read(b)?;
@@ -175,7 +211,7 @@ macro_rules! impl_ubx {
                         let (b,ck_b) = u8::parse_read(b)?;
 
                         if !Ubx::checksum_valid(c,ck_a,ck_b) {
-                            return Err(anyhow::Error::from(ParseError::InvalidChecksum))
+                            return Err(ErrorOffset::add(ParseError::InvalidChecksum.into(), 2 + c.len()))
                                 .context("checksum failed for ubx message");
                         }
 
@@ -219,6 +255,7 @@ macro_rules! impl_ubx {
         }
 
         #[derive(Debug,Serialize,Deserialize, Clone)]
+        #[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
         pub enum UbxPoll{
             $(
                 $var($p),
@@ -231,6 +268,35 @@ macro_rules! impl_ubx {
             }
         }
 
+        impl fmt::Display for Ubx{
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+                match *self{
+                    Ubx::Nav(nav::Nav::Pvt(ref x)) => write!(f, "UBX-NAV-PVT: {}", x),
+                    Ubx::Nav(nav::Nav::Hpposllh(ref x)) => write!(f, "UBX-NAV-HPPOSLLH: {}", x),
+                    Ubx::Nav(nav::Nav::RelPosNed(ref x)) => write!(f, "UBX-NAV-RELPOSNED: {}", x),
+                    Ubx::Nav(nav::Nav::Sat(ref x)) => write!(f, "UBX-NAV-SAT: {}", x),
+                    Ubx::Nav(nav::Nav::Svin(ref x)) => write!(f, "UBX-NAV-SVIN: {}", x),
+                    Ubx::Ack(ack::Ack::Ack(ref x)) => write!(f, "UBX-ACK-ACK: {}", x.message_id()),
+                    Ubx::Ack(ack::Ack::Nak(ref x)) => write!(f, "UBX-ACK-NAK: {}", x.message_id()),
+                    $(Ubx::$var(ref x) => write!(f, "UBX-{}", x),)*
+                    Ubx::Unknown{ class, msg, len, .. } => {
+                        write!(f, "UBX-0x{:02x}-0x{:02x} {} bytes", class, msg, len)
+                    }
+                }
+            }
+        }
+
+        impl fmt::Display for UbxPoll{
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+                match *self{
+                    $(UbxPoll::$var(ref x) => write!(f, "UBX-{} poll", x),)*
+                    UbxPoll::Unknown{ class, msg, .. } => {
+                        write!(f, "UBX-0x{:02x}-0x{:02x} poll", class, msg)
+                    }
+                }
+            }
+        }
+
         impl ParseData for UbxPoll{
 
             fn parse_read(b: &[u8]) -> Result<(&[u8],Self)>{
@@ -243,16 +309,21 @@ macro_rules! impl_ubx {
                 let (b,class) = u8::parse_read(b)?;
                 match class{
                     $($class_id => {
-                        let (b,inner) = <$p>::parse_read(b)?;
-                        let b = parse::tag(b, 0u16)?;
+                        let (b,inner) = <$p>::parse_read(b)
+                            .map_err(|e| ErrorOffset::add(e, 3))?;
+                        let b = parse::tag(b, 0u16)
+                            .map_err(|e| ErrorOffset::add(e, 4))?;
                         let c = &c[..c.len() - b.len()];
                         let (b,ck_a) = u8::parse_read(b)?;
                         let (b,ck_b) = u8::parse_read(b)?;
                         if !Ubx::checksum_valid(c,ck_a,ck_b){
-                            bail!(ParseError::InvalidChecksum)
+                            bail!(ErrorOffset::add(ParseError::InvalidChecksum.into(), 2 + c.len()))
                         }
                         Ok((b,UbxPoll::$var(inner)))
                     },)*
+                    x if !Ubx::class_defined(x) => {
+                        Err(ErrorOffset::add(ParseError::InvalidClass(x).into(), 2))
+                    }
                     _ => {
                         let (b,msg) = u8::parse_read(b)?;
                         let b = parse::tag(b, 0u16)?;
@@ -304,12 +375,25 @@ impl_ubx! {
         Mon(Mon,PollMon) = 0x0A,
         Rxm(Rxm,PollRxm) = 0x02,
         Inf(Inf,PollInf) = 0x04,
+        Log(Log,PollLog) = 0x21,
     }
 }
 
 impl Ubx {
+    pub(crate) const SYNC_CHAR: u8 = 0xb5;
+
+    /// The classes defined by the protocol. Messages of a defined class which isn't supported
+    /// are parsed as `Unknown`, messages of any other class are invalid.
+    const DEFINED_CLASSES: [u8; 15] = [
+        0x01, 0x02, 0x04, 0x05, 0x06, 0x09, 0x0a, 0x0b, 0x0d, 0x10, 0x13, 0x21, 0x27, 0x28, 0x29,
+    ];
+
+    fn class_defined(class: u8) -> bool {
+        Self::DEFINED_CLASSES.contains(&class)
+    }
+
     pub fn contains_prefix(b: &[u8]) -> bool {
-        b.len() >= 2 && b[0] == 0xb5 && b[1] == 0x62
+        b.len() >= 2 && b[0] == Self::SYNC_CHAR && b[1] == 0x62
     }
 
     pub fn message_usage(b: &[u8]) -> Option<usize> {
@@ -1,9 +1,49 @@
-use crate::{impl_struct, parse::ParseData};
+use crate::{impl_struct, msg::Ubx, parse::ParseData};
 
 use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The class and message id of a ubx message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct MessageId {
+    pub class: u8,
+    pub id: u8,
+}
+
+impl MessageId {
+    pub const CFG_RST: MessageId = MessageId::new(0x06, 0x04);
+    pub const CFG_TP5: MessageId = MessageId::new(0x06, 0x31);
+    pub const CFG_TMODE3: MessageId = MessageId::new(0x06, 0x71);
+    pub const CFG_VALGET: MessageId = MessageId::new(0x06, 0x8b);
+    pub const CFG_VALSET: MessageId = MessageId::new(0x06, 0x8a);
+    pub const CFG_VALDEL: MessageId = MessageId::new(0x06, 0x8c);
+    pub const NAV_EOE: MessageId = MessageId::new(0x01, 0x61);
+
+    pub const NAV_CLASS: u8 = 0x01;
+
+    pub const fn new(class: u8, id: u8) -> Self {
+        MessageId { class, id }
+    }
+
+    /// Returns the id of the ubx message in a raw frame.
+    pub fn from_frame(b: &[u8]) -> Option<Self> {
+        if !Ubx::contains_prefix(b) || b.len() < 4 {
+            return None;
+        }
+        Some(MessageId::new(b[2], b[3]))
+    }
+}
+
+impl fmt::Display for MessageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:02x}-0x{:02x}", self.class, self.id)
+    }
+}
 
 impl_struct! {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(default)]
 pub struct AckData{
     cls_id: u8,
@@ -11,9 +51,107 @@ pub struct AckData{
 }
 }
 
+impl AckData {
+    /// The id of the message this data refers to.
+    pub fn message_id(&self) -> MessageId {
+        MessageId::new(self.cls_id, self.msg_id)
+    }
+}
+
 impl_class! {
     pub enum Ack: PollAck{
         Ack(AckData)[2u16] = 0x01u8,
         Nak(AckData)[2u16] = 0x00u8,
     }
 }
+
+impl Ack {
+    /// Returns true if this is an acknowledgement of the message with the given class and id.
+    pub fn acks(&self, class: u8, id: u8) -> bool {
+        match *self {
+            Ack::Ack(ref x) => x.message_id() == MessageId::new(class, id),
+            _ => false,
+        }
+    }
+
+    /// Returns true if this is a negative acknowledgement of the message with the given class
+    /// and id.
+    pub fn naks(&self, class: u8, id: u8) -> bool {
+        match *self {
+            Ack::Nak(ref x) => x.message_id() == MessageId::new(class, id),
+            _ => false,
+        }
+    }
+
+    /// Returns whether the given message was acknowledged, or `None` if this message refers
+    /// to a different message.
+    pub fn result_for(&self, id: MessageId) -> Option<bool> {
+        if self.acks(id.class, id.id) {
+            Some(true)
+        } else if self.naks(id.class, id.id) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ack_of(id: MessageId) -> Ack {
+        Ack::Ack(AckData {
+            cls_id: id.class,
+            msg_id: id.id,
+        })
+    }
+
+    #[test]
+    fn acks_matches_class_and_id() {
+        let ack = ack_of(MessageId::CFG_VALSET);
+        assert!(ack.acks(0x06, 0x8a));
+        assert!(!ack.acks(0x06, 0x8b));
+        assert!(!ack.acks(0x01, 0x8a));
+        assert!(!ack.naks(0x06, 0x8a));
+
+        assert_eq!(ack.result_for(MessageId::CFG_VALSET), Some(true));
+        assert_eq!(ack.result_for(MessageId::CFG_VALGET), None);
+    }
+
+    #[test]
+    fn naks_matches_class_and_id() {
+        let nak = Ack::Nak(AckData {
+            cls_id: 0x06,
+            msg_id: 0x8b,
+        });
+        assert!(nak.naks(0x06, 0x8b));
+        assert!(!nak.acks(0x06, 0x8b));
+
+        assert_eq!(nak.result_for(MessageId::CFG_VALGET), Some(false));
+        assert_eq!(nak.result_for(MessageId::CFG_VALSET), None);
+    }
+
+    #[test]
+    fn message_id_from_frame() {
+        let frame = Ubx::Ack(ack_of(MessageId::CFG_VALSET))
+            .parse_to_vec()
+            .unwrap();
+        assert_eq!(
+            MessageId::from_frame(&frame),
+            Some(MessageId::new(0x05, 0x01))
+        );
+        assert_eq!(MessageId::from_frame(b"$GNGGA"), None);
+    }
+
+    #[test]
+    fn display_names_the_acknowledged_message() {
+        let ack = Ubx::Ack(ack_of(MessageId::CFG_VALSET));
+        assert_eq!(ack.to_string(), "UBX-ACK-ACK: 0x06-0x8a");
+        let nak = Ubx::Ack(Ack::Nak(AckData {
+            cls_id: 0x06,
+            msg_id: 0x8b,
+        }));
+        assert_eq!(nak.to_string(), "UBX-ACK-NAK: 0x06-0x8b");
+    }
+}
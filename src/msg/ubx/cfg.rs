@@ -1,8 +1,10 @@
 use std::io::Write;
 
+use super::Ubx;
 use crate::{
     impl_bitfield, impl_enum, impl_struct,
-    parse::{ser_bitflags, ParseData, ParseError, Result},
+    parse::{self, ser_bitflags, ParseData, ParseError, Result},
+    pread,
 };
 use anyhow::bail;
 use enumflags2::{bitflags, BitFlags};
@@ -12,6 +14,7 @@ mod values;
 pub use values::{Value, ValueKey};
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum TMode {
     Disabled,
     SurvayIn,
@@ -26,6 +29,7 @@ impl Default for TMode {
 }
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(default)]
 pub struct TModeFlags {
     pub lla: bool,
@@ -62,6 +66,7 @@ impl ParseData for TModeFlags {
 
 impl_struct! {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(default)]
     pub struct TMode3 {
         version: u8,
@@ -98,6 +103,7 @@ impl Default for Layer {
 
 impl_struct! {
     #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+    #[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(default)]
     pub struct ValGetRequest {
         layer: Layer,
@@ -108,6 +114,7 @@ impl_struct! {
 
 impl_struct! {
     #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+    #[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(default)]
     pub struct ValGetResponse{
         layer: Layer,
@@ -117,6 +124,7 @@ impl_struct! {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum ValGet {
     Request(ValGetRequest),
     Response(ValGetResponse),
@@ -165,6 +173,7 @@ impl ParseData for ValGet {
 #[bitflags]
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum BitLayer {
     Ram = 0b001,
     Bbr = 0b010,
@@ -174,15 +183,44 @@ pub enum BitLayer {
 impl_bitfield!(BitLayer);
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(default)]
 pub struct ValSet {
     pub version: u8,
+    #[cfg_attr(feature = "schemars", schemars(with = "Vec<BitLayer>"))]
     #[serde(with = "ser_bitflags")]
     pub layers: BitFlags<BitLayer>,
     pub res1: [u8; 2],
     pub values: Vec<Value>,
 }
 
+impl ValSet {
+    /// Create a message setting the values in the given layers.
+    pub fn new(layers: BitFlags<BitLayer>, values: Vec<Value>) -> Self {
+        ValSet {
+            version: 0,
+            layers,
+            res1: [0; 2],
+            values,
+        }
+    }
+
+    /// A message setting the values in the battery backed ram.
+    pub fn to_bbr(values: Vec<Value>) -> Ubx {
+        Ubx::Cfg(Cfg::ValSet(ValSet::new(BitLayer::Bbr.into(), values)))
+    }
+
+    /// A message setting the values in flash, the values are used after the next restart.
+    pub fn to_flash(values: Vec<Value>) -> Ubx {
+        Ubx::Cfg(Cfg::ValSet(ValSet::new(BitLayer::Flash.into(), values)))
+    }
+
+    /// A message setting the values in ram, battery backed ram and flash.
+    pub fn to_all_layers(values: Vec<Value>) -> Ubx {
+        Ubx::Cfg(Cfg::ValSet(ValSet::new(BitFlags::all(), values)))
+    }
+}
+
 impl ParseData for ValSet {
     fn parse_read(b: &[u8]) -> Result<(&[u8], Self)> {
         let (b, len) = u16::parse_read(b)?;
@@ -223,9 +261,77 @@ impl ParseData for ValSet {
     }
 }
 
+/// Delete values from battery backed ram or flash, the device falls back to the default for
+/// deleted values.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct ValDel {
+    pub version: u8,
+    /// Only the bbr and flash layers are valid.
+    #[cfg_attr(feature = "schemars", schemars(with = "Vec<BitLayer>"))]
+    #[serde(with = "ser_bitflags")]
+    pub layers: BitFlags<BitLayer>,
+    pub res1: [u8; 2],
+    pub keys: Vec<ValueKey>,
+}
+
+impl ValDel {
+    /// Create a message deleting the keys from the given layers.
+    pub fn new(layers: BitFlags<BitLayer>, keys: Vec<ValueKey>) -> Self {
+        ValDel {
+            version: 0,
+            layers,
+            res1: [0; 2],
+            keys,
+        }
+    }
+}
+
+impl ParseData for ValDel {
+    fn parse_read(b: &[u8]) -> Result<(&[u8], Self)> {
+        let (b, len) = u16::parse_read(b)?;
+        if b.len() < len as usize {
+            bail!(ParseError::NotEnoughData);
+        }
+        let (b, rem) = b.split_at(len.into());
+        let (b, version) = u8::parse_read(b)?;
+        if version != 0 {
+            bail!(ParseError::Invalid);
+        }
+        let (b, layers) = ParseData::parse_read(b)?;
+        let (b, res1) = ParseData::parse_read(b)?;
+        let (_, keys) = ParseData::parse_read(b)?;
+        Ok((
+            rem,
+            ValDel {
+                version,
+                layers,
+                res1,
+                keys,
+            },
+        ))
+    }
+
+    fn parse_write<W: Write>(&self, b: &mut W) -> Result<()> {
+        let mut buffer = Vec::new();
+
+        self.version.parse_write(&mut buffer).unwrap();
+        self.layers.parse_write(&mut buffer).unwrap();
+        self.res1.parse_write(&mut buffer).unwrap();
+        self.keys.parse_write(&mut buffer).unwrap();
+
+        let len = u16::try_from(buffer.len()).map_err(|_| ParseError::InvalidLen)?;
+        len.parse_write(b)?;
+        b.write_all(&buffer)?;
+        Ok(())
+    }
+}
+
 #[bitflags]
 #[repr(u16)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum BbrMask {
     Ephemeris = 0b1,
     Almanac = 0b10,
@@ -260,18 +366,297 @@ impl Default for ResetMode {
 
 impl_struct! {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Rst {
+    #[cfg_attr(feature = "schemars", schemars(with = "u16"))]
     nav_bbr_mask: BitFlags<BbrMask>,
     reset_mode: ResetMode,
     res1: u8,
 }
 }
 
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct Tp5Flags {
+    pub active: bool,
+    pub lock_gnss_freq: bool,
+    pub locked_other_set: bool,
+    pub is_freq: bool,
+    pub is_length: bool,
+    pub align_to_tow: bool,
+    pub polarity: bool,
+    pub grid_utc_gnss: u8,
+    pub sync_mode: u8,
+}
+
+impl ParseData for Tp5Flags {
+    fn parse_read(b: &[u8]) -> Result<(&[u8], Self)> {
+        let (b, d) = u32::parse_read(b)?;
+
+        Ok((
+            b,
+            Tp5Flags {
+                active: d & 0b1 != 0,
+                lock_gnss_freq: d >> 1 & 0b1 != 0,
+                locked_other_set: d >> 2 & 0b1 != 0,
+                is_freq: d >> 3 & 0b1 != 0,
+                is_length: d >> 4 & 0b1 != 0,
+                align_to_tow: d >> 5 & 0b1 != 0,
+                polarity: d >> 6 & 0b1 != 0,
+                grid_utc_gnss: (d >> 7 & 0b1111) as u8,
+                sync_mode: (d >> 11 & 0b111) as u8,
+            },
+        ))
+    }
+
+    fn parse_write<W: Write>(&self, b: &mut W) -> Result<()> {
+        let data = self.active as u32
+            | (self.lock_gnss_freq as u32) << 1
+            | (self.locked_other_set as u32) << 2
+            | (self.is_freq as u32) << 3
+            | (self.is_length as u32) << 4
+            | (self.align_to_tow as u32) << 5
+            | (self.polarity as u32) << 6
+            | (self.grid_utc_gnss as u32 & 0b1111) << 7
+            | (self.sync_mode as u32 & 0b111) << 11;
+        data.parse_write(b)
+    }
+}
+
+impl_struct! {
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(default)]
+    pub struct Tp5Config {
+        tp_idx: u8,
+        version: u8,
+        res1: [u8;2],
+        ant_cable_delay: i16,
+        rf_group_delay: i16,
+        freq_period: u32,
+        freq_period_lock: u32,
+        pulse_len_ratio: u32,
+        pulse_len_ratio_lock: u32,
+        user_config_delay: i32,
+        flags: Tp5Flags,
+    }
+}
+
+/// Timepulse configuration, the poll request contains the index of the timepulse to poll.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum Tp5 {
+    Poll(u8),
+    Config(Tp5Config),
+}
+
+impl ParseData for Tp5 {
+    fn parse_read(b: &[u8]) -> Result<(&[u8], Self)> {
+        let (b, len) = u16::parse_read(b)?;
+        match len {
+            1 => {
+                let (b, idx) = u8::parse_read(b)?;
+                Ok((b, Self::Poll(idx)))
+            }
+            32 => {
+                let (b, res) = Tp5Config::parse_read(b)?;
+                Ok((b, Self::Config(res)))
+            }
+            _ => bail!(ParseError::InvalidLen),
+        }
+    }
+
+    fn parse_write<W: Write>(&self, b: &mut W) -> Result<()> {
+        match *self {
+            Self::Poll(idx) => {
+                1u16.parse_write(b)?;
+                idx.parse_write(b)
+            }
+            Self::Config(ref x) => {
+                32u16.parse_write(b)?;
+                x.parse_write(b)
+            }
+        }
+    }
+}
+
+impl_struct! {
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct GnssBlock {
+    gnss_id: u8,
+    res_trk_ch: u8,
+    max_trk_ch: u8,
+    res1: u8,
+    flags: u32,
+}
+}
+
+impl GnssBlock {
+    const ENABLE: u32 = 0b1;
+
+    pub fn enabled(&self) -> bool {
+        self.flags & Self::ENABLE != 0
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled {
+            self.flags |= Self::ENABLE;
+        } else {
+            self.flags &= !Self::ENABLE;
+        }
+    }
+}
+
+/// The constellation configuration of M8 modules, F9 modules use the `Signal*` config values
+/// instead.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct Gnss {
+    pub msg_ver: u8,
+    pub num_trk_ch_hw: u8,
+    pub num_trk_ch_use: u8,
+    pub num_config_blocks: u8,
+    pub blocks: Vec<GnssBlock>,
+}
+
+impl ParseData for Gnss {
+    fn parse_read(b: &[u8]) -> Result<(&[u8], Self)> {
+        pread!(b => {
+            len: u16,
+            msg_ver: u8,
+            num_trk_ch_hw: u8,
+            num_trk_ch_use: u8,
+            num_config_blocks: u8,
+        });
+        if len as usize != 4 + num_config_blocks as usize * 8 {
+            bail!(ParseError::InvalidLen);
+        }
+        let (b, blocks) = parse::collect(b, num_config_blocks as usize)?;
+        Ok((
+            b,
+            Gnss {
+                msg_ver,
+                num_trk_ch_hw,
+                num_trk_ch_use,
+                num_config_blocks,
+                blocks,
+            },
+        ))
+    }
+
+    fn parse_write<W: Write>(&self, b: &mut W) -> Result<()> {
+        let len = u16::try_from(self.blocks.len() * 8 + 4).map_err(|_| ParseError::Invalid)?;
+        len.parse_write(b)?;
+        self.msg_ver.parse_write(b)?;
+        self.num_trk_ch_hw.parse_write(b)?;
+        self.num_trk_ch_use.parse_write(b)?;
+        u8::try_from(self.blocks.len())
+            .map_err(|_| ParseError::Invalid)?
+            .parse_write(b)?;
+        self.blocks.parse_write(b)
+    }
+}
+
 impl_class! {
     pub enum Cfg: PollCfg {
+        Gnss(Gnss) = 0x3e,
         TMode3(TMode3)[40] = 0x71,
         ValGet(ValGet) = 0x8b,
         ValSet(ValSet) = 0x8a,
+        ValDel(ValDel) = 0x8c,
         Rst(Rst)[4] = 0x04,
+        Tp5(Tp5) = 0x31,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serialize a configuration message and parse it again.
+    fn round_trip(cfg: Cfg) -> Cfg {
+        let b = Ubx::Cfg(cfg).parse_to_vec().unwrap();
+        assert_eq!(Ubx::message_usage(&b), Some(b.len()));
+        match Ubx::parse_read(&b).unwrap() {
+            ([], Ubx::Cfg(x)) => x,
+            x => panic!("expected a single configuration message, found {x:?}"),
+        }
+    }
+
+    #[test]
+    fn gnss_round_trip() {
+        let block = |gnss_id, flags| GnssBlock {
+            gnss_id,
+            res_trk_ch: 8,
+            max_trk_ch: 16,
+            res1: 0,
+            flags,
+        };
+        let mut glonass = block(6, 0x0001_0000);
+        glonass.set_enabled(true);
+        let mut gps = block(0, 0x0001_0001);
+        gps.set_enabled(false);
+        assert!(glonass.enabled());
+        assert!(!gps.enabled());
+        // Only the enable bit is changed, the signal configuration is kept.
+        assert_eq!((gps.flags, glonass.flags), (0x0001_0000, 0x0001_0001));
+
+        let gnss = Gnss {
+            msg_ver: 0,
+            num_trk_ch_hw: 32,
+            num_trk_ch_use: 32,
+            num_config_blocks: 2,
+            blocks: vec![gps, glonass],
+        };
+        let b = Ubx::Cfg(Cfg::Gnss(gnss.clone())).parse_to_vec().unwrap();
+        assert_eq!(u16::from_le_bytes([b[4], b[5]]), 4 + 2 * 8);
+        match round_trip(Cfg::Gnss(gnss.clone())) {
+            Cfg::Gnss(x) => assert_eq!(x, gnss),
+            x => panic!("expected CFG-GNSS, found {x:?}"),
+        }
+    }
+
+    #[test]
+    fn val_del_round_trip() {
+        let val_del = ValDel::new(
+            BitLayer::Bbr | BitLayer::Flash,
+            vec![ValueKey::Uart1Baudrate, ValueKey::RateMeas],
+        );
+        let b = Ubx::Cfg(Cfg::ValDel(val_del.clone()))
+            .parse_to_vec()
+            .unwrap();
+        // Version, layers, reserved bytes and the 4 byte key ids.
+        assert_eq!(u16::from_le_bytes([b[4], b[5]]), 4 + 2 * 4);
+        assert_eq!(b[7], 0b110);
+        assert_eq!(&b[10..14], &0x40520001u32.to_le_bytes());
+        match round_trip(Cfg::ValDel(val_del.clone())) {
+            Cfg::ValDel(x) => assert_eq!(x, val_del),
+            x => panic!("expected CFG-VALDEL, found {x:?}"),
+        }
+    }
+
+    #[test]
+    fn val_set_layer_constructors() {
+        let layers = |msg: Ubx| {
+            let b = msg.parse_to_vec().unwrap();
+            // The layers byte follows the length and the version.
+            b[7]
+        };
+        let values = || vec![Value::RateMeas(100)];
+        assert_eq!(layers(ValSet::to_bbr(values())), 0b010);
+        assert_eq!(layers(ValSet::to_flash(values())), 0b100);
+        assert_eq!(layers(ValSet::to_all_layers(values())), 0b111);
+
+        match ValSet::to_all_layers(values()) {
+            Ubx::Cfg(Cfg::ValSet(x)) => {
+                assert_eq!(x.layers, BitLayer::Ram | BitLayer::Bbr | BitLayer::Flash);
+                assert_eq!(x.values, values());
+            }
+            x => panic!("expected CFG-VALSET, found {x:?}"),
+        }
     }
 }
@@ -1,6 +1,6 @@
 use enumflags2::{bitflags, BitFlags};
 use serde::{Deserialize, Serialize};
-use std::io::Write;
+use std::{any::Any, fmt, io::Write};
 
 use crate::{
     impl_bitfield, impl_enum,
@@ -12,6 +12,7 @@ use clap::ValueEnum;
 #[bitflags]
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum MsgMask {
     Error = 0x01,
     Warning = 0x02,
@@ -29,6 +30,126 @@ impl_enum! {
     }
 }
 
+/// Types of configuration values, used for generating example configurations.
+pub trait ExampleValue {
+    fn example() -> Self;
+}
+
+macro_rules! impl_example_value {
+    ($($t:ty = $v:expr),*$(,)?) => {
+        $(impl ExampleValue for $t {
+            fn example() -> Self {
+                $v
+            }
+        })*
+    };
+}
+
+impl_example_value! {
+    bool = false,
+    u8 = 0,
+    u16 = 0,
+    u32 = 0,
+    i8 = 0,
+    i32 = 0,
+    BitFlags<MsgMask> = BitFlags::empty(),
+    StopBits = StopBits::One,
+    Databits = Databits::Eight,
+    Parity = Parity::None,
+    OdoProfile = OdoProfile::Run,
+    RtkMode = RtkMode::Fixed,
+    Tmode = Tmode::Disabled,
+    PosType = PosType::Ecef,
+}
+
+/// The kind of data stored in a configuration value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    Bool,
+    U8,
+    U16,
+    U32,
+    I8,
+    I32,
+    Enum,
+    Bitfield,
+}
+
+/// Types of configuration values and their kind.
+pub trait ValueType: Copy + 'static {
+    const KIND: ValueKind;
+}
+
+macro_rules! impl_value_type {
+    ($($t:ty = $kind:ident),*$(,)?) => {
+        $(impl ValueType for $t {
+            const KIND: ValueKind = ValueKind::$kind;
+        })*
+    };
+}
+
+impl_value_type! {
+    bool = Bool,
+    u8 = U8,
+    u16 = U16,
+    u32 = U32,
+    i8 = I8,
+    i32 = I32,
+    BitFlags<MsgMask> = Bitfield,
+    StopBits = Enum,
+    Databits = Enum,
+    Parity = Enum,
+    OdoProfile = Enum,
+    RtkMode = Enum,
+    Tmode = Enum,
+    PosType = Enum,
+}
+
+impl Value {
+    pub fn try_as_bool(&self) -> std::result::Result<bool, ValueTypeError> {
+        self.try_as()
+    }
+
+    pub fn try_as_u8(&self) -> std::result::Result<u8, ValueTypeError> {
+        self.try_as()
+    }
+
+    pub fn try_as_u16(&self) -> std::result::Result<u16, ValueTypeError> {
+        self.try_as()
+    }
+
+    pub fn try_as_u32(&self) -> std::result::Result<u32, ValueTypeError> {
+        self.try_as()
+    }
+
+    pub fn try_as_i8(&self) -> std::result::Result<i8, ValueTypeError> {
+        self.try_as()
+    }
+
+    pub fn try_as_i32(&self) -> std::result::Result<i32, ValueTypeError> {
+        self.try_as()
+    }
+}
+
+/// The error returned when accessing a value as the wrong type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueTypeError {
+    pub expected: ValueKind,
+    pub actual: ValueKind,
+}
+
+impl fmt::Display for ValueTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "expected a value of kind `{:?}`, found `{:?}`",
+            self.expected, self.actual
+        )
+    }
+}
+
+impl std::error::Error for ValueTypeError {}
+
 macro_rules! impl_value{
     (
         pub enum Value{
@@ -36,12 +157,14 @@ macro_rules! impl_value{
     }) => {
 
         #[derive(Debug,Clone,Copy,Eq,PartialEq, Serialize,Deserialize)]
+        #[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
         #[serde(tag = "kind",content="value", rename_all = "kebab-case")]
         pub enum Value{
             $($name($(#[$m])*$ty),)*
         }
 
         #[derive(Debug,Clone,Copy,Eq,PartialEq, Serialize,Deserialize, ValueEnum)]
+        #[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
         #[serde(rename_all = "kebab-case")]
         pub enum ValueKey{
             $($name,)*
@@ -72,20 +195,12 @@ macro_rules! impl_value{
         impl ParseData for ValueKey{
             fn parse_read(b: &[u8]) -> Result<(&[u8],Self)>{
                 let (b,id) = u32::parse_read(b)?;
-                match id{
-                    $($id => {
-                        Ok((b,Self::$name))
-                    })*
-                    _ => Err(ParseError::Invalid.into())
-                }
+                let key = Self::from_id(id).ok_or(ParseError::Invalid)?;
+                Ok((b,key))
             }
 
             fn parse_write<W: Write>(&self, buffer: &mut W) -> Result<()>{
-                match *self{
-                    $(Self::$name => {
-                        ($id as u32).parse_write(buffer)
-                    },)*
-                }
+                self.id().parse_write(buffer)
             }
         }
 
@@ -103,6 +218,54 @@ macro_rules! impl_value{
                     $(Self::$name(_) => ValueKey::$name,)*
                 }
             }
+
+            pub fn value_kind(&self) -> ValueKind{
+                match *self{
+                    $(Self::$name(_) => <$ty as ValueType>::KIND,)*
+                }
+            }
+
+            /// The data of the value if it is of type `T`.
+            pub fn try_as<T: ValueType>(&self) -> std::result::Result<T, ValueTypeError>{
+                let data: &dyn Any = match *self{
+                    $(Self::$name(ref x) => x,)*
+                };
+                data.downcast_ref::<T>().copied().ok_or(ValueTypeError{
+                    expected: T::KIND,
+                    actual: self.value_kind(),
+                })
+            }
+        }
+
+        impl ValueKey{
+            /// The key id as listed in the interface description.
+            pub fn id(&self) -> u32{
+                match *self{
+                    $(Self::$name => $id,)*
+                }
+            }
+
+            /// The key with the given id, if the key is known.
+            pub fn from_id(id: u32) -> Option<Self>{
+                match id{
+                    $($id => Some(Self::$name),)*
+                    _ => None
+                }
+            }
+
+            /// The name of the type of the value for this key.
+            pub fn type_name(&self) -> &'static str{
+                match *self{
+                    $(Self::$name => stringify!($ty),)*
+                }
+            }
+
+            /// An example value for this key.
+            pub fn example(&self) -> Value{
+                match *self{
+                    $(Self::$name => Value::$name(<$ty as ExampleValue>::example()),)*
+                }
+            }
         }
     }
 }
@@ -214,26 +377,32 @@ impl_value! {
         Uart2Remap(bool) = 0x20530006,
 
         InfmsgUbxUart1(
+            #[cfg_attr(feature = "schemars", schemars(with = "Vec<MsgMask>"))]
             #[serde(with = "ser_bitflags")]
             BitFlags<MsgMask>
         ) = 0x20920002,
         InfmsgUbxUart2(
+            #[cfg_attr(feature = "schemars", schemars(with = "Vec<MsgMask>"))]
             #[serde(with = "ser_bitflags")]
             BitFlags<MsgMask>
                        ) = 0x20920003,
         InfmsgUbxUsb(
+            #[cfg_attr(feature = "schemars", schemars(with = "Vec<MsgMask>"))]
             #[serde(with = "ser_bitflags")]
             BitFlags<MsgMask>
             ) = 0x20920004,
         InfmsgNmeaUart1(
+            #[cfg_attr(feature = "schemars", schemars(with = "Vec<MsgMask>"))]
             #[serde(with = "ser_bitflags")]
             BitFlags<MsgMask>
             ) = 0x20920007,
         InfmsgNmeaUart2(
+            #[cfg_attr(feature = "schemars", schemars(with = "Vec<MsgMask>"))]
             #[serde(with = "ser_bitflags")]
             BitFlags<MsgMask>
             ) = 0x20920008,
         InfmsgNmeaUsb(
+            #[cfg_attr(feature = "schemars", schemars(with = "Vec<MsgMask>"))]
             #[serde(with = "ser_bitflags")]
             BitFlags<MsgMask>
             ) = 0x20920009,
@@ -335,3 +504,291 @@ impl_value! {
         SignalGloL2Ena(bool) = 0x1031001a,
     }
 }
+
+impl ValueKey {
+    /// A short description of the key.
+    pub fn description(&self) -> &'static str {
+        use ValueKey::*;
+        match *self {
+            RateMeas => "Nominal time between GNSS measurements",
+            RateNav => "Number of measurements per navigation solution",
+            UsbInprotUbx => "Accept UBX on USB",
+            UsbInprotNmea => "Accept NMEA on USB",
+            UsbInprotRtcm3x => "Accept RTCM3 on USB",
+            UsbOutprotUbx => "Output UBX on USB",
+            UsbOutprotNmea => "Output NMEA on USB",
+            UsbOutprotRtcm3x => "Output RTCM3 on USB",
+            SpiInprotUbx => "Accept UBX on SPI",
+            SpiInprotNmea => "Accept NMEA on SPI",
+            SpiInprotRtcm3x => "Accept RTCM3 on SPI",
+            SpiOutprotUbx => "Output UBX on SPI",
+            SpiOutprotNmea => "Output NMEA on SPI",
+            SpiOutprotRtcm3x => "Output RTCM3 on SPI",
+            Uart1InprotUbx => "Accept UBX on UART1",
+            Uart1InprotNmea => "Accept NMEA on UART1",
+            Uart1InprotRtcm3x => "Accept RTCM3 on UART1",
+            Uart1OutprotUbx => "Output UBX on UART1",
+            Uart1OutprotNmea => "Output NMEA on UART1",
+            Uart1OutprotRtcm3x => "Output RTCM3 on UART1",
+            Uart2InprotUbx => "Accept UBX on UART2",
+            Uart2InprotNmea => "Accept NMEA on UART2",
+            Uart2InprotRtcm3x => "Accept RTCM3 on UART2",
+            Uart2OutprotUbx => "Output UBX on UART2",
+            Uart2OutprotNmea => "Output NMEA on UART2",
+            Uart2OutprotRtcm3x => "Output RTCM3 on UART2",
+            Uart1Baudrate => "UART1 baud rate",
+            Uart1StopBits => "UART1 number of stop bits",
+            Uart1Databits => "UART1 number of data bits",
+            Uart1Parity => "UART1 parity mode",
+            Uart1Enabled => "Enable UART1",
+            Uart2Baudrate => "UART2 baud rate",
+            Uart2StopBits => "UART2 number of stop bits",
+            Uart2Databits => "UART2 number of data bits",
+            Uart2Parity => "UART2 parity mode",
+            Uart2Enabled => "Enable UART2",
+            Uart2Remap => "Remap UART2 to alternative pins",
+            InfmsgUbxUart1 => "Information messages enabled for UBX on UART1",
+            InfmsgUbxUart2 => "Information messages enabled for UBX on UART2",
+            InfmsgUbxUsb => "Information messages enabled for UBX on USB",
+            InfmsgNmeaUart1 => "Information messages enabled for NMEA on UART1",
+            InfmsgNmeaUart2 => "Information messages enabled for NMEA on UART2",
+            InfmsgNmeaUsb => "Information messages enabled for NMEA on USB",
+            MsgoutRtcm3xType1005Usb => "Output rate of RTCM3 1005 on USB",
+            MsgoutRtcm3xType1074Usb => "Output rate of RTCM3 1074 on USB",
+            MsgoutRtcm3xType1077Usb => "Output rate of RTCM3 1077 on USB",
+            MsgoutRtcm3xType1084Usb => "Output rate of RTCM3 1084 on USB",
+            MsgoutRtcm3xType1087Usb => "Output rate of RTCM3 1087 on USB",
+            MsgoutRtcm3xType1094Usb => "Output rate of RTCM3 1094 on USB",
+            MsgoutRtcm3xType1097Usb => "Output rate of RTCM3 1097 on USB",
+            MsgoutRtcm3xType1124Usb => "Output rate of RTCM3 1124 on USB",
+            MsgoutRtcm3xType1127Usb => "Output rate of RTCM3 1127 on USB",
+            MsgoutRtcm3xType1230Usb => "Output rate of RTCM3 1230 on USB",
+            MsgoutRtcm3xType4072_0Usb => "Output rate of RTCM3 4072.0 on USB",
+            MsgoutRtcm3xType4072_1Usb => "Output rate of RTCM3 4072.1 on USB",
+            MsgoutUbxLogInfoUsb => "Output rate of UBX-LOG-INFO on USB",
+            MsgoutUbxMonHw2Usb => "Output rate of UBX-MON-HW2 on USB",
+            MsgoutUbxMonHw3Usb => "Output rate of UBX-MON-HW3 on USB",
+            MsgoutUbxMonHwUsb => "Output rate of UBX-MON-HW on USB",
+            MsgoutUbxMonIoUsb => "Output rate of UBX-MON-IO on USB",
+            MsgoutUbxMonCommsUsb => "Output rate of UBX-MON-COMMS on USB",
+            MsgoutUbxMonMsgppUsb => "Output rate of UBX-MON-MSGPP on USB",
+            MsgoutUbxMonRfUsb => "Output rate of UBX-MON-RF on USB",
+            MsgoutUbxMonRxbufUsb => "Output rate of UBX-MON-RXBUF on USB",
+            MsgoutUbxMonRxrUsb => "Output rate of UBX-MON-RXR on USB",
+            MsgoutUbxMonTxbufUsb => "Output rate of UBX-MON-TXBUF on USB",
+            MsgoutUbxNavClockUsb => "Output rate of UBX-NAV-CLOCK on USB",
+            MsgoutUbxNavDopUsb => "Output rate of UBX-NAV-DOP on USB",
+            MsgoutUbxNavEoeUsb => "Output rate of UBX-NAV-EOE on USB",
+            MsgoutUbxNavHpposecefUsb => "Output rate of UBX-NAV-HPPOSECEF on USB",
+            MsgoutUbxNavHpposllhUsb => "Output rate of UBX-NAV-HPPOSLLH on USB",
+            MsgoutUbxNavOdoUsb => "Output rate of UBX-NAV-ODO on USB",
+            MsgoutUbxNavOrbUsb => "Output rate of UBX-NAV-ORB on USB",
+            MsgoutUbxNavPosecefUsb => "Output rate of UBX-NAV-POSECEF on USB",
+            MsgoutUbxNavPosllhUsb => "Output rate of UBX-NAV-POSLLH on USB",
+            MsgoutUbxNavPvtUsb => "Output rate of UBX-NAV-PVT on USB",
+            MsgoutUbxNavRelPosNedUsb => "Output rate of UBX-NAV-RELPOSNED on USB",
+            MsgoutUbxNavSatUsb => "Output rate of UBX-NAV-SAT on USB",
+            MsgoutUbxNavSigUsb => "Output rate of UBX-NAV-SIG on USB",
+            MsgoutUbxNavStatusUsb => "Output rate of UBX-NAV-STATUS on USB",
+            MsgoutUbxNavSvinUsb => "Output rate of UBX-NAV-SVIN on USB",
+            MsgoutUbxNavTimebdsUsb => "Output rate of UBX-NAV-TIMEBDS on USB",
+            MsgoutUbxNavTimegalUsb => "Output rate of UBX-NAV-TIMEGAL on USB",
+            MsgoutUbxNavTimegloUsb => "Output rate of UBX-NAV-TIMEGLO on USB",
+            MsgoutUbxNavTimegpsUsb => "Output rate of UBX-NAV-TIMEGPS on USB",
+            MsgoutUbxNavTimelsUsb => "Output rate of UBX-NAV-TIMELS on USB",
+            MsgoutUbxNavTimeutcUsb => "Output rate of UBX-NAV-TIMEUTC on USB",
+            MsgoutUbxNavVelecefUsb => "Output rate of UBX-NAV-VELECEF on USB",
+            MsgoutUbxNavVelnedUsb => "Output rate of UBX-NAV-VELNED on USB",
+            MsgoutUbxRxmMeasxUsb => "Output rate of UBX-RXM-MEASX on USB",
+            MsgoutUbxRxmRawxUsb => "Output rate of UBX-RXM-RAWX on USB",
+            MsgoutUbxRxmRlmUsb => "Output rate of UBX-RXM-RLM on USB",
+            MsgoutUbxRxmRtcmUsb => "Output rate of UBX-RXM-RTCM on USB",
+            MsgoutUbxRxmSfrbxUsb => "Output rate of UBX-RXM-SFRBX on USB",
+            OdoUseOdo => "Use the odometer",
+            OdoUseCog => "Use the low-speed course over ground filter",
+            OdoOutlpvel => "Output the low-pass filtered velocity",
+            OdoOutlpcog => "Output the low-pass filtered course over ground",
+            OdoProfile => "Odometer profile",
+            OdoCogmaxspeed => "Upper speed limit for the low-speed course over ground filter",
+            OdoCogmaxposacc => {
+                "Maximum position accuracy for the low-speed course over ground filter"
+            }
+            OdoVellpgain => "Velocity low-pass filter level",
+            OdoCoglpgain => "Course over ground low-pass filter level",
+            NavhpgDgnssmode => "Differential corrections mode",
+            TmodeMode => "Time mode of the receiver",
+            TmodePosType => "Type of the fixed position",
+            TmodeEcefX => "Fixed position ECEF X",
+            TmodeEcefY => "Fixed position ECEF Y",
+            TmodeEcefZ => "Fixed position ECEF Z",
+            TmodeEcefXHp => "High precision part of the fixed position ECEF X",
+            TmodeEcefYHp => "High precision part of the fixed position ECEF Y",
+            TmodeEcefZHp => "High precision part of the fixed position ECEF Z",
+            TmodeFixedPosAcc => "Accuracy of the fixed position",
+            TmodeSvinMinDur => "Minimum survey-in duration",
+            TmodeSvinAccLimit => "Required survey-in position accuracy",
+            SignalGpsEna => "Enable GPS",
+            SignalGpsL1caEna => "Enable GPS L1C/A",
+            SignalGpsL2cEna => "Enable GPS L2C",
+            SignalGalEna => "Enable Galileo",
+            SignalGalE1Ena => "Enable Galileo E1",
+            SignalGalE5bEna => "Enable Galileo E5b",
+            SignalBdsEna => "Enable BeiDou",
+            SignalBdsB1Ena => "Enable BeiDou B1I",
+            SignalBdsB2Ena => "Enable BeiDou B2I",
+            SignalQzssEna => "Enable QZSS",
+            SignalQzssL1caEna => "Enable QZSS L1C/A",
+            SignalQzssL2cEna => "Enable QZSS L2C",
+            SignalGloEna => "Enable GLONASS",
+            SignalGloL1Ena => "Enable GLONASS L1",
+            SignalGloL2Ena => "Enable GLONASS L2",
+        }
+    }
+
+    /// The unit of the value of the key, if it has one.
+    pub fn unit(&self) -> Option<&'static str> {
+        use ValueKey::*;
+        match *self {
+            RateMeas => Some("ms"),
+            RateNav => Some("cycles"),
+            Uart1Baudrate | Uart2Baudrate => Some("bps"),
+            MsgoutRtcm3xType1005Usb
+            | MsgoutRtcm3xType1074Usb
+            | MsgoutRtcm3xType1077Usb
+            | MsgoutRtcm3xType1084Usb
+            | MsgoutRtcm3xType1087Usb
+            | MsgoutRtcm3xType1094Usb
+            | MsgoutRtcm3xType1097Usb
+            | MsgoutRtcm3xType1124Usb
+            | MsgoutRtcm3xType1127Usb
+            | MsgoutRtcm3xType1230Usb
+            | MsgoutRtcm3xType4072_0Usb
+            | MsgoutRtcm3xType4072_1Usb
+            | MsgoutUbxLogInfoUsb
+            | MsgoutUbxMonHw2Usb
+            | MsgoutUbxMonHw3Usb
+            | MsgoutUbxMonHwUsb
+            | MsgoutUbxMonIoUsb
+            | MsgoutUbxMonCommsUsb
+            | MsgoutUbxMonMsgppUsb
+            | MsgoutUbxMonRfUsb
+            | MsgoutUbxMonRxbufUsb
+            | MsgoutUbxMonRxrUsb
+            | MsgoutUbxMonTxbufUsb
+            | MsgoutUbxNavClockUsb
+            | MsgoutUbxNavDopUsb
+            | MsgoutUbxNavEoeUsb
+            | MsgoutUbxNavHpposecefUsb
+            | MsgoutUbxNavHpposllhUsb
+            | MsgoutUbxNavOdoUsb
+            | MsgoutUbxNavOrbUsb
+            | MsgoutUbxNavPosecefUsb
+            | MsgoutUbxNavPosllhUsb
+            | MsgoutUbxNavPvtUsb
+            | MsgoutUbxNavRelPosNedUsb
+            | MsgoutUbxNavSatUsb
+            | MsgoutUbxNavSigUsb
+            | MsgoutUbxNavStatusUsb
+            | MsgoutUbxNavSvinUsb
+            | MsgoutUbxNavTimebdsUsb
+            | MsgoutUbxNavTimegalUsb
+            | MsgoutUbxNavTimegloUsb
+            | MsgoutUbxNavTimegpsUsb
+            | MsgoutUbxNavTimelsUsb
+            | MsgoutUbxNavTimeutcUsb
+            | MsgoutUbxNavVelecefUsb
+            | MsgoutUbxNavVelnedUsb
+            | MsgoutUbxRxmMeasxUsb
+            | MsgoutUbxRxmRawxUsb
+            | MsgoutUbxRxmRlmUsb
+            | MsgoutUbxRxmRtcmUsb
+            | MsgoutUbxRxmSfrbxUsb => Some("epochs"),
+            OdoCogmaxspeed => Some("m/s"),
+            OdoCogmaxposacc => Some("m"),
+            TmodeEcefX | TmodeEcefY | TmodeEcefZ => Some("cm"),
+            TmodeEcefXHp | TmodeEcefYHp | TmodeEcefZHp | TmodeFixedPosAcc | TmodeSvinAccLimit => {
+                Some("0.1 mm")
+            }
+            TmodeSvinMinDur => Some("s"),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accessors_return_values_of_the_matching_type() {
+        assert_eq!(Value::UsbInprotUbx(true).try_as_bool(), Ok(true));
+        assert_eq!(Value::RateMeas(1000).try_as_u16(), Ok(1000));
+        assert_eq!(Value::Uart1Baudrate(115_200).try_as_u32(), Ok(115_200));
+        assert_eq!(Value::TmodeEcefX(-123_456).try_as_i32(), Ok(-123_456));
+        assert_eq!(Value::TmodeEcefXHp(-7).try_as_i8(), Ok(-7));
+        assert_eq!(
+            Value::Uart1StopBits(StopBits::Two).try_as::<StopBits>(),
+            Ok(StopBits::Two)
+        );
+    }
+
+    #[test]
+    fn accessors_report_mismatched_types() {
+        assert_eq!(
+            Value::Uart1Baudrate(115_200).try_as_u16(),
+            Err(ValueTypeError {
+                expected: ValueKind::U16,
+                actual: ValueKind::U32,
+            })
+        );
+        assert_eq!(
+            Value::UsbInprotUbx(true).try_as_u8(),
+            Err(ValueTypeError {
+                expected: ValueKind::U8,
+                actual: ValueKind::Bool,
+            })
+        );
+        let e = Value::Uart1StopBits(StopBits::One)
+            .try_as_i32()
+            .unwrap_err();
+        assert_eq!(e.actual, ValueKind::Enum);
+        assert_eq!(
+            e.to_string(),
+            "expected a value of kind `I32`, found `Enum`"
+        );
+        // Enums of a different type do not match even though they are of the same kind.
+        assert!(Value::TmodeMode(Tmode::Disabled)
+            .try_as::<StopBits>()
+            .is_err());
+    }
+
+    #[test]
+    fn keys_round_trip_through_name_and_id() {
+        let mut ids = std::collections::HashSet::new();
+        for &key in ValueKey::value_variants() {
+            assert_eq!(ValueKey::from_id(key.id()), Some(key));
+            assert!(ids.insert(key.id()), "duplicate id for {key:?}");
+
+            let name = serde_json::to_string(&key).unwrap();
+            assert_eq!(serde_json::from_str::<ValueKey>(&name).unwrap(), key);
+
+            let mut b = Vec::new();
+            key.parse_write(&mut b).unwrap();
+            assert_eq!(b, key.id().to_le_bytes());
+            assert_eq!(ValueKey::parse_read(&b).unwrap(), (&[][..], key));
+        }
+        assert_eq!(ValueKey::from_id(0x40520001), Some(ValueKey::Uart1Baudrate));
+        assert_eq!(ValueKey::from_id(0xdeadbeef), None);
+    }
+
+    #[test]
+    fn keys_have_descriptions_and_units() {
+        assert_eq!(ValueKey::Uart1Baudrate.description(), "UART1 baud rate");
+        assert_eq!(ValueKey::Uart1Baudrate.unit(), Some("bps"));
+        assert_eq!(ValueKey::RateMeas.unit(), Some("ms"));
+        assert_eq!(ValueKey::UsbInprotUbx.unit(), None);
+        for key in ValueKey::value_variants() {
+            assert!(!key.description().is_empty(), "{key:?} has no description");
+        }
+    }
+}
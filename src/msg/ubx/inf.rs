@@ -4,6 +4,7 @@ use serde::{Deserialize, Serialize};
 macro_rules! impl_inf {
     ($($name:ident),*) => {$(
         #[derive(Serialize, Deserialize, Clone, Debug)]
+        #[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
         pub struct $name(String);
 
         impl ParseData for $name {
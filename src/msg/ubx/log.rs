@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+use super::nav::FixType;
+use crate::{impl_struct, parse::ParseData};
+
+impl_struct! {
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct RetrievePos {
+    entry_index: u32,
+    lon: i32,
+    lat: i32,
+    hmsl: i32,
+    hacc: u32,
+    gspeed: u32,
+    heading: u32,
+    version: u8,
+    fix_type: FixType,
+    year: u16,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+    res1: u8,
+    num_sv: u8,
+    res2: u8,
+}
+}
+
+impl_class! {
+    pub enum Log: PollLog{
+        RetrievePos(RetrievePos)[40u16] = 0x0bu8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msg::Ubx;
+
+    #[test]
+    fn retrieve_pos_round_trip() {
+        let pos = RetrievePos {
+            entry_index: 42,
+            lon: 45_000_000,
+            lat: 520_000_000,
+            hmsl: 12_000,
+            hacc: 1_500,
+            gspeed: 250,
+            heading: 9_000_000,
+            version: 0,
+            fix_type: FixType::Fix3D,
+            year: 2022,
+            month: 8,
+            day: 24,
+            hour: 13,
+            minute: 37,
+            second: 5,
+            res1: 0,
+            num_sv: 11,
+            res2: 0,
+        };
+        let b = Ubx::Log(Log::RetrievePos(pos.clone()))
+            .parse_to_vec()
+            .unwrap();
+        assert_eq!(&b[2..6], &[0x21, 0x0b, 40, 0]);
+        // The fix type follows the version, both after the 28 bytes of position and motion.
+        assert_eq!(b[6 + 29], 3);
+        match Ubx::parse_read(&b).unwrap() {
+            ([], Ubx::Log(Log::RetrievePos(x))) => assert_eq!(x, pos),
+            x => panic!("expected LOG-RETRIEVEPOS, found {x:?}"),
+        }
+    }
+}
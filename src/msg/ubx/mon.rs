@@ -8,6 +8,7 @@ use crate::{
 
 impl_struct! {
 #[derive(Debug,Clone,Serialize,Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct CommBlock {
     port_id: u16,
     tx_pending: u16,
@@ -26,6 +27,7 @@ pub struct CommBlock {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Comms {
     pub version: u8,
     pub n_ports: u8,
@@ -81,6 +83,7 @@ impl ParseData for Comms {
 
 impl_struct! {
 #[derive(Debug,Clone,Serialize,Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Msgpp{
     msg1:[u16; 8],
     msg2:[u16; 8],
@@ -91,9 +94,144 @@ pub struct Msgpp{
 }
 }
 
+impl_struct! {
+#[derive(Debug,Clone,Serialize,Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct RfBlock {
+    block_id: u8,
+    flags: u8,
+    ant_status: u8,
+    ant_power: u8,
+    post_status: u32,
+    res1: [u8; 4],
+    noise_per_ms: u16,
+    agc_cnt: u16,
+    jam_ind: u8,
+    ofs_i: i8,
+    mag_i: u8,
+    ofs_q: i8,
+    mag_q: u8,
+    res2: [u8; 3],
+}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Rf {
+    pub version: u8,
+    pub n_blocks: u8,
+    pub res1: [u8; 2],
+    pub blocks: Vec<RfBlock>,
+}
+
+impl ParseData for Rf {
+    fn parse_read(b: &[u8]) -> crate::parse::Result<(&[u8], Self)> {
+        pread!(b => {
+            len: u16,
+            version: u8,
+            n_blocks: u8,
+            res1: [u8; 2],
+        });
+        if len as usize != 4 + n_blocks as usize * 24 {
+            anyhow::bail!(crate::parse::ParseError::InvalidLen);
+        }
+        let (b, blocks) = parse::collect(b, n_blocks as usize)?;
+        Ok((
+            b,
+            Self {
+                version,
+                n_blocks,
+                res1,
+                blocks,
+            },
+        ))
+    }
+
+    fn parse_write<W: std::io::Write>(&self, b: &mut W) -> crate::parse::Result<()> {
+        let len = u16::try_from(self.blocks.len() * 24 + 4)
+            .map_err(|_| crate::parse::ParseError::Invalid)?;
+        len.parse_write(b)?;
+        self.version.parse_write(b)?;
+        u8::try_from(self.blocks.len())
+            .map_err(|_| crate::parse::ParseError::Invalid)?
+            .parse_write(b)?;
+        self.res1.parse_write(b)?;
+        self.blocks.parse_write(b)?;
+        Ok(())
+    }
+}
+
 impl_class! {
     pub enum Mon: PollMon{
         Msgpp(Msgpp)[120] = 0x06,
         Comms(Comms) = 0x36,
+        Rf(Rf) = 0x38,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{msg::Ubx, parse::ParseError};
+
+    fn rf_block(block_id: u8, jam_ind: u8) -> RfBlock {
+        RfBlock {
+            block_id,
+            flags: 0b01,
+            ant_status: 2,
+            ant_power: 1,
+            post_status: 0,
+            res1: [0; 4],
+            noise_per_ms: 80 + block_id as u16,
+            agc_cnt: 4_000,
+            jam_ind,
+            ofs_i: -3,
+            mag_i: 150,
+            ofs_q: 4,
+            mag_q: 149,
+            res2: [0; 3],
+        }
+    }
+
+    #[test]
+    fn rf_round_trip() {
+        let rf = Rf {
+            version: 0,
+            n_blocks: 3,
+            res1: [0; 2],
+            blocks: vec![rf_block(0, 10), rf_block(1, 200), rf_block(2, 0)],
+        };
+        let b = Ubx::Mon(Mon::Rf(rf)).parse_to_vec().unwrap();
+        assert_eq!(u16::from_le_bytes([b[4], b[5]]), 4 + 3 * 24);
+        assert_eq!(b.len(), 6 + 4 + 3 * 24 + 2);
+
+        let rf = match Ubx::parse_read(&b).unwrap() {
+            ([], Ubx::Mon(Mon::Rf(x))) => x,
+            x => panic!("expected MON-RF, found {x:?}"),
+        };
+        assert_eq!(rf.n_blocks, 3);
+        let blocks = rf
+            .blocks
+            .iter()
+            .map(|x| (x.block_id, x.jam_ind, x.noise_per_ms, x.ofs_i))
+            .collect::<Vec<_>>();
+        assert_eq!(blocks, [(0, 10, 80, -3), (1, 200, 81, -3), (2, 0, 82, -3)]);
+        assert_eq!(Ubx::Mon(Mon::Rf(rf)).parse_to_vec().unwrap(), b);
+    }
+
+    #[test]
+    fn rf_length_must_match_the_blocks() {
+        let rf = Rf {
+            version: 0,
+            n_blocks: 1,
+            res1: [0; 2],
+            blocks: vec![rf_block(0, 0)],
+        };
+        let mut b = Vec::new();
+        rf.parse_write(&mut b).unwrap();
+        // Claim a second block which is not there.
+        b[3] = 2;
+        let e = Rf::parse_read(&b).unwrap_err();
+        assert_eq!(ParseError::of(&e), Some(ParseError::InvalidLen));
     }
 }
@@ -1,8 +1,9 @@
-use std::io::Write;
+use std::{fmt, io::Write};
 
 use crate::{
     impl_bitfield, impl_struct,
-    parse::{ser_bitflags, ParseData, ParseError, Result},
+    parse::{self, ser_bitflags, ParseData, ParseError, Result},
+    pread,
 };
 use anyhow::bail;
 use enumflags2::{bitflags, BitFlags};
@@ -10,6 +11,7 @@ use serde::{Deserialize, Serialize};
 
 impl_struct! {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(default)]
 pub struct Clock{
     i_tow: u32,
@@ -22,6 +24,7 @@ pub struct Clock{
 
 impl_struct! {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(default)]
 pub struct Dop{
     i_tow: u32,
@@ -37,6 +40,7 @@ pub struct Dop{
 
 impl_struct! {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(default)]
 pub struct Eoe{
     i_tow: u32,
@@ -45,6 +49,7 @@ pub struct Eoe{
 
 impl_struct! {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize,Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(default)]
 pub struct Hpposecef{
     version:u8,
@@ -61,8 +66,26 @@ pub struct Hpposecef{
 }
 }
 
+impl Hpposecef {
+    /// The ECEF x coordinate in meters including the high precision component.
+    pub fn ecef_x_m(&self) -> f64 {
+        self.ecef_x as f64 * 1e-2 + self.ecef_x_hp as f64 * 1e-4
+    }
+
+    /// The ECEF y coordinate in meters including the high precision component.
+    pub fn ecef_y_m(&self) -> f64 {
+        self.ecef_y as f64 * 1e-2 + self.ecef_y_hp as f64 * 1e-4
+    }
+
+    /// The ECEF z coordinate in meters including the high precision component.
+    pub fn ecef_z_m(&self) -> f64 {
+        self.ecef_z as f64 * 1e-2 + self.ecef_z_hp as f64 * 1e-4
+    }
+}
+
 impl_struct! {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize,Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(default)]
 pub struct Hpposllh{
     version:u8,
@@ -81,8 +104,44 @@ pub struct Hpposllh{
 }
 }
 
+impl fmt::Display for Hpposllh {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "lat={:.9}° lon={:.9}° height={:.4}m acc={:.4}m",
+            self.lat_deg(),
+            self.lon_deg(),
+            self.height_m(),
+            self.h_acc as f64 * 1e-4
+        )
+    }
+}
+
+impl Hpposllh {
+    /// The longitude in degrees including the high precision component.
+    pub fn lon_deg(&self) -> f64 {
+        self.lon as f64 * 1e-7 + self.lon_hp as f64 * 1e-9
+    }
+
+    /// The latitude in degrees including the high precision component.
+    pub fn lat_deg(&self) -> f64 {
+        self.lat as f64 * 1e-7 + self.lat_hp as f64 * 1e-9
+    }
+
+    /// The height above the ellipsoid in meters including the high precision component.
+    pub fn height_m(&self) -> f64 {
+        self.height as f64 * 1e-3 + self.height_hp as f64 * 1e-4
+    }
+
+    /// The height above mean sea level in meters including the high precision component.
+    pub fn h_msl_m(&self) -> f64 {
+        self.h_msl as f64 * 1e-3 + self.h_msl_hp as f64 * 1e-4
+    }
+}
+
 impl_struct! {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(default)]
 pub struct Odo{
     version:u8,
@@ -96,6 +155,7 @@ pub struct Odo{
 
 impl_struct! {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(default)]
 pub struct Posecef{
     i_tow: u32,
@@ -108,6 +168,7 @@ pub struct Posecef{
 
 impl_struct! {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(default)]
 pub struct Posllh{
     i_tow: u32,
@@ -116,12 +177,14 @@ pub struct Posllh{
     height: i32,
     h_msl: i32,
     h_acc: u32,
+    v_acc: u32,
 }
 }
 
 #[bitflags]
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum Valid {
     Date = 0b0001,
     Time = 0b0010,
@@ -134,6 +197,7 @@ impl_bitfield!(Valid);
 #[bitflags]
 #[repr(u32)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum RelFlags {
     GnssFixOk = 0b0000000001,
     DiffSoln = 0b0000000010,
@@ -150,6 +214,7 @@ pub enum RelFlags {
 impl_bitfield!(RelFlags);
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum PsmState {
     NotActive = 0,
     Enabled = 1,
@@ -166,6 +231,7 @@ impl Default for PsmState {
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum CarrierPhaseSol {
     NoSolution = 0,
     Float = 1,
@@ -179,6 +245,7 @@ impl Default for CarrierPhaseSol {
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum FixType {
     NoFix,
     DeadReckoning,
@@ -224,6 +291,7 @@ impl ParseData for FixType {
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(default)]
 pub struct FixStatus {
     pub car_sol: CarrierPhaseSol,
@@ -278,6 +346,7 @@ impl ParseData for FixStatus {
 
 impl_struct! {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize,Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(default)]
 pub struct Pvt{
         i_tow: u32,
@@ -287,6 +356,7 @@ pub struct Pvt{
         hour: u8,
         min: u8,
         sec: u8,
+        #[cfg_attr(feature = "schemars", schemars(with = "Vec<Valid>"))]
         #[serde(with = "ser_bitflags")]
         valid: BitFlags<Valid>,
         t_acc: u32,
@@ -317,8 +387,55 @@ pub struct Pvt{
 }
 }
 
+impl Pvt {
+    /// The longitude in degrees.
+    pub fn lon_deg(&self) -> f64 {
+        self.lon as f64 * 1e-7
+    }
+
+    /// The latitude in degrees.
+    pub fn lat_deg(&self) -> f64 {
+        self.lat as f64 * 1e-7
+    }
+
+    /// The height above the ellipsoid in meters.
+    pub fn height_m(&self) -> f64 {
+        self.height as f64 * 1e-3
+    }
+
+    /// The height above mean sea level in meters.
+    pub fn h_msl_m(&self) -> f64 {
+        self.height_sea as f64 * 1e-3
+    }
+
+    /// The horizontal accuracy estimate in meters.
+    pub fn h_acc_m(&self) -> f64 {
+        self.h_acc as f64 * 1e-3
+    }
+
+    /// The vertical accuracy estimate in meters.
+    pub fn v_acc_m(&self) -> f64 {
+        self.v_acc as f64 * 1e-3
+    }
+}
+
+impl fmt::Display for Pvt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "lat={:.7}° lon={:.7}° fix={:?} sats={} acc={:.3}m",
+            self.lat as f64 * 1e-7,
+            self.lon as f64 * 1e-7,
+            self.fix_type,
+            self.numsv,
+            self.h_acc as f64 * 1e-3
+        )
+    }
+}
+
 impl_struct! {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize,Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 #[serde(default)]
     pub struct RelPosNed {
         version: u8,
@@ -341,11 +458,266 @@ impl_struct! {
         acc_length: i32,
         acc_heading: i32,
         res3: [u8;4],
+        #[cfg_attr(feature = "schemars", schemars(with = "Vec<RelFlags>"))]
         #[serde(with = "ser_bitflags")]
         flags: BitFlags<RelFlags>,
     }
 }
 
+impl fmt::Display for RelPosNed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "length={:.4}m heading={:.2}° acc={:.4}m",
+            self.rel_pos_length as f64 * 1e-2 + self.rel_pos_length_hp as f64 * 1e-4,
+            self.rel_pos_heading as f64 * 1e-5,
+            self.acc_length as f64 * 1e-4
+        )
+    }
+}
+
+impl_struct! {
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct Svin{
+    version: u8,
+    res1: [u8;3],
+    i_tow: u32,
+    dur: u32,
+    mean_x: i32,
+    mean_y: i32,
+    mean_z: i32,
+    mean_x_hp: i8,
+    mean_y_hp: i8,
+    mean_z_hp: i8,
+    res2: u8,
+    mean_acc: u32,
+    obs: u32,
+    valid: u8,
+    active: u8,
+    res3: [u8;2],
+}
+}
+
+impl fmt::Display for Svin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "dur={}s obs={} acc={:.4}m valid={} active={}",
+            self.dur,
+            self.obs,
+            self.mean_acc as f64 * 1e-4,
+            self.valid != 0,
+            self.active != 0
+        )
+    }
+}
+
+impl Svin {
+    /// The mean ECEF x coordinate in meters including the high precision component.
+    pub fn mean_x_m(&self) -> f64 {
+        self.mean_x as f64 * 1e-2 + self.mean_x_hp as f64 * 1e-4
+    }
+
+    /// The mean ECEF y coordinate in meters including the high precision component.
+    pub fn mean_y_m(&self) -> f64 {
+        self.mean_y as f64 * 1e-2 + self.mean_y_hp as f64 * 1e-4
+    }
+
+    /// The mean ECEF z coordinate in meters including the high precision component.
+    pub fn mean_z_m(&self) -> f64 {
+        self.mean_z as f64 * 1e-2 + self.mean_z_hp as f64 * 1e-4
+    }
+}
+
+impl_struct! {
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct TimeLs{
+    i_tow: u32,
+    version: u8,
+    res1: [u8;3],
+    src_of_cur_ls: u8,
+    cur_ls: i8,
+    src_of_ls_change: u8,
+    ls_change: i8,
+    time_to_ls_event: i32,
+    date_of_ls_gps_wn: u16,
+    date_of_ls_gps_dn: u16,
+    res2: [u8;3],
+    valid: u8,
+}
+}
+
+impl TimeLs {
+    /// Whether the current number of leap seconds is valid.
+    pub fn cur_ls_valid(&self) -> bool {
+        self.valid & 0b01 != 0
+    }
+
+    /// Whether the time to the next leap second event is valid.
+    pub fn time_to_ls_event_valid(&self) -> bool {
+        self.valid & 0b10 != 0
+    }
+}
+
+impl_struct! {
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct SatInfo{
+    gnss_id: u8,
+    sv_id: u8,
+    cno: u8,
+    elev: i8,
+    azim: i16,
+    pr_res: i16,
+    flags: u32,
+}
+}
+
+impl SatInfo {
+    /// Whether the satellite is used for navigation.
+    pub fn used(&self) -> bool {
+        self.flags & 0b1000 != 0
+    }
+
+    /// The name of the gnss of the satellite.
+    pub fn gnss_name(&self) -> &'static str {
+        match self.gnss_id {
+            0 => "GPS",
+            1 => "SBAS",
+            2 => "Galileo",
+            3 => "BeiDou",
+            4 => "IMES",
+            5 => "QZSS",
+            6 => "GLONASS",
+            _ => "Unknown",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct Sat {
+    pub i_tow: u32,
+    pub version: u8,
+    pub num_svs: u8,
+    pub res1: [u8; 2],
+    pub svs: Vec<SatInfo>,
+}
+
+impl fmt::Display for Sat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "sats={} used={}",
+            self.svs.len(),
+            self.svs.iter().filter(|x| x.used()).count()
+        )
+    }
+}
+
+impl ParseData for Sat {
+    fn parse_read(b: &[u8]) -> Result<(&[u8], Self)> {
+        pread!(b => {
+            len: u16,
+            i_tow: u32,
+            version: u8,
+            num_svs: u8,
+            res1: [u8; 2],
+        });
+        if len as usize != 8 + num_svs as usize * 12 {
+            bail!(ParseError::InvalidLen);
+        }
+        let (b, svs) = parse::collect(b, num_svs as usize)?;
+        Ok((
+            b,
+            Sat {
+                i_tow,
+                version,
+                num_svs,
+                res1,
+                svs,
+            },
+        ))
+    }
+
+    fn parse_write<W: Write>(&self, b: &mut W) -> Result<()> {
+        let len = u16::try_from(self.svs.len() * 12 + 8).map_err(|_| ParseError::Invalid)?;
+        len.parse_write(b)?;
+        self.i_tow.parse_write(b)?;
+        self.version.parse_write(b)?;
+        (self.svs.len() as u8).parse_write(b)?;
+        self.res1.parse_write(b)?;
+        self.svs.parse_write(b)
+    }
+}
+
+impl_struct! {
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct OrbBlock{
+    gnss_id: u8,
+    sv_id: u8,
+    sv_flag: u8,
+    eph: u8,
+    alm: u8,
+    other_orb: u8,
+}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct Orb {
+    pub i_tow: u32,
+    pub version: u8,
+    pub num_sv: u8,
+    pub res1: [u8; 2],
+    pub svs: Vec<OrbBlock>,
+}
+
+impl ParseData for Orb {
+    fn parse_read(b: &[u8]) -> Result<(&[u8], Self)> {
+        pread!(b => {
+            len: u16,
+            i_tow: u32,
+            version: u8,
+            num_sv: u8,
+            res1: [u8; 2],
+        });
+        if len as usize != 8 + num_sv as usize * 6 {
+            bail!(ParseError::InvalidLen);
+        }
+        let (b, svs) = parse::collect(b, num_sv as usize)?;
+        Ok((
+            b,
+            Orb {
+                i_tow,
+                version,
+                num_sv,
+                res1,
+                svs,
+            },
+        ))
+    }
+
+    fn parse_write<W: Write>(&self, b: &mut W) -> Result<()> {
+        let len = u16::try_from(self.svs.len() * 6 + 8).map_err(|_| ParseError::Invalid)?;
+        len.parse_write(b)?;
+        self.i_tow.parse_write(b)?;
+        self.version.parse_write(b)?;
+        (self.svs.len() as u8).parse_write(b)?;
+        self.res1.parse_write(b)?;
+        self.svs.parse_write(b)
+    }
+}
+
 impl_class! {
     pub enum Nav: PollNav{
         Clock(Clock)[20u16] = 0x22u8,
@@ -354,9 +726,251 @@ impl_class! {
         Hpposecef(Hpposecef)[28u16] = 0x13u8,
         Hpposllh(Hpposllh)[36u16] = 0x14u8,
         Odo(Odo)[20u16] = 0x09u8,
+        Orb(Orb) = 0x34u8,
         Posecef(Posecef)[20u16] = 0x01u8,
         Posllh(Posllh)[28u16] = 0x02u8,
         Pvt(Pvt)[92u16] = 0x07u8,
         RelPosNed(RelPosNed)[64u16] = 0x3Cu8,
+        Sat(Sat) = 0x35u8,
+        Svin(Svin)[40u16] = 0x3Bu8,
+        TimeLs(TimeLs)[24u16] = 0x26u8,
+    }
+}
+
+impl Nav {
+    /// The GPS time of week of the navigation epoch the message belongs to.
+    pub fn i_tow(&self) -> Option<u32> {
+        match *self {
+            Nav::Clock(ref x) => Some(x.i_tow),
+            Nav::Dop(ref x) => Some(x.i_tow),
+            Nav::Eoe(ref x) => Some(x.i_tow),
+            Nav::Hpposecef(ref x) => Some(x.i_tow),
+            Nav::Hpposllh(ref x) => Some(x.i_tow),
+            Nav::Odo(ref x) => Some(x.i_tow),
+            Nav::Orb(ref x) => Some(x.i_tow),
+            Nav::Posecef(ref x) => Some(x.i_tow),
+            Nav::Posllh(ref x) => Some(x.i_tow),
+            Nav::Pvt(ref x) => Some(x.i_tow),
+            Nav::RelPosNed(ref x) => Some(x.i_tow),
+            Nav::Sat(ref x) => Some(x.i_tow),
+            Nav::Svin(ref x) => Some(x.i_tow),
+            Nav::TimeLs(ref x) => Some(x.i_tow),
+            Nav::Unknown { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msg::Ubx;
+
+    /// Serialize a navigation message and parse it again, checking the length of the frame.
+    fn round_trip(nav: Nav, payload_len: usize) -> Nav {
+        let b = Ubx::Nav(nav).parse_to_vec().unwrap();
+        assert_eq!(b.len(), payload_len + 8);
+        assert_eq!(Ubx::message_usage(&b), Some(b.len()));
+        match Ubx::parse_read(&b).unwrap() {
+            ([], Ubx::Nav(x)) => x,
+            x => panic!("expected a single navigation message, found {x:?}"),
+        }
+    }
+
+    #[test]
+    fn clock_round_trip() {
+        let clock = Clock {
+            i_tow: 123_456_000,
+            clk_b: -1_234_567,
+            clk_d: 89,
+            t_acc: 15,
+            f_acc: 250,
+        };
+        match round_trip(Nav::Clock(clock.clone()), 20) {
+            Nav::Clock(x) => assert_eq!(x, clock),
+            x => panic!("expected NAV-CLOCK, found {x:?}"),
+        }
+    }
+
+    #[test]
+    fn display_summaries() {
+        let hpposllh = Ubx::Nav(Nav::Hpposllh(Hpposllh {
+            lat: 520_000_000,
+            lat_hp: 12,
+            lon: 45_000_000,
+            lon_hp: -5,
+            height: 12_345,
+            height_hp: 6,
+            h_acc: 140,
+            ..Default::default()
+        }));
+        assert_eq!(
+            hpposllh.to_string(),
+            "UBX-NAV-HPPOSLLH: lat=52.000000012° lon=4.499999995° height=12.3456m acc=0.0140m"
+        );
+
+        let rel_pos_ned = Ubx::Nav(Nav::RelPosNed(RelPosNed {
+            rel_pos_length: 150,
+            rel_pos_length_hp: 25,
+            rel_pos_heading: 9_000_000,
+            acc_length: 30,
+            ..Default::default()
+        }));
+        assert_eq!(
+            rel_pos_ned.to_string(),
+            "UBX-NAV-RELPOSNED: length=1.5025m heading=90.00° acc=0.0030m"
+        );
+
+        let svin = Ubx::Nav(Nav::Svin(Svin {
+            dur: 60,
+            obs: 59,
+            mean_acc: 25_000,
+            valid: 0,
+            active: 1,
+            ..Default::default()
+        }));
+        assert_eq!(
+            svin.to_string(),
+            "UBX-NAV-SVIN: dur=60s obs=59 acc=2.5000m valid=false active=true"
+        );
+
+        let sat = |flags| SatInfo {
+            flags,
+            ..Default::default()
+        };
+        let sat = Ubx::Nav(Nav::Sat(Sat {
+            i_tow: 0,
+            version: 1,
+            num_svs: 3,
+            res1: [0; 2],
+            svs: vec![sat(0b1000), sat(0), sat(0b1111)],
+        }));
+        assert_eq!(sat.to_string(), "UBX-NAV-SAT: sats=3 used=2");
+    }
+
+    #[test]
+    fn orb_round_trip() {
+        let block = |sv_id, eph| OrbBlock {
+            gnss_id: 0,
+            sv_id,
+            sv_flag: 0b0000_1101,
+            eph,
+            alm: 0b0010_0111,
+            other_orb: 0,
+        };
+        let orb = Orb {
+            i_tow: 345_600_000,
+            version: 1,
+            num_sv: 2,
+            res1: [0; 2],
+            svs: vec![block(3, 0b1011_1111), block(17, 0)],
+        };
+        let b = Ubx::Nav(Nav::Orb(orb.clone())).parse_to_vec().unwrap();
+        // The length field covers the header and both blocks.
+        assert_eq!(u16::from_le_bytes([b[4], b[5]]), 8 + 2 * 6);
+        match round_trip(Nav::Orb(orb.clone()), 20) {
+            Nav::Orb(x) => assert_eq!(x, orb),
+            x => panic!("expected NAV-ORB, found {x:?}"),
+        }
+    }
+
+    #[test]
+    fn posllh_round_trip() {
+        let posllh = Posllh {
+            i_tow: 432_000_000,
+            lon: -1_234_567,
+            lat: 512_345_678,
+            height: 95_123,
+            h_msl: 49_876,
+            h_acc: 1_500,
+            v_acc: 2_250,
+        };
+        let b = Ubx::Nav(Nav::Posllh(posllh.clone()))
+            .parse_to_vec()
+            .unwrap();
+        // The vertical accuracy is the last field of the payload.
+        assert_eq!(&b[6 + 24..6 + 28], &2_250u32.to_le_bytes());
+        match round_trip(Nav::Posllh(posllh.clone()), 28) {
+            Nav::Posllh(x) => assert_eq!(x, posllh),
+            x => panic!("expected NAV-POSLLH, found {x:?}"),
+        }
+    }
+
+    #[test]
+    fn odo_round_trip() {
+        let odo = Odo {
+            version: 0,
+            res1: [0; 3],
+            i_tow: 86_400_000,
+            distance: 1_250,
+            total_distance: 987_654,
+            distance_std: 3,
+        };
+        let b = Ubx::Nav(Nav::Odo(odo.clone())).parse_to_vec().unwrap();
+        // The distances follow the time of week in the order of the specification.
+        let payload = &b[6..26];
+        assert_eq!(&payload[4..8], &86_400_000u32.to_le_bytes());
+        assert_eq!(&payload[8..12], &1_250u32.to_le_bytes());
+        assert_eq!(&payload[12..16], &987_654u32.to_le_bytes());
+        assert_eq!(&payload[16..20], &3u32.to_le_bytes());
+        match round_trip(Nav::Odo(odo.clone()), 20) {
+            Nav::Odo(x) => assert_eq!(x, odo),
+            x => panic!("expected NAV-ODO, found {x:?}"),
+        }
+    }
+
+    #[test]
+    fn high_precision_accessors_keep_sub_millimeter_precision() {
+        let hpposllh = Hpposllh {
+            lon: 45_678_901,
+            lon_hp: 23,
+            lat: -339_876_543,
+            lat_hp: -45,
+            height: 12_345,
+            height_hp: 7,
+            h_msl: -2_001,
+            h_msl_hp: -9,
+            ..Default::default()
+        };
+        assert!((hpposllh.lon_deg() - 4.567_890_123).abs() < 1e-12);
+        assert!((hpposllh.lat_deg() - -33.987_654_345).abs() < 1e-12);
+        assert!((hpposllh.height_m() - 12.3457).abs() < 1e-9);
+        assert!((hpposllh.h_msl_m() - -2.0019).abs() < 1e-9);
+
+        let hpposecef = Hpposecef {
+            ecef_x: 392_345_678,
+            ecef_x_hp: 12,
+            ecef_y: -30_000_000,
+            ecef_y_hp: -99,
+            ecef_z: 1,
+            ecef_z_hp: 1,
+            ..Default::default()
+        };
+        assert!((hpposecef.ecef_x_m() - 3_923_456.781_2).abs() < 1e-6);
+        assert!((hpposecef.ecef_y_m() - -300_000.009_9).abs() < 1e-6);
+        assert!((hpposecef.ecef_z_m() - 0.0101).abs() < 1e-9);
+    }
+
+    #[test]
+    fn time_ls_round_trip() {
+        let time_ls = TimeLs {
+            i_tow: 518_400_000,
+            version: 0,
+            res1: [0; 3],
+            src_of_cur_ls: 2,
+            cur_ls: 18,
+            src_of_ls_change: 2,
+            ls_change: -1,
+            time_to_ls_event: -3_600,
+            date_of_ls_gps_wn: 2_238,
+            date_of_ls_gps_dn: 7,
+            res2: [0; 3],
+            valid: 0b01,
+        };
+        assert!(time_ls.cur_ls_valid());
+        assert!(!time_ls.time_to_ls_event_valid());
+        match round_trip(Nav::TimeLs(time_ls.clone()), 24) {
+            Nav::TimeLs(x) => assert_eq!(x, time_ls),
+            x => panic!("expected NAV-TIMELS, found {x:?}"),
+        }
     }
 }
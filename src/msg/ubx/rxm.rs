@@ -6,6 +6,7 @@ use crate::{impl_bitfield, impl_struct, parse::ParseData};
 #[bitflags]
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum RtcmFlags {
     CrcFailed = 0b1,
 }
@@ -14,8 +15,10 @@ impl_bitfield!(RtcmFlags);
 
 impl_struct! {
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Rtcm {
     version: u8,
+    #[cfg_attr(feature = "schemars", schemars(with = "u8"))]
     flags: BitFlags<RtcmFlags>,
     res1: [u8; 2],
     ref_stations: u16,
@@ -1,4 +1,18 @@
-use std::{fmt, io::Write, result::Result as StdResult};
+use std::{
+    fmt,
+    io::Write,
+    mem::{ManuallyDrop, MaybeUninit},
+    pin::Pin,
+    ptr,
+    result::Result as StdResult,
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+use log::warn;
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::msg::GpsMsg;
 
 pub mod ser_bitflags {
     use enumflags2::{BitFlag, BitFlags};
@@ -75,10 +89,10 @@ macro_rules! impl_struct{
 
         impl ParseData for $name {
             fn parse_read(b: &[u8]) -> anyhow::Result<(&[u8], Self)> {
-
-                use anyhow::Context as ErrorContext;
+                let start = b;
                 $(let (b,$field) = <$ty>::parse_read(b)
-                    .context(concat!("failed to parse field ",stringify!($field)," struct ",stringify!($name)))?;)*
+                    .map_err(|e| $crate::parse::field_error(e, start.len() - b.len(),
+                        stringify!($name), stringify!($field)))?;)*
                 Ok((b,$name{
                     $($field,)*
                 }))
@@ -115,6 +129,7 @@ macro_rules! impl_enum{
     }) => {
         #[repr($repr)]
         #[derive(Debug,Clone,Copy,Eq,PartialEq,Serialize,Deserialize)]
+        #[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
         pub enum $name{
             $($kind = $v),*
         }
@@ -147,6 +162,18 @@ pub enum ParseError {
     InvalidMsg(u8),
     InvalidLen,
     Invalid,
+    /// Context added to the error of a field of a message which failed to parse.
+    InvalidField {
+        struct_name: &'static str,
+        field_name: &'static str,
+    },
+}
+
+impl ParseError {
+    /// The error parsing failed with, without the fields it is reported in.
+    pub fn of(e: &anyhow::Error) -> Option<ParseError> {
+        e.root_cause().downcast_ref::<ParseError>().copied()
+    }
 }
 
 impl fmt::Display for ParseError {
@@ -156,11 +183,20 @@ impl fmt::Display for ParseError {
             ParseError::InvalidChecksum => write!(f, "checksum is not valid"),
             ParseError::InvalidHeader => write!(f, "header is not valid"),
             ParseError::InvalidClass(x) => {
-                write!(f, "encountered unknown ubx message class `{}`", x)
+                write!(f, "encountered unknown ubx message class `{:#04x}`", x)
+            }
+            ParseError::InvalidMsg(x) => {
+                write!(f, "encountered unknown ubx message id `{:#04x}`", x)
             }
-            ParseError::InvalidMsg(x) => write!(f, "encountered unknown ubx message id `{}`", x),
             ParseError::InvalidLen => write!(f, "ubx message length is not as specified in spec"),
             ParseError::Invalid => write!(f, "failed to parse buffer"),
+            ParseError::InvalidField {
+                struct_name,
+                field_name,
+            } => write!(
+                f,
+                "failed to parse field `{field_name}` of struct `{struct_name}`"
+            ),
         }
     }
 }
@@ -176,9 +212,7 @@ pub trait ResultExt {
 impl<T> ResultExt for Result<T> {
     fn map_invalid<E: Into<anyhow::Error>>(self, e: E) -> Self {
         match self {
-            Err(er) if er.downcast_ref::<ParseError>().copied() == Some(ParseError::Invalid) => {
-                Err(e.into())
-            }
+            Err(er) if ParseError::of(&er) == Some(ParseError::Invalid) => Err(e.into()),
             x => x,
         }
     }
@@ -190,10 +224,58 @@ pub trait ErrorExt {
 
 impl ErrorExt for anyhow::Error {
     fn not_enough_data(&self) -> bool {
-        self.downcast_ref::<ParseError>().copied() == Some(ParseError::NotEnoughData)
+        ParseError::of(self) == Some(ParseError::NotEnoughData)
     }
 }
 
+/// The offset in bytes at which parsing failed.
+///
+/// The offset is attached where parsing fails, relative to the buffer given to that parser, and
+/// every enclosing parser adds the position of that buffer with [`ErrorOffset::add`]. Once the
+/// error is returned from [`GpsMsg::parse_read`](crate::msg::GpsMsg) the offset is relative to
+/// the start of the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorOffset(pub usize);
+
+impl fmt::Display for ErrorOffset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to parse message at byte {}", self.0)
+    }
+}
+
+impl ErrorOffset {
+    /// Add the offset of the part which failed to parse within the enclosing buffer to the
+    /// error, attaching an offset if the error doesn't have one yet.
+    pub fn add(mut e: anyhow::Error, offset: usize) -> anyhow::Error {
+        match e.downcast_mut::<ErrorOffset>() {
+            Some(x) => {
+                x.0 += offset;
+                e
+            }
+            None => e.context(ErrorOffset(offset)),
+        }
+    }
+
+    /// The offset attached to the error, if any.
+    pub fn of(e: &anyhow::Error) -> Option<usize> {
+        e.downcast_ref::<ErrorOffset>().map(|x| x.0)
+    }
+}
+
+/// Attach context to the error of a field which failed to parse, `offset` is the offset of the
+/// field within the struct.
+pub fn field_error(
+    e: anyhow::Error,
+    offset: usize,
+    struct_name: &'static str,
+    field_name: &'static str,
+) -> anyhow::Error {
+    ErrorOffset::add(e, offset).context(ParseError::InvalidField {
+        struct_name,
+        field_name,
+    })
+}
+
 pub trait Offset {
     fn offset(&self, other: &Self) -> usize;
 }
@@ -227,7 +309,7 @@ impl ParseData for u64 {
         }
         let d = [b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]];
         let d = u64::from_le_bytes(d);
-        Ok((&b[4..], d))
+        Ok((&b[8..], d))
     }
 
     fn parse_write<W: Write>(&self, b: &mut W) -> Result<()> {
@@ -338,17 +420,75 @@ impl ParseData for bool {
     }
 }
 
+/// A string prefixed with its length in bytes as a `u16`.
+impl ParseData for String {
+    fn parse_read(b: &[u8]) -> Result<(&[u8], Self)> {
+        let (b, len) = u16::parse_read(b)?;
+        if b.len() < len as usize {
+            return Err(ParseError::NotEnoughData)?;
+        }
+        let (v, b) = b.split_at(len as usize);
+        let v = std::str::from_utf8(v).map_err(|_| ParseError::Invalid)?;
+        Ok((b, v.to_string()))
+    }
+
+    fn parse_write<W: Write>(&self, b: &mut W) -> Result<()> {
+        let len = u16::try_from(self.len()).map_err(|_| ParseError::InvalidLen)?;
+        len.parse_write(b)?;
+        b.write_all(self.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Builds an array one element at a time. The elements pushed so far are dropped if the builder
+/// is dropped before the array is finished.
+struct ArrayBuilder<T, const N: usize> {
+    array: [MaybeUninit<T>; N],
+    len: usize,
+}
+
+impl<T, const N: usize> ArrayBuilder<T, N> {
+    fn new() -> Self {
+        ArrayBuilder {
+            // SAFETY: An array of `MaybeUninit` doesn't need to be initialized.
+            array: unsafe { MaybeUninit::<[MaybeUninit<T>; N]>::uninit().assume_init() },
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, t: T) {
+        self.array[self.len].write(t);
+        self.len += 1;
+    }
+
+    /// Returns the array, panics if not all elements were pushed.
+    fn finish(self) -> [T; N] {
+        assert_eq!(self.len, N, "array not fully initialized");
+        let this = ManuallyDrop::new(self);
+        // SAFETY: All elements are initialized and `[MaybeUninit<T>; N]` has the same layout as
+        // `[T; N]`. The builder isn't dropped so the elements are only owned by the result.
+        unsafe { ptr::read(this.array.as_ptr() as *const [T; N]) }
+    }
+}
+
+impl<T, const N: usize> Drop for ArrayBuilder<T, N> {
+    fn drop(&mut self) {
+        for x in &mut self.array[..self.len] {
+            // SAFETY: The first `len` elements are initialized.
+            unsafe { x.assume_init_drop() };
+        }
+    }
+}
+
 impl<T: ParseData, const N: usize> ParseData for [T; N] {
     fn parse_read(mut b: &[u8]) -> Result<(&[u8], Self)> {
-        let mut tmp = std::mem::MaybeUninit::<[T; N]>::uninit();
-        for i in 0..N {
+        let mut res = ArrayBuilder::new();
+        for _ in 0..N {
             let (nb, t) = T::parse_read(b)?;
             b = nb;
-            unsafe {
-                tmp.as_mut_ptr().cast::<T>().add(i).write(t);
-            }
+            res.push(t);
         }
-        Ok((b, unsafe { tmp.assume_init() }))
+        Ok((b, res.finish()))
     }
 
     fn parse_write<W: Write>(&self, b: &mut W) -> Result<()> {
@@ -410,3 +550,243 @@ pub fn eat<T: ParseData>(b: &[u8], len: usize) -> Result<&[u8]> {
         Ok(&b[len..])
     }
 }
+
+/// A stream of messages parsed from a raw byte source like a serial port.
+///
+/// Skips over bytes which are not the start of a message and buffers incomplete messages
+/// until enough data is read from the source.
+pub struct ParseIterator<R> {
+    buffer: Vec<u8>,
+    read_buffer: Vec<u8>,
+    frame: Vec<u8>,
+    source: R,
+}
+
+impl<R> ParseIterator<R> {
+    pub fn new(source: R) -> Self {
+        Self::with_buffer_size(source, 4096)
+    }
+
+    pub fn with_buffer_size(source: R, cap: usize) -> Self {
+        ParseIterator {
+            buffer: Vec::with_capacity(cap),
+            read_buffer: vec![0u8; cap],
+            frame: Vec::new(),
+            source,
+        }
+    }
+
+    /// The raw bytes of the last message returned from the stream.
+    pub fn last_frame(&self) -> &[u8] {
+        &self.frame
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for ParseIterator<R> {
+    type Item = Result<GpsMsg>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+
+        loop {
+            let skipped = GpsMsg::skip_to_prefix(&mut this.buffer);
+            if skipped != 0 {
+                warn!("skipped over {skipped} bytes");
+            }
+
+            if let Some(x) = GpsMsg::message_usage(&this.buffer) {
+                let mut rest = this.buffer.split_off(x);
+                std::mem::swap(&mut rest, &mut this.buffer);
+                this.frame = rest;
+                return Poll::Ready(Some(GpsMsg::parse_read(&this.frame).map(|x| x.1)));
+            }
+
+            let mut buffer = ReadBuf::new(&mut this.read_buffer);
+            match Pin::new(&mut this.source).poll_read(cx, &mut buffer) {
+                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                Poll::Ready(Ok(())) => {
+                    let filled = buffer.filled();
+                    if filled.is_empty() {
+                        return Poll::Ready(None);
+                    }
+                    this.buffer.extend_from_slice(filled);
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use futures::StreamExt;
+    use tokio_test::io::Builder;
+
+    use super::*;
+    use crate::msg::{
+        ubx::nav::{Eoe, Nav},
+        Ubx,
+    };
+
+    thread_local! {
+        static DROPPED: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    /// A byte which fails to parse if it is `0xff` and counts how often it is dropped.
+    struct Counted(u8);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            DROPPED.with(|x| x.set(x.get() + 1));
+        }
+    }
+
+    impl ParseData for Counted {
+        fn parse_read(b: &[u8]) -> Result<(&[u8], Self)> {
+            match u8::parse_read(b)? {
+                (_, 0xff) => Err(ParseError::Invalid.into()),
+                (b, x) => Ok((b, Counted(x))),
+            }
+        }
+
+        fn parse_write<W: Write>(&self, b: &mut W) -> Result<()> {
+            self.0.parse_write(b)
+        }
+    }
+
+    #[test]
+    fn parse_error_formats_bytes_as_hex() {
+        assert_eq!(
+            ParseError::InvalidClass(0x06).to_string(),
+            "encountered unknown ubx message class `0x06`"
+        );
+        assert_eq!(
+            ParseError::InvalidMsg(0xee).to_string(),
+            "encountered unknown ubx message id `0xee`"
+        );
+    }
+
+    #[test]
+    fn field_error_keeps_the_cause() {
+        let e = field_error(ParseError::NotEnoughData.into(), 4, "Pvt", "lon");
+        assert_eq!(e.to_string(), "failed to parse field `lon` of struct `Pvt`");
+        assert_eq!(ParseError::of(&e), Some(ParseError::NotEnoughData));
+        assert!(e.not_enough_data());
+        assert_eq!(ErrorOffset::of(&e), Some(4));
+    }
+
+    #[test]
+    fn array_drops_parsed_elements_on_error() {
+        DROPPED.with(|x| x.set(0));
+        assert!(<[Counted; 4]>::parse_read(&[1, 2, 0xff, 4]).is_err());
+        assert_eq!(DROPPED.with(|x| x.get()), 2);
+
+        DROPPED.with(|x| x.set(0));
+        let (rest, array) = <[Counted; 4]>::parse_read(&[1, 2, 3, 4, 5]).unwrap();
+        assert_eq!(rest, [5]);
+        assert_eq!(array.iter().map(|x| x.0).collect::<Vec<_>>(), [1, 2, 3, 4]);
+        assert_eq!(DROPPED.with(|x| x.get()), 0);
+        drop(array);
+        assert_eq!(DROPPED.with(|x| x.get()), 4);
+    }
+
+    fn eoe_frame(i_tow: u32) -> Vec<u8> {
+        GpsMsg::Ubx(Ubx::Nav(Nav::Eoe(Eoe { i_tow })))
+            .parse_to_vec()
+            .unwrap()
+    }
+
+    fn eoe_i_tow(msg: Option<Result<GpsMsg>>) -> u32 {
+        match msg {
+            Some(Ok(GpsMsg::Ubx(Ubx::Nav(Nav::Eoe(x))))) => x.i_tow,
+            x => panic!("expected a NAV-EOE message, found {x:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn parse_iterator_multiple_messages_in_one_read() {
+        let mut data = eoe_frame(1);
+        data.extend_from_slice(&eoe_frame(2));
+        let mut iter = ParseIterator::new(Builder::new().read(&data).build());
+
+        assert_eq!(eoe_i_tow(iter.next().await), 1);
+        assert_eq!(iter.last_frame(), eoe_frame(1));
+        assert_eq!(eoe_i_tow(iter.next().await), 2);
+        assert!(iter.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn parse_iterator_message_split_over_reads() {
+        let frame = eoe_frame(3);
+        let source = Builder::new()
+            .read(&frame[..3])
+            .read(&frame[3..7])
+            .read(&frame[7..])
+            .build();
+        let mut iter = ParseIterator::new(source);
+
+        assert_eq!(eoe_i_tow(iter.next().await), 3);
+        assert_eq!(iter.last_frame(), frame);
+        assert!(iter.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn parse_iterator_skips_garbage() {
+        let source = Builder::new()
+            .read(b"garbage")
+            .read(&eoe_frame(4))
+            .read(b"more garbage")
+            .read(&eoe_frame(5))
+            .build();
+        let mut iter = ParseIterator::new(source);
+
+        assert_eq!(eoe_i_tow(iter.next().await), 4);
+        assert_eq!(eoe_i_tow(iter.next().await), 5);
+        assert!(iter.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn parse_iterator_small_buffer() {
+        let mut data = eoe_frame(6);
+        data.extend_from_slice(&eoe_frame(7));
+        let mut iter =
+            ParseIterator::with_buffer_size(Builder::new().read(&data).read(&data).build(), 4);
+
+        for i_tow in [6, 7, 6, 7] {
+            assert_eq!(eoe_i_tow(iter.next().await), i_tow);
+        }
+        assert!(iter.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn parse_iterator_read_error() {
+        let source = Builder::new()
+            .read(&eoe_frame(8))
+            .read_error(io::Error::other("device unplugged"))
+            .build();
+        let mut iter = ParseIterator::new(source);
+
+        assert_eq!(eoe_i_tow(iter.next().await), 8);
+        match iter.next().await {
+            Some(Err(e)) => assert!(e.is::<io::Error>()),
+            x => panic!("expected an io error, found {x:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn parse_iterator_invalid_message() {
+        let mut frame = eoe_frame(9);
+        // Break the checksum.
+        *frame.last_mut().unwrap() ^= 0xff;
+        let mut iter = ParseIterator::new(Builder::new().read(&frame).build());
+
+        match iter.next().await {
+            Some(Err(e)) => assert!(!e.is::<io::Error>()),
+            x => panic!("expected a parse error, found {x:?}"),
+        }
+        assert_eq!(iter.last_frame(), frame);
+        assert!(iter.next().await.is_none());
+    }
+}
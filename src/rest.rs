@@ -0,0 +1,181 @@
+//! A HTTP api for reading the latest state of a device.
+
+use std::{future::Future, net::SocketAddr};
+
+use anyhow::{Context, Result};
+use axum::{extract, http::StatusCode, routing::get, Json, Router};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::state::{SharedHealth, SharedState, State};
+
+async fn health() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+async fn state(extract::State(state): extract::State<SharedState>) -> Json<State> {
+    Json(state.lock().unwrap().clone())
+}
+
+/// Returns the field selected by `f`, or 404 if the device has not send the message yet.
+fn field<T: Serialize>(
+    state: &SharedState,
+    f: impl FnOnce(&State) -> &Option<T>,
+) -> Result<Json<Value>, StatusCode> {
+    let state = state.lock().unwrap();
+    let value = f(&state).as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    serde_json::to_value(value)
+        .map(Json)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn pvt(
+    extract::State(state): extract::State<SharedState>,
+) -> Result<Json<Value>, StatusCode> {
+    field(&state, |x| &x.pvt)
+}
+
+async fn relposned(
+    extract::State(state): extract::State<SharedState>,
+) -> Result<Json<Value>, StatusCode> {
+    field(&state, |x| &x.relposned)
+}
+
+async fn svin(
+    extract::State(state): extract::State<SharedState>,
+) -> Result<Json<Value>, StatusCode> {
+    field(&state, |x| &x.svin)
+}
+
+pub fn router(state: SharedState) -> Router {
+    Router::new()
+        .route("/health", get(health))
+        .route("/state", get(self::state))
+        .route("/state/pvt", get(pvt))
+        .route("/state/relposned", get(relposned))
+        .route("/state/svin", get(svin))
+        .with_state(state)
+}
+
+async fn health_status(extract::State(health): extract::State<SharedHealth>) -> Json<Value> {
+    Json(json!({
+        "status": "ok",
+        "uptime_s": health.uptime().as_secs(),
+        "connections": health.connections(),
+    }))
+}
+
+/// A router with only a health check, for example for a container `HEALTHCHECK`.
+pub fn health_router(health: SharedHealth) -> Router {
+    Router::new()
+        .route("/health", get(health_status))
+        .with_state(health)
+}
+
+/// Bind the api to the given address, the returned future serves requests until an error occurs.
+pub fn bind(addr: SocketAddr, state: SharedState) -> Result<impl Future<Output = Result<()>>> {
+    let server = axum::Server::try_bind(&addr)
+        .with_context(|| format!("failed to bind rest api to `{addr}`"))?
+        .serve(router(state).into_make_service());
+    Ok(async move { server.await.context("rest api failed") })
+}
+
+/// Bind the health check to the given address, the returned future serves requests until an error
+/// occurs.
+pub fn bind_health(
+    addr: SocketAddr,
+    health: SharedHealth,
+) -> Result<impl Future<Output = Result<()>>> {
+    let server = axum::Server::try_bind(&addr)
+        .with_context(|| format!("failed to bind health check to `{addr}`"))?
+        .serve(health_router(health).into_make_service());
+    Ok(async move { server.await.context("health check failed") })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use hyper::{body, Client, StatusCode};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{
+        msg::ubx::nav::{Nav, Pvt},
+        msg::{GpsMsg, Ubx},
+        state::Health,
+    };
+
+    /// Serve the router on a free port on localhost, returning the address it listens on.
+    fn serve(router: Router) -> SocketAddr {
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let server = axum::Server::bind(&addr).serve(router.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    async fn get(addr: SocketAddr, path: &str) -> (StatusCode, Option<Value>) {
+        let uri = format!("http://{addr}{path}").parse().unwrap();
+        let res = Client::new().get(uri).await.unwrap();
+        let status = res.status();
+        let body = body::to_bytes(res.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).ok())
+    }
+
+    #[tokio::test]
+    async fn health_is_ok() {
+        let addr = serve(router(SharedState::default()));
+        let (status, body) = get(addr, "/health").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, Some(json!({ "status": "ok" })));
+    }
+
+    #[tokio::test]
+    async fn health_router_reports_connections() {
+        let health = SharedHealth::new(Health::new());
+        health.set_connections(3);
+        let addr = serve(health_router(health));
+        let (status, body) = get(addr, "/health").await;
+        assert_eq!(status, StatusCode::OK);
+        let body = body.unwrap();
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["connections"], 3);
+        assert!(body["uptime_s"].is_u64());
+    }
+
+    #[tokio::test]
+    async fn state_follows_device_messages() {
+        let state = SharedState::default();
+        let addr = serve(router(state.clone()));
+
+        let (status, body) = get(addr, "/state").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.unwrap()["pvt"], Value::Null);
+        let (status, _) = get(addr, "/state/pvt").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        state
+            .lock()
+            .unwrap()
+            .update(&GpsMsg::Ubx(Ubx::Nav(Nav::Pvt(Pvt {
+                i_tow: 1000,
+                lon: 51_234_567,
+                lat: -12_345_678,
+                ..Default::default()
+            }))));
+
+        let (status, body) = get(addr, "/state/pvt").await;
+        assert_eq!(status, StatusCode::OK);
+        let body = body.unwrap();
+        assert_eq!(body["i_tow"], 1000);
+        assert_eq!(body["lon"], 51_234_567);
+        assert_eq!(body["lat"], -12_345_678);
+
+        let (status, body) = get(addr, "/state").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.unwrap()["pvt"]["lon"], 51_234_567);
+        let (status, _) = get(addr, "/state/relposned").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,80 @@
+//! The latest state of a device, collected from the messages it sends.
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::msg::{
+    ubx::{
+        mon::{Comms, Mon},
+        nav::{Nav, Pvt, RelPosNed, Svin},
+    },
+    GpsMsg, Ubx,
+};
+
+/// State shared between the server loop and other tasks.
+pub type SharedState = Arc<Mutex<State>>;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct State {
+    pub pvt: Option<Pvt>,
+    pub relposned: Option<RelPosNed>,
+    pub comms: Option<Comms>,
+    pub svin: Option<Svin>,
+}
+
+impl State {
+    /// Update the state with a message from the device, other messages are ignored.
+    pub fn update(&mut self, msg: &GpsMsg) {
+        match msg {
+            GpsMsg::Ubx(Ubx::Nav(Nav::Pvt(x))) => self.pvt = Some(x.clone()),
+            GpsMsg::Ubx(Ubx::Nav(Nav::RelPosNed(x))) => self.relposned = Some(x.clone()),
+            GpsMsg::Ubx(Ubx::Nav(Nav::Svin(x))) => self.svin = Some(x.clone()),
+            GpsMsg::Ubx(Ubx::Mon(Mon::Comms(x))) => self.comms = Some(x.clone()),
+            _ => {}
+        }
+    }
+}
+
+/// Health of the server shared with the health check endpoint.
+pub type SharedHealth = Arc<Health>;
+
+#[derive(Debug)]
+pub struct Health {
+    started: Instant,
+    connections: AtomicUsize,
+}
+
+impl Health {
+    pub fn new() -> Self {
+        Health {
+            started: Instant::now(),
+            connections: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// The number of clients connected to the server.
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    pub fn set_connections(&self, count: usize) {
+        self.connections.store(count, Ordering::Relaxed);
+    }
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self::new()
+    }
+}